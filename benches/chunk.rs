@@ -0,0 +1,127 @@
+//! Benchmarks for the chunk hot paths: ticking, rendering and scanning kinds.
+//!
+//! Rendering and the kind scan also run against the previous layout, an array of whole elements
+//! indexed column by column, to show what the structure of arrays gains.
+
+use std::hint::black_box;
+
+use bevy::math::IVec2;
+use criterion::{criterion_group, criterion_main, Criterion};
+use pixelands::{
+    common::Rect,
+    coordinates::ChunkPos,
+    simulation::{Chunk, Element, ElementKind, Sandbox},
+};
+
+/// A sandbox with a pile of sand and a pool of water falling onto a stone floor.
+fn busy_sandbox() -> Sandbox {
+    let mut sandbox = Sandbox::with_seed(42);
+    let element = |kind| sandbox.elements.element(kind);
+    let (stone, sand, water) = (
+        element(ElementKind::Stone),
+        element(ElementKind::Sand),
+        element(ElementKind::Water),
+    );
+    sandbox.fill_rect(Rect::new(IVec2::new(-64, 120), IVec2::new(128, 128)), stone);
+    sandbox.fill_rect(Rect::new(IVec2::new(-40, 10), IVec2::new(0, 60)), sand);
+    sandbox.fill_rect(Rect::new(IVec2::new(20, 10), IVec2::new(80, 60)), water);
+    sandbox
+}
+
+fn tick(c: &mut Criterion) {
+    c.bench_function("tick a busy sandbox", |b| {
+        b.iter_batched_ref(
+            busy_sandbox,
+            |sandbox| {
+                for _ in 0..10 {
+                    sandbox.tick();
+                }
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+/// How chunks stored their elements before the structure of arrays.
+struct ElementArrayChunk {
+    size: usize,
+    elements: Vec<Element>,
+}
+
+impl ElementArrayChunk {
+    fn from_chunk(chunk: &Chunk) -> Self {
+        let size = chunk.size();
+        let mut elements = vec![Element::default(); size.cells()];
+        for (index, element) in elements.iter_mut().enumerate() {
+            let (x, y) = (index as i32 / size.0, index as i32 % size.0);
+            *element = chunk.get_element(size.to_position((y * size.0 + x) as usize));
+        }
+        Self {
+            size: size.0 as usize,
+            elements,
+        }
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let sandbox = busy_sandbox();
+    let chunk = sandbox.get_chunk(ChunkPos::new(0, 0)).unwrap();
+    let old = ElementArrayChunk::from_chunk(&chunk);
+    let mut image = vec![0u8; chunk.size().cells() * 4];
+
+    let mut group = c.benchmark_group("copy chunk colours into an image");
+    group.bench_function("array of elements, column-major", |b| {
+        b.iter(|| {
+            for x in 0..old.size {
+                for y in 0..old.size {
+                    let color = old.elements[x * old.size + y].color;
+                    let index = (y * old.size + x) * 4;
+                    image[index..index + 4].copy_from_slice(&[color.0, color.1, color.2, 255]);
+                }
+            }
+            black_box(&image);
+        })
+    });
+    group.bench_function("structure of arrays, row-major", |b| {
+        b.iter(|| {
+            for (pixel, (r, g, b)) in image.chunks_exact_mut(4).zip(chunk.colors()) {
+                pixel.copy_from_slice(&[*r, *g, *b, 255]);
+            }
+            black_box(&image);
+        })
+    });
+    group.finish();
+}
+
+fn scan_kinds(c: &mut Criterion) {
+    let sandbox = busy_sandbox();
+    let chunk = sandbox.get_chunk(ChunkPos::new(0, 0)).unwrap();
+    let old = ElementArrayChunk::from_chunk(&chunk);
+
+    let mut group = c.benchmark_group("count air, row by row");
+    group.bench_function("array of elements, column-major", |b| {
+        b.iter(|| {
+            let mut air = 0;
+            for y in 0..old.size {
+                for x in 0..old.size {
+                    air += (old.elements[x * old.size + y].kind == ElementKind::Air) as usize;
+                }
+            }
+            black_box(air)
+        })
+    });
+    group.bench_function("structure of arrays, row-major", |b| {
+        b.iter(|| {
+            let air = chunk
+                .kinds()
+                .iter()
+                .filter(|kind| **kind == ElementKind::Air)
+                .count();
+            black_box(air)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tick, render, scan_kinds);
+criterion_main!(benches);
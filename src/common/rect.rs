@@ -1,6 +1,7 @@
 use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Rect {
     /// The top-left corner of the rectangle.
     pub min: IVec2,
@@ -54,6 +55,11 @@ impl Rect {
         Self::new(self.min.min(other.min), self.max.max(other.max))
     }
 
+    /// The part of the rectangle inside `other`, empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Self {
+        Self::new(self.min.max(other.min), self.max.min(other.max))
+    }
+
     pub fn union_point(&mut self, point: IVec2) {
         self.min = self.min.min(point);
         self.max = self.max.max(point);
@@ -1,3 +1,5 @@
-pub const CHUNK_SIZE: usize = 64;
-pub const CHUNK_SIZE_I32: i32 = CHUNK_SIZE as i32;
-pub const RESOLUTION: usize = 6;
+/// The chunk size of worlds that don't choose their own, see
+/// [`WorldSettings`](crate::simulation::WorldSettings).
+pub const DEFAULT_CHUNK_SIZE: i32 = 64;
+/// How many pixels wide a cell is before zooming, unless the world chooses otherwise.
+pub const DEFAULT_RESOLUTION: f32 = 6.0;
@@ -0,0 +1,437 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+};
+
+use bevy::{input::InputSystem, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+/// Where the user-editable bindings are read from and saved to.
+pub const BINDINGS_PATH: &str = "bindings.ron";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveCameraUp,
+    MoveCameraDown,
+    MoveCameraLeft,
+    MoveCameraRight,
+    TogglePause,
+    SelectSand,
+    SelectStone,
+    SelectWater,
+    SelectNextElement,
+    SelectPreviousElement,
+    ToggleDebug,
+    ToggleControls,
+    TogglePalette,
+    DetachBody,
+    Detonate,
+    QuickSave,
+    QuickLoad,
+    ToggleRecording,
+    PlayReplay,
+    ToggleTimeline,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::MoveCameraUp,
+        Action::MoveCameraDown,
+        Action::MoveCameraLeft,
+        Action::MoveCameraRight,
+        Action::TogglePause,
+        Action::SelectSand,
+        Action::SelectStone,
+        Action::SelectWater,
+        Action::SelectNextElement,
+        Action::SelectPreviousElement,
+        Action::ToggleDebug,
+        Action::ToggleControls,
+        Action::TogglePalette,
+        Action::DetachBody,
+        Action::Detonate,
+        Action::QuickSave,
+        Action::QuickLoad,
+        Action::ToggleRecording,
+        Action::PlayReplay,
+        Action::ToggleTimeline,
+    ];
+
+    pub const fn label(&self) -> &'static str {
+        match self {
+            Self::MoveCameraUp => "Move camera up",
+            Self::MoveCameraDown => "Move camera down",
+            Self::MoveCameraLeft => "Move camera left",
+            Self::MoveCameraRight => "Move camera right",
+            Self::TogglePause => "Pause / resume",
+            Self::SelectSand => "Select sand",
+            Self::SelectStone => "Select stone",
+            Self::SelectWater => "Select water",
+            Self::SelectNextElement => "Next element",
+            Self::SelectPreviousElement => "Previous element",
+            Self::ToggleDebug => "Toggle debug view",
+            Self::ToggleControls => "Toggle controls window",
+            Self::TogglePalette => "Toggle element palette",
+            Self::DetachBody => "Detach rigid body",
+            Self::Detonate => "Detonate at cursor",
+            Self::QuickSave => "Save the world",
+            Self::QuickLoad => "Load the saved world",
+            Self::ToggleRecording => "Start / stop recording a replay",
+            Self::PlayReplay => "Play the recorded replay",
+            Self::ToggleTimeline => "Toggle the timeline",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    fn same_device(&self, other: &Binding) -> bool {
+        matches!(
+            (self, other),
+            (Binding::Key(_), Binding::Key(_)) | (Binding::Gamepad(_), Binding::Gamepad(_))
+        )
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Gamepad(button) => format!("Gamepad {button:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Resource, Serialize, Deserialize)]
+pub struct InputBindings {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use Binding::*;
+
+        let bindings = BTreeMap::from([
+            (
+                Action::MoveCameraUp,
+                vec![Key(KeyCode::KeyW), Gamepad(GamepadButton::DPadUp)],
+            ),
+            (
+                Action::MoveCameraDown,
+                vec![Key(KeyCode::KeyS), Gamepad(GamepadButton::DPadDown)],
+            ),
+            (
+                Action::MoveCameraLeft,
+                vec![Key(KeyCode::KeyA), Gamepad(GamepadButton::DPadLeft)],
+            ),
+            (
+                Action::MoveCameraRight,
+                vec![Key(KeyCode::KeyD), Gamepad(GamepadButton::DPadRight)],
+            ),
+            (
+                Action::TogglePause,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButton::Start)],
+            ),
+            (Action::SelectSand, vec![Key(KeyCode::Digit1)]),
+            (Action::SelectStone, vec![Key(KeyCode::Digit2)]),
+            (Action::SelectWater, vec![Key(KeyCode::Digit3)]),
+            (
+                Action::SelectNextElement,
+                vec![Key(KeyCode::KeyE), Gamepad(GamepadButton::RightTrigger)],
+            ),
+            (
+                Action::SelectPreviousElement,
+                vec![Key(KeyCode::KeyQ), Gamepad(GamepadButton::LeftTrigger)],
+            ),
+            (
+                Action::ToggleDebug,
+                vec![Key(KeyCode::F1), Gamepad(GamepadButton::Select)],
+            ),
+            (Action::ToggleControls, vec![Key(KeyCode::F2)]),
+            (Action::TogglePalette, vec![Key(KeyCode::Tab)]),
+            (Action::DetachBody, vec![Key(KeyCode::KeyB)]),
+            (Action::Detonate, vec![Key(KeyCode::KeyX)]),
+            (Action::QuickSave, vec![Key(KeyCode::F5)]),
+            (Action::QuickLoad, vec![Key(KeyCode::F9)]),
+            (Action::ToggleRecording, vec![Key(KeyCode::F6)]),
+            (Action::PlayReplay, vec![Key(KeyCode::F7)]),
+            (Action::ToggleTimeline, vec![Key(KeyCode::KeyT)]),
+        ]);
+
+        Self { bindings }
+    }
+}
+
+impl InputBindings {
+    /// Loads the bindings file, writing the defaults to it when it doesn't exist yet.
+    /// A file that can't be parsed is left untouched so the user doesn't lose their edits.
+    /// Actions missing from the file, like ones added since it was written, get their defaults.
+    pub fn load_or_default() -> Self {
+        let Ok(contents) = fs::read_to_string(BINDINGS_PATH) else {
+            let bindings = Self::default();
+            bindings.save();
+            return bindings;
+        };
+
+        match ron::from_str::<Self>(&contents) {
+            Ok(mut bindings) => {
+                for (action, default) in Self::default().bindings {
+                    bindings.bindings.entry(action).or_insert(default);
+                }
+                bindings
+            }
+            Err(err) => {
+                warn!("Invalid {BINDINGS_PATH}, using the default bindings: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let contents = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Failed to serialize the input bindings: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) = fs::write(BINDINGS_PATH, contents) {
+            error!("Failed to save {BINDINGS_PATH}: {err}");
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Binds `binding` to `action`, replacing the previous binding of the same device.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.retain(|other| !other.same_device(&binding));
+        bindings.push(binding);
+    }
+}
+
+/// The state of every [`Action`] for the current frame, so gameplay systems don't need to know
+/// which keys or buttons are bound to them.
+#[derive(Debug, Default, Resource)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// Analog camera movement from the gamepad sticks, on top of the camera actions.
+    pub camera_axis: Vec2,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// The action waiting for a key or button press in the controls window.
+#[derive(Debug, Default, Resource)]
+pub struct Rebinding(pub Option<Action>);
+
+#[derive(Debug, Default, Resource)]
+pub struct ControlsWindow {
+    pub open: bool,
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputBindings::load_or_default())
+            .init_resource::<ActionState>()
+            .init_resource::<Rebinding>()
+            .init_resource::<ControlsWindow>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem))
+            .add_systems(
+                Update,
+                (toggle_controls_window, controls_ui, capture_rebinding),
+            );
+    }
+}
+
+fn update_action_state(
+    mut state: ResMut<ActionState>,
+    mut egui_ctx: EguiContexts,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+) {
+    state.pressed.clear();
+    state.just_pressed.clear();
+    state.camera_axis = Vec2::ZERO;
+
+    // The key press belongs to the controls window
+    if rebinding.0.is_some() {
+        return;
+    }
+
+    // Don't move the camera while the user is typing into a text field
+    let keyboard_enabled = !egui_ctx.ctx_mut().wants_keyboard_input();
+
+    for action in Action::ALL {
+        for binding in bindings.get(action) {
+            let (pressed, just_pressed) = match binding {
+                Binding::Key(key) => (
+                    keyboard_enabled && keyboard.pressed(*key),
+                    keyboard_enabled && keyboard.just_pressed(*key),
+                ),
+                Binding::Gamepad(button) => (
+                    gamepads.iter().any(|gamepad| gamepad.pressed(*button)),
+                    gamepads.iter().any(|gamepad| gamepad.just_pressed(*button)),
+                ),
+            };
+
+            if pressed {
+                state.pressed.insert(action);
+            }
+            if just_pressed {
+                state.just_pressed.insert(action);
+            }
+        }
+    }
+
+    for gamepad in gamepads.iter() {
+        state.camera_axis += gamepad.left_stick();
+    }
+}
+
+fn toggle_controls_window(mut window: ResMut<ControlsWindow>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::ToggleControls) {
+        window.open = !window.open;
+    }
+}
+
+fn controls_ui(
+    mut contexts: EguiContexts,
+    mut window: ResMut<ControlsWindow>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    if !window.open {
+        rebinding.0 = None;
+        return;
+    }
+
+    egui::Window::new("Controls")
+        .open(&mut window.open)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.label());
+
+                    let bound = bindings
+                        .get(action)
+                        .iter()
+                        .map(Binding::label)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    ui.label(if bound.is_empty() {
+                        "-".to_string()
+                    } else {
+                        bound
+                    });
+
+                    if rebinding.0 == Some(action) {
+                        ui.label("Press a key or button (Esc to cancel)");
+                    } else if ui.button("Rebind").clicked() {
+                        rebinding.0 = Some(action);
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            if ui.button("Reset to defaults").clicked() {
+                *bindings = InputBindings::default();
+                bindings.save();
+            }
+            ui.label(format!("Bindings are saved to {BINDINGS_PATH}"));
+        });
+}
+
+fn capture_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+
+    let binding = if let Some(key) = keyboard.get_just_pressed().next() {
+        if *key == KeyCode::Escape {
+            rebinding.0 = None;
+            return;
+        }
+        Binding::Key(*key)
+    } else if let Some(button) = gamepads
+        .iter()
+        .find_map(|gamepad| gamepad.get_just_pressed().next().copied())
+    {
+        Binding::Gamepad(button)
+    } else {
+        return;
+    };
+
+    bindings.rebind(action, binding);
+    bindings.save();
+    rebinding.0 = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_round_trip_through_ron() {
+        let bindings = InputBindings::default();
+        let contents =
+            ron::ser::to_string_pretty(&bindings, ron::ser::PrettyConfig::default()).unwrap();
+        assert_eq!(ron::from_str::<InputBindings>(&contents).unwrap(), bindings);
+    }
+
+    #[test]
+    fn every_action_has_a_default_binding() {
+        let bindings = InputBindings::default();
+        for action in Action::ALL {
+            assert!(!bindings.get(action).is_empty(), "{action:?}");
+        }
+    }
+
+    #[test]
+    fn rebind_only_replaces_the_same_device() {
+        let mut bindings = InputBindings::default();
+        bindings.rebind(Action::MoveCameraUp, Binding::Key(KeyCode::ArrowUp));
+        assert_eq!(
+            bindings.get(Action::MoveCameraUp),
+            [
+                Binding::Gamepad(GamepadButton::DPadUp),
+                Binding::Key(KeyCode::ArrowUp)
+            ]
+        );
+
+        bindings.rebind(Action::SelectSand, Binding::Gamepad(GamepadButton::North));
+        assert_eq!(
+            bindings.get(Action::SelectSand),
+            [
+                Binding::Key(KeyCode::Digit1),
+                Binding::Gamepad(GamepadButton::North)
+            ]
+        );
+    }
+}
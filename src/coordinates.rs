@@ -1,24 +1,288 @@
-use bevy::math::*;
+//! The coordinate spaces used by the game, each with its own type so they can't be mixed up.
+//!
+//! * [`ScreenPos`]: Bevy world-space pixels, the space of transforms and the camera. Y grows upwards.
+//! * [`WorldCell`]: a cell of the simulation grid. Y grows downwards, like gravity.
+//! * [`ChunkPos`]: the position of a chunk in the chunk grid. Y grows upwards, like the screen.
+//! * [`LocalCell`]: a cell relative to the top-left corner of a chunk. Y grows downwards.
+//!
+//! Every conversion between them lives here, including the y-flips.
 
-use crate::constants::CHUNK_SIZE;
+use std::fmt;
 
-pub fn world_to_chunk_position(world_position: Vec2) -> IVec2 {
-    IVec2::new(
-        world_position.x.div_euclid(CHUNK_SIZE as f32) as i32,
-        -(world_position.y.div_euclid(CHUNK_SIZE as f32) as i32),
-    )
+use bevy::{
+    ecs::resource::Resource, math::*, render::camera::Camera,
+    transform::components::GlobalTransform,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Rect, constants::DEFAULT_CHUNK_SIZE};
+
+/// The size of a cell in [`ScreenPos`] pixels.
+#[derive(Resource)]
+pub struct Resolution(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScreenPos(pub Vec2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct WorldCell(pub IVec2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChunkPos(pub IVec2);
+
+/// Usually inside the chunk, but it may point outside of it to address its neighbours, like
+/// `(-1, 0)` for the last cell of the chunk at its left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LocalCell(pub IVec2);
+
+/// The width and height of the chunks of a world, in cells. Chosen when the world is created,
+/// see [`WorldSettings`](crate::simulation::WorldSettings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkSize(pub i32);
+
+impl ScreenPos {
+    /// Projects a position on the window (e.g. the cursor) through the camera.
+    pub fn from_viewport(
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        viewport_position: Vec2,
+    ) -> Option<Self> {
+        camera
+            .viewport_to_world(camera_transform, viewport_position)
+            .ok()
+            .map(|ray| Self(ray.origin.truncate()))
+    }
+
+    /// The inverse of [`ScreenPos::to_world_cell`] for continuous world cell coordinates, like
+    /// the position of a rigid body.
+    pub fn from_world_point(point: Vec2, resolution: &Resolution) -> Self {
+        Self(Vec2::new(point.x, -point.y) * resolution.0)
+    }
+
+    pub fn to_world_cell(self, resolution: &Resolution) -> WorldCell {
+        WorldCell(
+            (Vec2::new(self.0.x, -self.0.y) / resolution.0)
+                .floor()
+                .as_ivec2(),
+        )
+    }
+}
+
+impl WorldCell {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self(IVec2::new(x, y))
+    }
+
+    pub fn chunk(self, size: ChunkSize) -> ChunkPos {
+        ChunkPos(IVec2::new(
+            self.0.x.div_euclid(size.0),
+            -self.0.y.div_euclid(size.0),
+        ))
+    }
+
+    pub fn local(self, size: ChunkSize) -> LocalCell {
+        LocalCell(self.0.rem_euclid(IVec2::splat(size.0)))
+    }
+
+    /// The top-left corner of the cell.
+    pub fn to_screen_pos(self, resolution: &Resolution) -> ScreenPos {
+        ScreenPos(Vec2::new(self.0.x as f32, -self.0.y as f32) * resolution.0)
+    }
 }
 
-pub fn chunk_to_world_position(chunk_position: IVec2) -> Vec2 {
-    Vec2::new(
-        chunk_position.x as f32 * CHUNK_SIZE as f32,
-        chunk_position.y as f32 * CHUNK_SIZE as f32,
-    )
+impl ChunkPos {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self(IVec2::new(x, y))
+    }
+
+    pub fn offset(self, direction: IVec2) -> Self {
+        Self(self.0 + direction)
+    }
+
+    pub fn cell(self, local: LocalCell, size: ChunkSize) -> WorldCell {
+        WorldCell(IVec2::new(
+            self.0.x * size.0 + local.0.x,
+            -self.0.y * size.0 + local.0.y,
+        ))
+    }
+
+    /// The top-left cell of the chunk.
+    pub fn origin(self, size: ChunkSize) -> WorldCell {
+        self.cell(LocalCell::default(), size)
+    }
+
+    /// The top-left corner of the chunk.
+    pub fn to_screen_pos(self, size: ChunkSize, resolution: &Resolution) -> ScreenPos {
+        self.origin(size).to_screen_pos(resolution)
+    }
 }
 
-pub fn world_to_element_position(world_position: Vec2) -> IVec2 {
-    IVec2::new(
-        world_position.x.rem_euclid(CHUNK_SIZE as f32) as i32,
-        world_position.y.rem_euclid(CHUNK_SIZE as f32) as i32,
-    )
+impl LocalCell {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self(IVec2::new(x, y))
+    }
+
+    pub const fn is_inside_chunk(self, size: ChunkSize) -> bool {
+        self.0.x >= 0 && self.0.y >= 0 && self.0.x < size.0 && self.0.y < size.0
+    }
+
+    /// Splits a cell that may be outside of its chunk into the direction of the chunk that
+    /// actually contains it and the position inside that chunk.
+    pub fn split(self, size: ChunkSize) -> (IVec2, LocalCell) {
+        let cell = WorldCell(self.0);
+        (cell.chunk(size).0, cell.local(size))
+    }
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl ChunkSize {
+    /// The number of cells in a chunk.
+    pub const fn cells(self) -> usize {
+        (self.0 * self.0) as usize
+    }
+
+    /// The area of a chunk, in local cells.
+    pub fn rect(self) -> Rect {
+        Rect::new(IVec2::ZERO, IVec2::splat(self.0))
+    }
+
+    /// Where a chunk stores the element at `position`, row by row.
+    pub const fn to_index(self, position: LocalCell) -> usize {
+        (position.0.y * self.0 + position.0.x) as usize
+    }
+
+    /// The inverse of [`ChunkSize::to_index`].
+    pub const fn to_position(self, index: usize) -> LocalCell {
+        LocalCell(IVec2::new(index as i32 % self.0, index as i32 / self.0))
+    }
+}
+
+impl fmt::Display for WorldCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for ChunkPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: ChunkSize = ChunkSize(64);
+
+    const CELLS: [IVec2; 8] = [
+        IVec2::new(0, 0),
+        IVec2::new(-1, -1),
+        IVec2::new(-64, -64),
+        IVec2::new(-65, 64),
+        IVec2::new(63, -1),
+        IVec2::new(64, 63),
+        IVec2::new(-129, 130),
+        IVec2::new(1000, -1000),
+    ];
+
+    #[test]
+    fn world_cell_round_trips_through_chunk_and_local_cell() {
+        for cell in CELLS.map(WorldCell) {
+            let local = cell.local(SIZE);
+            assert!(local.is_inside_chunk(SIZE), "{cell}");
+            assert_eq!(cell.chunk(SIZE).cell(local, SIZE), cell);
+        }
+    }
+
+    #[test]
+    fn chunk_y_grows_upwards_while_cell_y_grows_downwards() {
+        assert_eq!(WorldCell::new(0, -1).chunk(SIZE), ChunkPos::new(0, 1));
+        assert_eq!(WorldCell::new(0, 64).chunk(SIZE), ChunkPos::new(0, -1));
+        assert_eq!(WorldCell::new(-1, 0).chunk(SIZE), ChunkPos::new(-1, 0));
+        assert_eq!(ChunkPos::new(-1, 1).origin(SIZE), WorldCell::new(-64, -64));
+        assert_eq!(ChunkPos::new(2, -3).origin(SIZE), WorldCell::new(128, 192));
+    }
+
+    #[test]
+    fn world_cell_round_trips_through_screen_pos() {
+        for resolution in [0.5, 1.0, 6.0, 7.3] {
+            let resolution = Resolution(resolution);
+            for cell in CELLS.map(WorldCell) {
+                let corner = cell.to_screen_pos(&resolution);
+                assert_eq!(
+                    corner.to_world_cell(&resolution),
+                    cell,
+                    "{cell} at {}",
+                    resolution.0
+                );
+
+                // Anywhere inside the cell maps back to it
+                let inside = ScreenPos(corner.0 + Vec2::new(0.5, -0.5) * resolution.0);
+                assert_eq!(
+                    inside.to_world_cell(&resolution),
+                    cell,
+                    "{cell} at {}",
+                    resolution.0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_screen_pos_is_its_top_left_corner() {
+        let resolution = Resolution(2.0);
+        assert_eq!(
+            ChunkPos::new(1, 1).to_screen_pos(SIZE, &resolution),
+            ScreenPos(Vec2::new(128.0, 128.0))
+        );
+        assert_eq!(
+            ChunkPos::new(-1, -1).to_screen_pos(SIZE, &resolution),
+            ScreenPos(Vec2::new(-128.0, -128.0))
+        );
+        assert_eq!(
+            ScreenPos(Vec2::new(-0.1, 0.1))
+                .to_world_cell(&resolution)
+                .chunk(SIZE),
+            ChunkPos::new(-1, 1)
+        );
+    }
+
+    #[test]
+    fn local_cell_outside_of_its_chunk_splits_into_a_neighbour() {
+        assert_eq!(
+            LocalCell::new(-1, 0).split(SIZE),
+            (IVec2::new(-1, 0), LocalCell::new(63, 0))
+        );
+        assert_eq!(
+            LocalCell::new(10, 64).split(SIZE),
+            (IVec2::new(0, -1), LocalCell::new(10, 0))
+        );
+        assert_eq!(
+            LocalCell::new(64, -1).split(SIZE),
+            (IVec2::new(1, 1), LocalCell::new(0, 63))
+        );
+        assert_eq!(
+            LocalCell::new(5, 6).split(SIZE),
+            (IVec2::ZERO, LocalCell::new(5, 6))
+        );
+    }
+
+    #[test]
+    fn smaller_chunks_hold_fewer_cells() {
+        let size = ChunkSize(16);
+        assert_eq!(size.cells(), 256);
+        assert_eq!(WorldCell::new(17, 20).chunk(size), ChunkPos::new(1, -1));
+        assert_eq!(WorldCell::new(17, 20).local(size), LocalCell::new(1, 4));
+        assert_eq!(ChunkPos::new(-1, 1).origin(size), WorldCell::new(-16, -16));
+        assert_eq!(
+            LocalCell::new(16, -1).split(size),
+            (IVec2::new(1, 1), LocalCell::new(0, 15))
+        );
+    }
 }
@@ -5,10 +5,11 @@ use bevy::{
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 
 use crate::{
-    constants::CHUNK_SIZE,
+    controls::{Action, ActionState},
+    coordinates::{LocalCell, ScreenPos},
     simulation::{
-        plugin::{Resolution, WorldChunk},
-        Sandbox,
+        plugin::{Resolution, UserEdit, WorldChunk},
+        Edit, GravityDirection, Sandbox,
     },
 };
 
@@ -17,6 +18,8 @@ pub struct DebugUiPlugin;
 #[derive(Resource)]
 pub struct DebugSettings {
     pub show_borders: bool,
+    /// Highlight the cells that keep their chunk awake.
+    pub show_wakers: bool,
 }
 
 impl Plugin for DebugUiPlugin {
@@ -27,23 +30,43 @@ impl Plugin for DebugUiPlugin {
         .add_plugins(FrameTimeDiagnosticsPlugin::new(10))
         .insert_resource(DebugSettings {
             show_borders: false,
+            show_wakers: false,
         })
         .add_systems(Update, tweak_settings)
-        .add_systems(Update, diagnostics_ui)
+        .add_systems(Update, (diagnostics_ui, world_settings_ui))
         .add_systems(
             Update,
-            (draw_chunk_borders, draw_dirty_rect).run_if(should_draw_chunk_borders),
+            (
+                track_wakers.run_if(resource_changed::<DebugSettings>),
+                draw_wakers.run_if(should_draw_wakers),
+            )
+                .chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                draw_chunk_borders,
+                draw_dirty_rect,
+                draw_body_outlines,
+                draw_gravity_zones,
+            )
+                .run_if(should_draw_chunk_borders),
         );
     }
 }
 
-fn tweak_settings(mut settings: ResMut<DebugSettings>, input: Res<ButtonInput<KeyCode>>) {
-    if input.just_pressed(KeyCode::F1) {
+fn tweak_settings(mut settings: ResMut<DebugSettings>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::ToggleDebug) {
         settings.show_borders = !settings.show_borders;
     }
 }
 
-fn diagnostics_ui(diagnostics: Res<DiagnosticsStore>, mut contexts: EguiContexts) {
+fn diagnostics_ui(
+    diagnostics: Res<DiagnosticsStore>,
+    mut sandbox: ResMut<Sandbox>,
+    mut settings: ResMut<DebugSettings>,
+    mut contexts: EguiContexts,
+) {
     egui::Window::new("Diagnostics").show(contexts.ctx_mut(), |ui| {
         ui.label(format!(
             "FPS: {}",
@@ -59,22 +82,96 @@ fn diagnostics_ui(diagnostics: Res<DiagnosticsStore>, mut contexts: EguiContexts
                 .and_then(|ms| ms.smoothed())
                 .unwrap_or(0.0) as i64
         ));
+
+        let awake = sandbox
+            .chunks
+            .values()
+            .filter(|chunk| chunk.read().active())
+            .count();
+        ui.label(format!("Awake chunks: {awake}/{}", sandbox.chunks.len()));
+
+        // Only written on change, so toggling the tracking doesn't run every frame
+        let mut show_wakers = settings.show_wakers;
+        ui.checkbox(&mut show_wakers, "Show cells keeping chunks awake");
+        if show_wakers != settings.show_wakers {
+            settings.show_wakers = show_wakers;
+        }
+
+        ui.checkbox(
+            &mut sandbox.check_conservation,
+            "Check element conservation",
+        );
+    });
+}
+
+fn world_settings_ui(
+    mut contexts: EguiContexts,
+    sandbox: Res<Sandbox>,
+    mut edits: EventWriter<UserEdit>,
+) {
+    let mut gravity = sandbox.gravity.world;
+    egui::Window::new("World").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Gravity:");
+            egui::ComboBox::from_id_salt("gravity_direction")
+                .selected_text(gravity.direction.name())
+                .show_ui(ui, |ui| {
+                    for direction in GravityDirection::ALL {
+                        ui.selectable_value(&mut gravity.direction, direction, direction.name());
+                    }
+                });
+        });
+        ui.add(egui::Slider::new(&mut gravity.strength, 0.0..=2.0).text("Strength"));
     });
+
+    if gravity != sandbox.gravity.world {
+        edits.write(UserEdit(Edit::SetGravity(gravity)));
+    }
 }
 
 fn should_draw_chunk_borders(settings: Res<DebugSettings>) -> bool {
     settings.show_borders
 }
 
+fn should_draw_wakers(settings: Res<DebugSettings>) -> bool {
+    settings.show_wakers
+}
+
+fn track_wakers(settings: Res<DebugSettings>, mut sandbox: ResMut<Sandbox>) {
+    sandbox.track_wakers(settings.show_wakers);
+}
+
+/// Outlines every cell that marked its chunk as dirty during the last tick.
+fn draw_wakers(mut gizmos: Gizmos, sandbox: Res<Sandbox>, resolution: Res<Resolution>) {
+    let size = Vec2::splat(resolution.0);
+    for chunk in sandbox.chunks.values() {
+        let chunk = chunk.read();
+        for cell in chunk.wakers.iter() {
+            let bottom_left = chunk
+                .position
+                .cell(LocalCell::new(cell.0.x, cell.0.y + 1), chunk.size())
+                .to_screen_pos(&resolution)
+                .0;
+            gizmos.rect_2d(bottom_left + size / 2.0, size, Color::srgb_u8(255, 40, 40));
+        }
+    }
+}
+
 fn draw_chunk_borders(
     mut gizmos: Gizmos,
     world_chunks: Query<&WorldChunk>,
+    sandbox: Res<Sandbox>,
     resolution: Res<Resolution>,
 ) {
+    let chunk_size = sandbox.chunk_size();
     for chunk in world_chunks.iter() {
-        let size = Vec2::splat(resolution.0 * CHUNK_SIZE as f32);
-        let position = (Vec2::new(0.0, -1.0) + chunk.position.as_vec2()) * size;
-        gizmos.rect_2d(position + size / 2.0, size, Color::srgb_u8(0, 255, 0));
+        let size = Vec2::splat(resolution.0 * chunk_size.0 as f32);
+        let top_left = chunk.position.to_screen_pos(chunk_size, &resolution).0;
+        gizmos.rect_2d(
+            top_left + Vec2::new(size.x, -size.y) / 2.0,
+            size,
+            Color::srgb_u8(0, 255, 0),
+        );
     }
 }
 
@@ -85,25 +182,55 @@ fn draw_dirty_rect(
     resolution: Res<Resolution>,
 ) {
     for chunk in world_chunks.iter() {
-        let chunk = sandbox.get_chunk(chunk.position).unwrap();
+        let Some(chunk) = sandbox.get_chunk(chunk.position) else {
+            continue;
+        };
         let rect = chunk.dirty_rect();
         if rect.is_empty() {
             continue;
         }
 
-        let chunk_size = Vec2::splat(resolution.0 * CHUNK_SIZE as f32);
-        let chunk_position = (Vec2::new(0.0, -1.0) + chunk.position.as_vec2()) * chunk_size;
-
         // Calculate size, ensuring it's at least 1 pixel
         let size = (rect.size().as_vec2() * resolution.0).max(Vec2::splat(1.0));
 
-        // Calculate the top-left corner of the rect
-        let rect_top_left =
-            Vec2::new(rect.min.x as f32, CHUNK_SIZE as f32 - rect.max.y as f32) * resolution.0;
+        // Calculate the bottom-left corner of the rect
+        let rect_bottom_left = chunk
+            .position
+            .cell(LocalCell::new(rect.min.x, rect.max.y), chunk.size())
+            .to_screen_pos(&resolution)
+            .0;
 
         // Adjust for pixel-perfect alignment and correct the 1-pixel offset
-        let final_position = (chunk_position + rect_top_left).floor() + Vec2::splat(0.5) * size;
+        let final_position = rect_bottom_left.floor() + Vec2::splat(0.5) * size;
 
         gizmos.rect_2d(final_position, size, Color::srgb_u8(252, 115, 3));
     }
 }
+
+/// Outlines every gravity zone with an arrow pointing the way things fall in it.
+fn draw_gravity_zones(mut gizmos: Gizmos, sandbox: Res<Sandbox>, resolution: Res<Resolution>) {
+    let color = Color::srgb_u8(230, 60, 200);
+    for zone in sandbox.gravity.zones.iter() {
+        let min = ScreenPos::from_world_point(zone.area.min.as_vec2(), &resolution).0;
+        let max = ScreenPos::from_world_point(zone.area.max.as_vec2(), &resolution).0;
+        let center = (min + max) / 2.0;
+        gizmos.rect_2d(center, (max - min).abs(), color);
+
+        if !zone.gravity.is_zero() {
+            // Cell y grows downwards but screen y upwards
+            let down = zone.gravity.direction.down().as_vec2() * Vec2::new(1.0, -1.0);
+            let length = (max - min).abs().min_element() / 3.0;
+            gizmos.arrow_2d(center - down * length, center + down * length, color);
+        }
+    }
+}
+
+fn draw_body_outlines(mut gizmos: Gizmos, sandbox: Res<Sandbox>, resolution: Res<Resolution>) {
+    for (start, end) in sandbox.bodies.iter().flat_map(|body| body.outline()) {
+        gizmos.line_2d(
+            ScreenPos::from_world_point(start, &resolution).0,
+            ScreenPos::from_world_point(end, &resolution).0,
+            Color::srgb_u8(0, 200, 255),
+        );
+    }
+}
@@ -0,0 +1,12 @@
+//! The simulation and the Bevy plugins that make up the game, so other crates can extend it,
+//! e.g. with packs of elements registered through
+//! [`ElementAppExt`](simulation::plugin::ElementAppExt).
+
+pub mod common;
+pub mod constants;
+pub mod controls;
+pub mod coordinates;
+pub mod debug_ui;
+pub mod palette;
+pub mod simulation;
+pub mod timeline;
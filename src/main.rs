@@ -1,36 +1,113 @@
-mod common;
-mod constants;
-mod coordinates;
-mod debug_ui;
-mod simulation;
-
 use bevy::{
     image::ImageSampler,
     prelude::*,
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
     window::PresentMode,
 };
-use debug_ui::DebugUiPlugin;
+use pixelands::{
+    controls::ControlsPlugin,
+    coordinates::ScreenPos,
+    debug_ui::DebugUiPlugin,
+    palette::PalettePlugin,
+    simulation::{self, Client, Host, Network, RemoteControl, DEFAULT_PORT, DEFAULT_REMOTE_PORT},
+    timeline::TimelinePlugin,
+};
 
 fn main() {
-    App::new()
-        .add_plugins(
-            DefaultPlugins
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Sandbox".to_string(),
-                        present_mode: PresentMode::Immediate,
-                        ..Default::default()
-                    }),
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Sandbox".to_string(),
+                    present_mode: PresentMode::Immediate,
                     ..Default::default()
-                })
-                .set(ImagePlugin::default_nearest()),
-        )
-        .add_plugins(DebugUiPlugin)
-        .add_plugins(simulation::plugin::SimulationPlugin)
-        .add_systems(Startup, spawn_pivot)
-        .add_systems(PostUpdate, move_piv_towards_mouse)
-        .run();
+                }),
+                ..Default::default()
+            })
+            .set(ImagePlugin::default_nearest()),
+    )
+    .add_plugins(DebugUiPlugin)
+    .add_plugins(ControlsPlugin)
+    .add_plugins(PalettePlugin)
+    .add_plugins(TimelinePlugin)
+    .add_plugins(simulation::plugin::SimulationPlugin)
+    .add_systems(Startup, spawn_pivot)
+    .add_systems(PostUpdate, move_piv_towards_mouse);
+
+    match resources_from_args() {
+        Ok((network, remote)) => {
+            if let Some(network) = network {
+                app.insert_resource(network);
+            }
+            if let Some(remote) = remote {
+                app.insert_resource(remote);
+            }
+        }
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    }
+    app.run();
+}
+
+/// `--host [address]` shares the world with whoever joins, `--join <address>` plays in the
+/// world of a host. Addresses without a port use [`DEFAULT_PORT`].
+///
+/// `--remote [address]` lets other programs drive the sandbox, on localhost and
+/// [`DEFAULT_REMOTE_PORT`] unless told otherwise.
+fn resources_from_args() -> Result<(Option<Network>, Option<RemoteControl>), String> {
+    let mut args = std::env::args().skip(1).peekable();
+    let with_port = |address: String, port: u16| {
+        if address.contains(':') {
+            address
+        } else {
+            format!("{address}:{port}")
+        }
+    };
+
+    let mut network = None;
+    let mut remote = None;
+    while let Some(arg) = args.next() {
+        let address = args.next_if(|next| !next.starts_with("--"));
+        match arg.as_str() {
+            "--host" => {
+                let address = with_port(address.unwrap_or("0.0.0.0".to_string()), DEFAULT_PORT);
+                let host = Host::bind(&address)
+                    .map_err(|err| format!("Failed to host on {address}: {err}"))?;
+                info!("Hosting on {}", host.address());
+                network = Some(Network::Host(host));
+            }
+            "--join" => {
+                let address = with_port(
+                    address.ok_or("--join needs the address of the host")?,
+                    DEFAULT_PORT,
+                );
+                let client = Client::connect(&address)
+                    .map_err(|err| format!("Failed to join {address}: {err}"))?;
+                info!("Joined {address}");
+                network = Some(Network::Client(client));
+            }
+            "--remote" => {
+                let address = with_port(
+                    address.unwrap_or("127.0.0.1".to_string()),
+                    DEFAULT_REMOTE_PORT,
+                );
+                let control = RemoteControl::bind(&address)
+                    .map_err(|err| format!("Failed to listen on {address}: {err}"))?;
+                info!("Taking remote control requests on {}", control.address());
+                remote = Some(control);
+            }
+            other => {
+                return Err(format!(
+                    "Unknown argument {other}, expected --host [address], --join <address> or \
+                     --remote [address]"
+                ))
+            }
+        }
+    }
+    Ok((network, remote))
 }
 
 #[derive(Component)]
@@ -79,9 +156,7 @@ fn move_piv_towards_mouse(
         return;
     };
 
-    let Ok(mouse_position) = camera
-        .viewport_to_world(camera_transform, cursor_position)
-        .map(|p| p.origin.truncate())
+    let Some(mouse_position) = ScreenPos::from_viewport(camera, camera_transform, cursor_position)
     else {
         return;
     };
@@ -90,6 +165,6 @@ fn move_piv_towards_mouse(
         return;
     };
 
-    piv.translation.x = mouse_position.x;
-    piv.translation.y = mouse_position.y;
+    piv.translation.x = mouse_position.0.x;
+    piv.translation.y = mouse_position.0.y;
 }
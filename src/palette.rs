@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    controls::{Action, ActionState},
+    simulation::{
+        plugin::{SelectedElement, SelectedEmitter},
+        ElementCategory, ElementKind, ElementRegistry, EmitterPreset, Sandbox,
+    },
+};
+
+#[derive(Debug, Resource)]
+pub struct Palette {
+    pub open: bool,
+    pub search: String,
+    pub favourites: BTreeSet<ElementKind>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            open: true,
+            search: String::new(),
+            favourites: BTreeSet::new(),
+        }
+    }
+}
+
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Palette>()
+            .add_systems(Update, (toggle_palette, palette_ui));
+    }
+}
+
+fn toggle_palette(mut palette: ResMut<Palette>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::TogglePalette) {
+        palette.open = !palette.open;
+    }
+}
+
+fn palette_ui(
+    mut contexts: EguiContexts,
+    mut palette: ResMut<Palette>,
+    mut selected_element: ResMut<SelectedElement>,
+    mut selected_emitter: ResMut<SelectedEmitter>,
+    sandbox: Res<Sandbox>,
+) {
+    let elements = &*sandbox.elements;
+    let Palette {
+        open,
+        search,
+        favourites,
+    } = &mut *palette;
+    let selected = &mut selected_element.0;
+    let emitter = &mut selected_emitter.0;
+
+    egui::Window::new("Elements")
+        .open(open)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Selected:");
+                match emitter {
+                    Some(preset) => {
+                        ui.strong(preset.name());
+                    }
+                    None => {
+                        swatch(ui, elements, *selected);
+                        ui.strong(elements.name(*selected));
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Search:");
+                ui.text_edit_singleline(search);
+            });
+            ui.separator();
+
+            let query = search.trim().to_lowercase();
+            let matches = |kind: &ElementKind| elements.name(*kind).to_lowercase().contains(&query);
+
+            let favourite_matches = favourites
+                .iter()
+                .copied()
+                .filter(matches)
+                .collect::<Vec<_>>();
+            if !favourite_matches.is_empty() {
+                egui::CollapsingHeader::new("Favourites")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for kind in favourite_matches {
+                            element_row(ui, elements, kind, selected, emitter, favourites);
+                        }
+                    });
+            }
+
+            for category in ElementCategory::ALL {
+                let kinds = elements
+                    .kinds()
+                    .filter(|kind| elements.category(*kind) == category && matches(kind))
+                    .collect::<Vec<_>>();
+                if kinds.is_empty() {
+                    continue;
+                }
+
+                egui::CollapsingHeader::new(category.name())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for kind in kinds {
+                            element_row(ui, elements, kind, selected, emitter, favourites);
+                        }
+                    });
+            }
+
+            let presets = EmitterPreset::ALL
+                .into_iter()
+                .filter(|preset| preset.name().to_lowercase().contains(&query))
+                .collect::<Vec<_>>();
+            if !presets.is_empty() {
+                egui::CollapsingHeader::new("Emitters")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for preset in presets {
+                            let label = ui
+                                .selectable_label(*emitter == Some(preset), preset.name())
+                                .on_hover_text(preset.description());
+                            if label.clicked() {
+                                *emitter = Some(preset);
+                            }
+                        }
+                    });
+            }
+        });
+}
+
+fn element_row(
+    ui: &mut egui::Ui,
+    elements: &ElementRegistry,
+    kind: ElementKind,
+    selected: &mut ElementKind,
+    emitter: &mut Option<EmitterPreset>,
+    favourites: &mut BTreeSet<ElementKind>,
+) {
+    ui.horizontal(|ui| {
+        swatch(ui, elements, kind);
+
+        let label = ui
+            .selectable_label(*selected == kind && emitter.is_none(), elements.name(kind))
+            .on_hover_ui(|ui| element_tooltip(ui, elements, kind));
+        if label.clicked() {
+            *selected = kind;
+            *emitter = None;
+        }
+
+        let is_favourite = favourites.contains(&kind);
+        let (star, hint) = if is_favourite {
+            ("★", "Remove from favourites")
+        } else {
+            ("☆", "Add to favourites")
+        };
+        if ui.small_button(star).on_hover_text(hint).clicked() {
+            if is_favourite {
+                favourites.remove(&kind);
+            } else {
+                favourites.insert(kind);
+            }
+        }
+    });
+}
+
+fn element_tooltip(ui: &mut egui::Ui, elements: &ElementRegistry, kind: ElementKind) {
+    ui.strong(elements.name(kind));
+    ui.label(format!("Category: {}", elements.category(kind).name()));
+    ui.label(format!("Density: {}", elements.density(kind)));
+    ui.label(format!("Hardness: {}", elements.hardness(kind)));
+}
+
+fn swatch(ui: &mut egui::Ui, elements: &ElementRegistry, kind: ElementKind) {
+    let (r, g, b) = elements.base_color(kind);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(14.0, 14.0), egui::Sense::hover());
+    ui.painter()
+        .rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+}
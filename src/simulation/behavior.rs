@@ -0,0 +1,223 @@
+use bevy::math::IVec2;
+
+use super::*;
+
+/// How the elements of a kind act, registered for that kind in the [`ElementRegistry`].
+///
+/// Every hook gets a [`LocalApi`] whose `element` is the element the hook is about, and
+/// positions are relative to the chunk at the center of the API.
+pub trait ElementBehavior: Send + Sync + 'static {
+    /// Called once per tick for every element of the kind inside a dirty rect.
+    fn tick(&self, api: &mut LocalApi);
+
+    /// Called after an element was put into the world, e.g. drawn by the player or created by
+    /// [`LocalApi::replace_element`].
+    fn on_placed(&self, _api: &mut LocalApi) {}
+
+    /// Called before an element is overwritten by an element of another kind.
+    fn on_destroyed(&self, _api: &mut LocalApi) {}
+
+    /// Called when a moving element runs into the element at `other`.
+    fn on_contact(&self, _api: &mut LocalApi, _other: IVec2) {}
+}
+
+/// Accelerates the held element by `sideways` along the side axis of gravity and `falling`
+/// along its direction.
+fn accelerate(api: &mut LocalApi, gravity: GravityDirection, sideways: f32, falling: f32) {
+    let acceleration = gravity.side().as_vec2() * sideways + gravity.down().as_vec2() * falling;
+    api.accelerate(acceleration.x, acceleration.y);
+}
+
+/// Which way along the side axis the held element is moving, or a random one if it isn't.
+fn sideways_direction(api: &mut LocalApi, gravity: GravityDirection) -> i32 {
+    let speed = api.element.0.velocity.dot(gravity.side().as_vec2());
+    if speed == 0.0 {
+        api.random_direction()
+    } else {
+        speed.signum() as i32
+    }
+}
+
+pub struct SandBehavior;
+
+impl ElementBehavior for SandBehavior {
+    fn tick(&self, api: &mut LocalApi) {
+        let gravity = api.gravity();
+        if gravity.is_zero() {
+            api.move_element();
+            return;
+        }
+
+        let position = api.element.1;
+        let (down, side, fall) = (
+            gravity.direction.down(),
+            gravity.direction.side(),
+            gravity.strength,
+        );
+        let dir = sideways_direction(api, gravity.direction);
+
+        let can_move_down = api.can_move_to(position + down);
+
+        if can_move_down {
+            api.update_element(|element| scale_along(&mut element.velocity, side, 0.6));
+            accelerate(api, gravity.direction, 0.0, fall);
+        } else if api.can_move_to(position + side * dir + down)
+            && api.can_move_to(position + side * dir)
+        {
+            accelerate(api, gravity.direction, 0.6 * (dir as f32), fall);
+        } else if api.can_move_to(position - side * dir + down)
+            && api.can_move_to(position - side * dir)
+        {
+            accelerate(api, gravity.direction, 0.6 * (-dir as f32), fall);
+        }
+
+        api.move_element();
+    }
+}
+
+/// How a liquid flows, see [`LiquidBehavior`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Liquid {
+    /// How fast it spreads sideways, in cells per tick².
+    pub dispersion: f32,
+    /// The chance, between 0 and 1, that it stays put for a tick instead of flowing. Falling
+    /// freely isn't slowed down.
+    pub viscosity: f64,
+    /// How many cells sideways it looks for somewhere lower to flow to, up to [`MAX_REACH`].
+    /// If there is nowhere and no liquid is pressing down on it, it settles, so the surface
+    /// comes to rest instead of rippling forever.
+    pub reach: i32,
+}
+
+impl Liquid {
+    pub const WATER: Liquid = Liquid {
+        dispersion: 0.6,
+        viscosity: 0.0,
+        reach: 32,
+    };
+    pub const OIL: Liquid = Liquid {
+        dispersion: 0.7,
+        viscosity: 0.1,
+        reach: 24,
+    };
+    pub const HONEY: Liquid = Liquid {
+        dispersion: 0.2,
+        viscosity: 0.85,
+        reach: 4,
+    };
+}
+
+/// Falls, slides off slopes and spreads out until its surface is level. Denser liquids sink
+/// through lighter ones, which is all it takes for liquids to layer.
+pub struct LiquidBehavior(pub Liquid);
+
+impl LiquidBehavior {
+    /// Whether there is somewhere lower to flow to within reach along `step`. Liquid already
+    /// flowing that way is followed, so a stream doesn't settle behind its head.
+    fn finds_drop(&self, api: &LocalApi, step: IVec2, down: IVec2) -> bool {
+        let position = api.element.1;
+        for distance in 1..=self.0.reach {
+            let beside = position + step * distance;
+            if !api.can_move_to(beside) {
+                let element = api.get_element(beside);
+                return api.elements.category(element.kind) == ElementCategory::Liquid
+                    && element.velocity.dot(step.as_vec2()) >= 1.0;
+            }
+            if api.can_move_to(beside + down) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Once the liquid has left `position`, the surface within reach may have settled and
+    /// now has somewhere lower to flow to.
+    fn wake_surface(&self, api: &mut LocalApi, position: IVec2, down: IVec2, side: IVec2) {
+        if api.element.1 != position {
+            let above = position - down;
+            api.mark_dirty(above - side * self.0.reach);
+            api.mark_dirty(above + side * self.0.reach);
+        }
+    }
+
+    /// Whether viscosity holds the liquid back this tick. It stays awake, as it still has
+    /// somewhere to go.
+    fn held_back(&self, api: &mut LocalApi) -> bool {
+        if self.0.viscosity > 0.0 && api.chance(self.0.viscosity) {
+            api.mark_element_dirty();
+            return true;
+        }
+        false
+    }
+}
+
+impl ElementBehavior for LiquidBehavior {
+    fn tick(&self, api: &mut LocalApi) {
+        let gravity = api.gravity();
+        if gravity.is_zero() {
+            api.move_element();
+            return;
+        }
+
+        let position = api.element.1;
+        let (down, side, fall) = (
+            gravity.direction.down(),
+            gravity.direction.side(),
+            gravity.strength,
+        );
+
+        if api.can_move_to(position + down) {
+            api.update_element(|element| scale_along(&mut element.velocity, side, 0.4));
+            accelerate(api, gravity.direction, 0.0, fall);
+            api.move_element();
+            self.wake_surface(api, position, down, side);
+            return;
+        }
+
+        let dir = api.random_direction();
+        let slide = [dir, -dir].into_iter().find(|dir| {
+            api.can_move_to(position + side * *dir + down)
+                && api.can_move_to(position + side * *dir)
+        });
+        if let Some(dir) = slide {
+            if self.held_back(api) {
+                return;
+            }
+            accelerate(
+                api,
+                gravity.direction,
+                self.0.dispersion * (dir as f32),
+                fall,
+            );
+            api.move_element();
+            return;
+        }
+
+        // Liquid resting on top pushes it aside, that's how the bottom of a pool spreads out
+        let above = api.get_element(position - down).kind;
+        let pressed = api.elements.category(above) == ElementCategory::Liquid;
+        let dir = sideways_direction(api, gravity.direction);
+        let flow = [dir, -dir].into_iter().find(|dir| {
+            (pressed && api.can_move_to(position + side * *dir))
+                || self.finds_drop(api, side * *dir, down)
+        });
+        match flow {
+            Some(dir) => {
+                if self.held_back(api) {
+                    return;
+                }
+
+                // Spreads faster over itself than over the ground
+                let mut speed = self.0.dispersion;
+                if api.get_element(position + down).kind == api.element.0.kind {
+                    speed *= 2.0;
+                }
+                accelerate(api, gravity.direction, speed * (dir as f32), 0.0);
+            }
+            None => api.update_element(|element| scale_along(&mut element.velocity, side, 0.0)),
+        }
+
+        api.move_element();
+        self.wake_surface(api, position, down, side);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::coordinates::{ChunkPos, ChunkSize, WorldCell};
+
+use super::*;
+
+/// How many changed cells a single chunk lists in a [`Discrepancy`].
+const MAX_REPORTED_CELLS: usize = 8;
+
+/// A copy of the element kinds of every chunk in a [`Sandbox`] and of its flying particles,
+/// used to check that a tick neither creates nor destroys elements, other than on purpose.
+#[derive(Debug, Clone, Default)]
+pub struct ElementCensus {
+    size: ChunkSize,
+    chunks: HashMap<ChunkPos, Vec<ElementKind>>,
+    particles: Vec<ElementKind>,
+    /// How much the count of each kind is meant to change, see [`ElementCensus::expect`].
+    expected: BTreeMap<ElementKind, i64>,
+}
+
+/// A kind whose total count changed between two censuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub kind: ElementKind,
+    pub before: usize,
+    pub after: usize,
+    /// Every chunk where the count of `kind` changed.
+    pub chunks: Vec<ChunkDiscrepancy>,
+    /// How much the count of `kind` among the flying particles changed.
+    pub particles: i64,
+    /// How much of the change was [expected](ElementCensus::expect).
+    pub expected: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkDiscrepancy {
+    pub position: ChunkPos,
+    pub delta: i64,
+    /// World positions of (some of) the cells that gained or lost `kind`.
+    pub cells: Vec<WorldCell>,
+}
+
+impl ElementCensus {
+    pub fn take(sandbox: &Sandbox) -> Self {
+        let mut chunks = HashMap::with_capacity(sandbox.chunks.len());
+        for (position, chunk) in sandbox.chunks.iter() {
+            chunks.insert(*position, chunk.read().kinds().to_vec());
+        }
+
+        let particles = sandbox
+            .particles
+            .iter()
+            .map(|particle| particle.element.kind)
+            .collect();
+
+        Self {
+            size: sandbox.chunk_size(),
+            chunks,
+            particles,
+            expected: BTreeMap::new(),
+        }
+    }
+
+    /// Lets cells change from one kind to another on purpose, like elements turned into others
+    /// by their behaviour.
+    pub fn expect(&mut self, conversions: &[(ElementKind, ElementKind)]) {
+        for (from, to) in conversions {
+            *self.expected.entry(*from).or_default() -= 1;
+            *self.expected.entry(*to).or_default() += 1;
+        }
+    }
+
+    pub fn totals(&self) -> BTreeMap<ElementKind, usize> {
+        let mut totals = BTreeMap::new();
+        for kind in self.chunks.values().flatten().chain(self.particles.iter()) {
+            *totals.entry(*kind).or_default() += 1;
+        }
+
+        totals.remove(&ElementKind::Air);
+        totals
+    }
+
+    /// Compares this census (taken before a tick) against `after`, returning one entry per
+    /// element kind whose total count is different than expected.
+    pub fn compare(&self, after: &ElementCensus) -> Vec<Discrepancy> {
+        let before_totals = self.totals();
+        let after_totals = after.totals();
+
+        let mut kinds = before_totals
+            .keys()
+            .chain(after_totals.keys())
+            .collect::<Vec<_>>();
+        kinds.sort();
+        kinds.dedup();
+
+        let mut discrepancies = Vec::new();
+        for kind in kinds {
+            let before = before_totals.get(kind).copied().unwrap_or(0);
+            let after_count = after_totals.get(kind).copied().unwrap_or(0);
+            let expected = self.expected.get(kind).copied().unwrap_or(0);
+            if before as i64 + expected == after_count as i64 {
+                continue;
+            }
+
+            discrepancies.push(Discrepancy {
+                kind: *kind,
+                before,
+                after: after_count,
+                chunks: self.chunk_discrepancies(after, *kind),
+                particles: count(&after.particles, *kind) - count(&self.particles, *kind),
+                expected,
+            });
+        }
+
+        discrepancies
+    }
+
+    fn chunk_discrepancies(
+        &self,
+        after: &ElementCensus,
+        kind: ElementKind,
+    ) -> Vec<ChunkDiscrepancy> {
+        let mut positions = self
+            .chunks
+            .keys()
+            .chain(after.chunks.keys())
+            .collect::<Vec<_>>();
+        positions.sort_by_key(|pos| (pos.0.y, pos.0.x));
+        positions.dedup();
+
+        let empty = vec![ElementKind::Air; self.size.cells()];
+        let mut chunks = Vec::new();
+        for position in positions {
+            let old = self.chunks.get(position).unwrap_or(&empty);
+            let new = after.chunks.get(position).unwrap_or(&empty);
+
+            let delta = count(new, kind) - count(old, kind);
+            if delta == 0 {
+                continue;
+            }
+
+            let cells = old
+                .iter()
+                .zip(new.iter())
+                .enumerate()
+                .filter(|(_, (old, new))| old != new && (**old == kind || **new == kind))
+                .take(MAX_REPORTED_CELLS)
+                .map(|(index, _)| position.cell(self.size.to_position(index), self.size))
+                .collect();
+
+            chunks.push(ChunkDiscrepancy {
+                position: *position,
+                delta,
+                cells,
+            });
+        }
+
+        chunks
+    }
+}
+
+fn count(kinds: &[ElementKind], kind: ElementKind) -> i64 {
+    kinds.iter().filter(|other| **other == kind).count() as i64
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} count changed from {} to {}",
+            self.kind, self.before, self.after
+        )?;
+
+        for chunk in self.chunks.iter() {
+            write!(
+                f,
+                "; chunk {} ({:+}) at {:?}",
+                chunk.position,
+                chunk.delta,
+                chunk.cells.iter().map(|cell| cell.0).collect::<Vec<_>>()
+            )?;
+        }
+
+        if self.particles != 0 {
+            write!(f, "; particles ({:+})", self.particles)?;
+        }
+
+        if self.expected != 0 {
+            write!(f, "; {:+} on purpose", self.expected)?;
+        }
+
+        Ok(())
+    }
+}
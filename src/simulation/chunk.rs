@@ -1,24 +1,111 @@
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::Arc;
 
 use bevy::math::{IVec2, Vec2};
+use parking_lot::{ArcRwLockWriteGuard, RawRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use serde::{Deserialize, Serialize};
 
-use crate::{common::Rect, constants::*};
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, ChunkSize, LocalCell},
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ElementCategory {
+    Powder,
+    Liquid,
+    Gas,
+    Solid,
+    Special,
+}
+
+impl ElementCategory {
+    pub const ALL: [ElementCategory; 5] = [
+        Self::Powder,
+        Self::Liquid,
+        Self::Gas,
+        Self::Solid,
+        Self::Special,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Powder => "Powders",
+            Self::Liquid => "Liquids",
+            Self::Gas => "Gases",
+            Self::Solid => "Solids",
+            Self::Special => "Special",
+        }
+    }
+}
+
+/// Serialized as is, custom kinds by their index. Saves go by the names of the kinds instead,
+/// see [`WorldSave::kinds`](super::WorldSave::kinds).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ElementKind {
     Air,
     Sand,
     Stone,
     Water,
+    Oil,
+    Honey,
+    Gunpowder,
+    Tnt,
+    /// An element registered at runtime, its properties live in the [`ElementRegistry`].
+    Custom(u16),
 }
 
 impl ElementKind {
+    /// The built-in kinds, see [`ElementRegistry::kinds`] for the custom ones too.
+    pub const ALL: [ElementKind; 8] = [
+        Self::Air,
+        Self::Sand,
+        Self::Stone,
+        Self::Water,
+        Self::Oil,
+        Self::Honey,
+        Self::Gunpowder,
+        Self::Tnt,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Air => "Air",
+            Self::Sand => "Sand",
+            Self::Stone => "Stone",
+            Self::Water => "Water",
+            Self::Oil => "Oil",
+            Self::Honey => "Honey",
+            Self::Gunpowder => "Gunpowder",
+            Self::Tnt => "TNT",
+            Self::Custom(_) => "Custom",
+        }
+    }
+
+    pub const fn category(&self) -> ElementCategory {
+        match self {
+            Self::Air => ElementCategory::Special,
+            Self::Sand => ElementCategory::Powder,
+            Self::Stone => ElementCategory::Solid,
+            Self::Water => ElementCategory::Liquid,
+            Self::Oil => ElementCategory::Liquid,
+            Self::Honey => ElementCategory::Liquid,
+            Self::Gunpowder => ElementCategory::Powder,
+            Self::Tnt => ElementCategory::Solid,
+            Self::Custom(_) => ElementCategory::Special,
+        }
+    }
+
     pub const fn base_color(&self) -> (u8, u8, u8) {
         match self {
             Self::Air => (0, 0, 0),
             Self::Sand => (232, 171, 79),
             Self::Stone => (114, 121, 133),
             Self::Water => (44, 113, 232),
+            Self::Oil => (92, 70, 34),
+            Self::Honey => (235, 168, 28),
+            Self::Gunpowder => (64, 62, 60),
+            Self::Tnt => (196, 38, 46),
+            Self::Custom(_) => (255, 0, 255),
         }
     }
 
@@ -28,16 +115,62 @@ impl ElementKind {
             Self::Sand => 100,
             Self::Stone => 255,
             Self::Water => 60,
+            Self::Oil => 40,
+            Self::Honey => 80,
+            Self::Gunpowder => 90,
+            Self::Tnt => 255,
+            Self::Custom(_) => 0,
+        }
+    }
+
+    /// How much of an explosion's power an element withstands before it breaks.
+    pub const fn hardness(&self) -> u8 {
+        match self {
+            Self::Air => 0,
+            Self::Sand => 15,
+            Self::Stone => 80,
+            Self::Water => 5,
+            Self::Oil => 5,
+            Self::Honey => 5,
+            Self::Gunpowder => 5,
+            Self::Tnt => 40,
+            Self::Custom(_) => 0,
+        }
+    }
+
+    /// What an element breaks into when an explosion is too much for it, nothing if it's
+    /// destroyed.
+    pub const fn rubble(&self) -> Option<ElementKind> {
+        match self {
+            Self::Stone => Some(Self::Sand),
+            _ => None,
+        }
+    }
+
+    /// The radius and power of the explosion an element sets off when another explosion
+    /// reaches it.
+    pub const fn blast(&self) -> Option<(f32, f32)> {
+        match self {
+            Self::Gunpowder => Some((4.0, 60.0)),
+            Self::Tnt => Some((12.0, 200.0)),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// How many blocked moves in a row it takes for an element to come to rest.
+pub const REST_TICKS: u8 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Element {
     pub color: (u8, u8, u8),
     pub velocity: Vec2,
     pub kind: ElementKind,
-    pub wframe: u8,
+    /// The tick the element was last updated in, so it isn't updated twice when it moves
+    /// ahead of the scan. Zero for elements that were never updated.
+    pub wframe: u64,
+    /// How many times in a row the element tried to move and was blocked.
+    pub rest: u8,
 }
 
 impl Default for Element {
@@ -47,34 +180,63 @@ impl Default for Element {
             velocity: Vec2::ZERO,
             kind: ElementKind::Air,
             wframe: 0,
+            rest: 0,
         }
     }
 }
 
+impl Element {
+    /// Whether the element has settled against whatever blocks it. Accelerating no longer
+    /// keeps its chunk awake, until it moves again or is pushed towards a free cell.
+    pub const fn is_resting(&self) -> bool {
+        self.rest >= REST_TICKS
+    }
+}
+
+/// A square of the world, storing its elements field by field: one array per field of
+/// [`Element`], all indexed row by row (see [`ChunkSize::to_index`]). Code that only looks at kinds,
+/// like movement checks and the census, doesn't drag velocities and colours through the cache.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Chunk {
-    pub position: IVec2,
+    pub position: ChunkPos,
+    size: ChunkSize,
     pub current_dirty_rect: Rect,
     pub next_dirty_rect: Rect,
-    elements: [Element; CHUNK_SIZE * CHUNK_SIZE],
+    /// The cells that marked the chunk as dirty during the last tick, if tracked. They are
+    /// what keeps it awake.
+    pub wakers: Vec<LocalCell>,
+    next_wakers: Vec<LocalCell>,
+    track_wakers: bool,
+    kinds: Box<[ElementKind]>,
+    velocities: Box<[Vec2]>,
+    colors: Box<[(u8, u8, u8)]>,
+    wframes: Box<[u64]>,
+    rests: Box<[u8]>,
 }
 
 #[allow(unused)]
 impl Chunk {
-    pub fn new(position: IVec2) -> Self {
+    pub fn new(position: ChunkPos, size: ChunkSize) -> Self {
+        let air = Element::default();
+        let cells = size.cells();
         Self {
             position,
-            current_dirty_rect: Rect::new(IVec2::ZERO, IVec2::splat(CHUNK_SIZE_I32)),
-            next_dirty_rect: Rect::new(IVec2::ZERO, IVec2::splat(CHUNK_SIZE_I32)),
-            elements: [Element::default(); CHUNK_SIZE * CHUNK_SIZE],
+            size,
+            current_dirty_rect: size.rect(),
+            next_dirty_rect: size.rect(),
+            wakers: Vec::new(),
+            next_wakers: Vec::new(),
+            track_wakers: false,
+            kinds: vec![air.kind; cells].into(),
+            velocities: vec![air.velocity; cells].into(),
+            colors: vec![air.color; cells].into(),
+            wframes: vec![air.wframe; cells].into(),
+            rests: vec![air.rest; cells].into(),
         }
     }
 
-    pub const fn is_outside(position: IVec2) -> bool {
-        position.x < 0
-            || position.y < 0
-            || position.x >= CHUNK_SIZE_I32
-            || position.y >= CHUNK_SIZE_I32
+    pub fn size(&self) -> ChunkSize {
+        self.size
     }
 
     pub fn dirty_rect(&self) -> Rect {
@@ -85,39 +247,99 @@ impl Chunk {
         !self.current_dirty_rect.is_empty() || !self.next_dirty_rect.is_empty()
     }
 
-    pub fn is_empty(&self, position: IVec2) -> bool {
-        self.get_element(position).kind == ElementKind::Air
+    pub fn is_empty(&self, position: LocalCell) -> bool {
+        self.kind(position) == ElementKind::Air
+    }
+
+    pub fn get_element(&self, position: LocalCell) -> Element {
+        let index = self.size.to_index(position);
+        Element {
+            color: self.colors[index],
+            velocity: self.velocities[index],
+            kind: self.kinds[index],
+            wframe: self.wframes[index],
+            rest: self.rests[index],
+        }
+    }
+
+    pub fn kind(&self, position: LocalCell) -> ElementKind {
+        self.kinds[self.size.to_index(position)]
+    }
+
+    /// The kind of every cell, row by row.
+    pub fn kinds(&self) -> &[ElementKind] {
+        &self.kinds
+    }
+
+    /// The colour of every cell, row by row like the pixels of an image.
+    pub fn colors(&self) -> &[(u8, u8, u8)] {
+        &self.colors
     }
 
-    pub fn get_element(&self, position: IVec2) -> &Element {
-        &self.elements[Self::to_index(position.x, position.y)]
+    pub fn wframe(&self, position: LocalCell) -> u64 {
+        self.wframes[self.size.to_index(position)]
     }
 
-    pub fn get_element_mut(&mut self, position: IVec2) -> &mut Element {
-        &mut self.elements[Self::to_index(position.x, position.y)]
+    /// Changes an element in place, without marking it as dirty. Returns the changed element.
+    pub fn update_element(
+        &mut self,
+        position: LocalCell,
+        callback: impl FnOnce(&mut Element),
+    ) -> Element {
+        let mut element = self.get_element(position);
+        callback(&mut element);
+        let index = self.size.to_index(position);
+        self.write(index, element);
+        element
     }
 
-    pub fn set_element(&mut self, position: IVec2, element: Element) {
+    pub fn set_element(&mut self, position: LocalCell, element: Element) {
         self.mark_point_dirty(position);
-        self.elements[Self::to_index(position.x, position.y)] = element;
+        let index = self.size.to_index(position);
+        self.write(index, element);
+    }
+
+    pub fn set_wframe(&mut self, position: LocalCell, wframe: u64) {
+        let index = self.size.to_index(position);
+        self.wframes[index] = wframe;
     }
 
-    pub fn set_wframe(&mut self, position: IVec2, wframe: u8) {
-        self.elements[Self::to_index(position.x, position.y)].wframe = wframe;
+    fn write(&mut self, index: usize, element: Element) {
+        self.kinds[index] = element.kind;
+        self.velocities[index] = element.velocity;
+        self.colors[index] = element.color;
+        self.wframes[index] = element.wframe;
+        self.rests[index] = element.rest;
     }
 
-    pub fn mark_point_dirty(&mut self, position: IVec2) {
+    pub fn mark_point_dirty(&mut self, position: LocalCell) {
         self.next_dirty_rect
-            .union_point_plus(position, IVec2::splat(2));
+            .union_point_plus(position.0, IVec2::splat(2));
+        if self.track_wakers {
+            self.next_wakers.push(position);
+        }
     }
 
-    pub fn mark_dirty_everything(&mut self) {
-        self.next_dirty_rect = Rect::new(IVec2::ZERO, IVec2::splat(CHUNK_SIZE_I32));
+    /// Starts or stops remembering the [wakers](Chunk::wakers), for the debug overlay.
+    pub fn track_wakers(&mut self, track: bool) {
+        self.track_wakers = track;
+        if !track {
+            self.wakers.clear();
+            self.next_wakers.clear();
+        }
+    }
+
+    /// Moves on to the next tick: the area marked as dirty during this one gets updated next.
+    pub fn swap_dirty_rects(&mut self) {
         self.current_dirty_rect = self.next_dirty_rect;
+        self.next_dirty_rect.clear();
+        self.wakers.clear();
+        std::mem::swap(&mut self.wakers, &mut self.next_wakers);
     }
 
-    pub const fn to_index(x: i32, y: i32) -> usize {
-        (x * CHUNK_SIZE_I32 + y) as usize
+    pub fn mark_dirty_everything(&mut self) {
+        self.next_dirty_rect = self.size.rect();
+        self.current_dirty_rect = self.next_dirty_rect;
     }
 }
 
@@ -128,6 +350,9 @@ pub struct SharedChunk {
 
 pub type ReadableChunk<'a> = RwLockReadGuard<'a, Chunk>;
 pub type WritableChunk<'a> = RwLockWriteGuard<'a, Chunk>;
+/// A chunk locked for writing until the guard is dropped, which doesn't borrow the
+/// [`SharedChunk`] it came from.
+pub type OwnedChunk = ArcRwLockWriteGuard<RawRwLock, Chunk>;
 
 impl SharedChunk {
     pub fn new(chunk: Chunk) -> Self {
@@ -137,10 +362,19 @@ impl SharedChunk {
     }
 
     pub fn read(&self) -> ReadableChunk {
-        self.inner.read().unwrap()
+        self.inner.read()
     }
 
     pub fn write(&self) -> WritableChunk {
-        self.inner.write().unwrap()
+        self.inner.write()
+    }
+
+    pub fn lock(&self) -> OwnedChunk {
+        self.inner.write_arc()
+    }
+
+    /// Whether both share the same chunk, rather than two that look the same.
+    pub fn ptr_eq(&self, other: &SharedChunk) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
     }
 }
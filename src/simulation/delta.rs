@@ -0,0 +1,386 @@
+//! Compact binary deltas of chunks: the cells of an area that differ from a baseline.
+//!
+//! The one encoding for everything that stores or sends chunks, like the updates a
+//! [`Host`] sends its clients and the [snapshots](Snapshot) of the rewind and of recordings.
+//! The area is usually the dirty rect of the chunk, as nothing changes outside of it.
+//!
+//! A delta starts with the area and a mask of the changed cells, as alternating runs of
+//! unchanged and changed cells. The changed cells follow field by field, like a [`Chunk`]
+//! stores them, each field as runs of equal values: kinds, colours, velocities, rests and, if
+//! [exact](Precision::Exact), the ticks. Numbers are LEB128 varints, signed ones zigzagged.
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Rect, coordinates::LocalCell};
+
+use super::*;
+
+/// The steps velocities are rounded to by [`Precision::Quantised`], in cells per tick.
+pub const VELOCITY_STEP: f32 = 1.0 / 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precision {
+    /// Velocities are rounded to [`VELOCITY_STEP`] and the ticks the elements were last updated
+    /// in are left out, which is plenty to draw the world or carry on simulating it.
+    Quantised,
+    /// Every field bit for bit, so the simulation resumes exactly like it would have.
+    Exact,
+}
+
+impl Precision {
+    /// Whether `a` and `b` encode the same, so that a delta can leave the cell out.
+    fn same(self, a: &Element, b: &Element) -> bool {
+        a.kind == b.kind
+            && a.color == b.color
+            && a.rest == b.rest
+            && match self {
+                Self::Quantised => quantise(a.velocity) == quantise(b.velocity),
+                Self::Exact => {
+                    a.velocity.to_array().map(f32::to_bits)
+                        == b.velocity.to_array().map(f32::to_bits)
+                        && a.wframe == b.wframe
+                }
+            }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkDelta {
+    pub position: IVec2,
+    bytes: Vec<u8>,
+}
+
+impl ChunkDelta {
+    /// Encodes the cells of `chunk` inside `area` that differ from `baseline`, or all of them
+    /// without a baseline. The area is clipped to the chunk.
+    pub fn encode(
+        chunk: &Chunk,
+        baseline: Option<&Chunk>,
+        area: Rect,
+        precision: Precision,
+    ) -> Self {
+        let size = chunk.size();
+        let area = if area.is_empty() {
+            Rect::new(IVec2::ZERO, IVec2::ZERO)
+        } else {
+            let area = area.intersection(&size.rect());
+            Rect::new(area.min, area.max.max(area.min))
+        };
+
+        let mut changed = Vec::new();
+        let mut mask = Vec::new();
+        for y in area.min.y..area.max.y {
+            for x in area.min.x..area.max.x {
+                let position = LocalCell::new(x, y);
+                let element = chunk.get_element(position);
+                let is_changed = baseline.is_none_or(|baseline| {
+                    !precision.same(&baseline.get_element(position), &element)
+                });
+                mask.push(is_changed);
+                if is_changed {
+                    changed.push(element);
+                }
+            }
+        }
+
+        let mut bytes = vec![match precision {
+            Precision::Quantised => 0,
+            Precision::Exact => 1,
+        }];
+        for value in [area.min.x, area.min.y, area.max.x, area.max.y] {
+            write_varint(&mut bytes, value as u64);
+        }
+        write_varint(&mut bytes, changed.len() as u64);
+
+        // Alternating runs, starting with unchanged cells
+        let mut is_changed = false;
+        let mut run = 0;
+        for cell in mask.iter().copied() {
+            if cell != is_changed {
+                write_varint(&mut bytes, run);
+                is_changed = cell;
+                run = 0;
+            }
+            run += 1;
+        }
+        if !mask.is_empty() {
+            write_varint(&mut bytes, run);
+        }
+
+        write_runs(&mut bytes, changed.iter().map(|e| e.kind), |bytes, kind| {
+            write_varint(bytes, kind_code(kind))
+        });
+        write_runs(
+            &mut bytes,
+            changed.iter().map(|e| e.color),
+            |bytes, color| bytes.extend([color.0, color.1, color.2]),
+        );
+        match precision {
+            Precision::Quantised => {
+                let velocities = changed.iter().map(|e| quantise(e.velocity));
+                write_runs(&mut bytes, velocities, |bytes, [x, y]| {
+                    write_signed(bytes, x);
+                    write_signed(bytes, y);
+                });
+            }
+            Precision::Exact => {
+                let velocities = changed
+                    .iter()
+                    .map(|e| e.velocity.to_array().map(f32::to_bits));
+                write_runs(&mut bytes, velocities, |bytes, [x, y]| {
+                    bytes.extend(x.to_le_bytes());
+                    bytes.extend(y.to_le_bytes());
+                });
+            }
+        }
+        write_runs(&mut bytes, changed.iter().map(|e| e.rest), |bytes, rest| {
+            bytes.push(rest)
+        });
+        if precision == Precision::Exact {
+            write_runs(&mut bytes, changed.iter().map(|e| e.wframe), write_varint);
+        }
+
+        Self {
+            position: chunk.position.0,
+            bytes,
+        }
+    }
+
+    /// Encodes every cell of `chunk`.
+    pub fn whole(chunk: &Chunk, precision: Precision) -> Self {
+        Self::encode(chunk, None, chunk.size().rect(), precision)
+    }
+
+    /// Takes a delta back from its [bytes](ChunkDelta::as_bytes), which are only checked once
+    /// it is applied.
+    pub fn from_bytes(position: IVec2, bytes: Vec<u8>) -> Self {
+        Self { position, bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// How many cells the delta holds, zero if none changed.
+    pub fn cells(&self) -> usize {
+        self.header().map_or(0, |(_, _, cells, _)| cells)
+    }
+
+    /// Writes the cells into `chunk`, which has to be at the same position and usually holds
+    /// the baseline, without marking them as dirty. Returns the area of the delta. Fails
+    /// without touching the chunk if the delta is malformed or doesn't fit.
+    pub fn apply(&self, chunk: &mut Chunk) -> Result<Rect, SaveError> {
+        if self.position != chunk.position.0 {
+            return Err(malformed("belongs to another chunk"));
+        }
+
+        let (precision, area, cells, mut reader) = self.header()?;
+        if area.min.cmplt(IVec2::ZERO).any()
+            || area.max.cmpgt(IVec2::splat(chunk.size().0)).any()
+            || area.min.cmpgt(area.max).any()
+        {
+            return Err(malformed("reaches outside of the chunk"));
+        }
+
+        let size = area.size();
+        let area_cells = (size.x * size.y) as usize;
+        // Checked before anything is allocated for them, the count comes from the bytes
+        if cells > area_cells {
+            return Err(malformed("has more cells than its area"));
+        }
+        let mut positions = Vec::with_capacity(cells);
+        let (mut index, mut is_changed) = (0, false);
+        while index < area_cells {
+            let run = reader.varint()? as usize;
+            if run > area_cells - index {
+                return Err(malformed("has more cells than its area"));
+            }
+            if is_changed {
+                positions.extend((index..index + run).map(|index| {
+                    let index = index as i32;
+                    LocalCell(area.min + IVec2::new(index % size.x, index / size.x))
+                }));
+            }
+            index += run;
+            is_changed = !is_changed;
+        }
+        if positions.len() != cells {
+            return Err(malformed("doesn't have as many cells as it says"));
+        }
+
+        let kinds = read_runs(&mut reader, cells, |reader| {
+            kind_from_code(reader.varint()?)
+        })?;
+        let colors = read_runs(&mut reader, cells, |reader| {
+            Ok((reader.byte()?, reader.byte()?, reader.byte()?))
+        })?;
+        let velocities = match precision {
+            Precision::Quantised => read_runs(&mut reader, cells, |reader| {
+                let (x, y) = (reader.signed()?, reader.signed()?);
+                Ok(Vec2::new(x as f32, y as f32) * VELOCITY_STEP)
+            })?,
+            Precision::Exact => read_runs(&mut reader, cells, |reader| {
+                let (x, y) = (reader.u32()?, reader.u32()?);
+                Ok(Vec2::new(f32::from_bits(x), f32::from_bits(y)))
+            })?,
+        };
+        let rests = read_runs(&mut reader, cells, |reader| reader.byte())?;
+        let wframes = match precision {
+            Precision::Quantised => vec![0; cells],
+            Precision::Exact => read_runs(&mut reader, cells, |reader| reader.varint())?,
+        };
+        if !reader.bytes.is_empty() {
+            return Err(malformed("has bytes left over"));
+        }
+
+        for (index, position) in positions.into_iter().enumerate() {
+            chunk.update_element(position, |element| {
+                *element = Element {
+                    color: colors[index],
+                    velocity: velocities[index],
+                    kind: kinds[index],
+                    wframe: wframes[index],
+                    rest: rests[index],
+                }
+            });
+        }
+        Ok(area)
+    }
+
+    fn header(&self) -> Result<(Precision, Rect, usize, Reader<'_>), SaveError> {
+        let mut reader = Reader { bytes: &self.bytes };
+        let precision = match reader.byte()? {
+            0 => Precision::Quantised,
+            1 => Precision::Exact,
+            other => return Err(malformed(&format!("has an unknown precision {other}"))),
+        };
+        let mut coordinate = || -> Result<i32, SaveError> {
+            i32::try_from(reader.varint()?).map_err(|_| malformed("reaches outside of the chunk"))
+        };
+        let min = IVec2::new(coordinate()?, coordinate()?);
+        let max = IVec2::new(coordinate()?, coordinate()?);
+        let cells = reader.varint()? as usize;
+        Ok((precision, Rect::new(min, max), cells, reader))
+    }
+}
+
+fn quantise(velocity: Vec2) -> [i64; 2] {
+    (velocity / VELOCITY_STEP)
+        .round()
+        .to_array()
+        .map(|value| value as i64)
+}
+
+/// Built-in kinds by their place in [`ElementKind::ALL`], custom ones after them.
+fn kind_code(kind: ElementKind) -> u64 {
+    match kind {
+        ElementKind::Custom(index) => (ElementKind::ALL.len() + index as usize) as u64,
+        _ => ElementKind::ALL
+            .iter()
+            .position(|other| *other == kind)
+            .unwrap() as u64,
+    }
+}
+
+fn kind_from_code(code: u64) -> Result<ElementKind, SaveError> {
+    match code.checked_sub(ElementKind::ALL.len() as u64) {
+        None => Ok(ElementKind::ALL[code as usize]),
+        Some(index) => u16::try_from(index)
+            .map(ElementKind::Custom)
+            .map_err(|_| malformed(&format!("has an unknown kind {code}"))),
+    }
+}
+
+fn malformed(problem: &str) -> SaveError {
+    SaveError::Format(format!("The chunk delta {problem}"))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_varint(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+/// Writes `values` as runs of equal ones: the length of the run, then the value.
+fn write_runs<T: PartialEq>(
+    bytes: &mut Vec<u8>,
+    values: impl Iterator<Item = T>,
+    mut write: impl FnMut(&mut Vec<u8>, T),
+) {
+    let mut values = values.peekable();
+    while let Some(value) = values.next() {
+        let mut run = 1;
+        while values.next_if_eq(&value).is_some() {
+            run += 1;
+        }
+        write_varint(bytes, run);
+        write(bytes, value);
+    }
+}
+
+/// Reads runs of values until they cover `cells`, which has to be checked against the area of
+/// the delta first as that many values are allocated up front.
+fn read_runs<T: Clone>(
+    reader: &mut Reader,
+    cells: usize,
+    mut read: impl FnMut(&mut Reader) -> Result<T, SaveError>,
+) -> Result<Vec<T>, SaveError> {
+    let mut values = Vec::with_capacity(cells);
+    while values.len() < cells {
+        let run = reader.varint()? as usize;
+        if run == 0 || run > cells - values.len() {
+            return Err(malformed("has a run that doesn't fit"));
+        }
+        let value = read(reader)?;
+        values.extend(std::iter::repeat_n(value, run));
+    }
+    Ok(values)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, SaveError> {
+        let (byte, rest) = self
+            .bytes
+            .split_first()
+            .ok_or_else(|| malformed("ends early"))?;
+        self.bytes = rest;
+        Ok(*byte)
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes([
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+        ]))
+    }
+
+    fn varint(&mut self) -> Result<u64, SaveError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("has a number that is too long"))
+    }
+
+    fn signed(&mut self) -> Result<i64, SaveError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+}
@@ -0,0 +1,135 @@
+//! Explosions, and the radius queries they need to reach across any number of chunks.
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::coordinates::{ChunkPos, LocalCell, WorldCell};
+
+use super::*;
+
+/// Bigger explosions are shrunk to this radius, so a single one can't stall the simulation.
+pub const MAX_EXPLOSION_RADIUS: f32 = 64.0;
+
+/// How much velocity a unit of explosion power gives the elements it pushes.
+const PUSH: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Explosion {
+    /// Continuous world cell coordinates, like the position of a [`Particle`].
+    pub center: Vec2,
+    pub radius: f32,
+    pub power: f32,
+}
+
+impl Sandbox {
+    /// Every cell whose center is within `radius` of `center`, however many chunks that takes.
+    /// Cells of chunks that don't exist or outside of the [bounds](WorldBounds) are skipped.
+    /// Every chunk is locked only once, and the cells come chunk by chunk, in the same order
+    /// every time.
+    pub fn cells_in_radius(&self, center: Vec2, radius: f32) -> Vec<(WorldCell, Element)> {
+        let min = WorldCell((center - radius).floor().as_ivec2());
+        let max = WorldCell((center + radius).ceil().as_ivec2());
+        // Chunk y grows upwards, so the top of the area is in the highest row of chunks
+        let size = self.chunk_size();
+        let (top_left, bottom_right) = (min.chunk(size).0, max.chunk(size).0);
+
+        let mut cells = Vec::new();
+        for chunk_y in (bottom_right.y..=top_left.y).rev() {
+            for chunk_x in top_left.x..=bottom_right.x {
+                let position = ChunkPos::new(chunk_x, chunk_y);
+                let Some(chunk) = self.get_chunk(position) else {
+                    continue;
+                };
+
+                let origin = position.origin(size).0;
+                let from = (min.0 - origin).max(IVec2::ZERO);
+                let to = (max.0 - origin).min(IVec2::splat(size.0 - 1));
+                for y in from.y..=to.y {
+                    for x in from.x..=to.x {
+                        let cell = WorldCell(origin + IVec2::new(x, y));
+                        if !self.settings().bounds.contains(cell) {
+                            continue;
+                        }
+
+                        let distance = (cell.0.as_vec2() + Vec2::splat(0.5)).distance(center);
+                        if distance <= radius {
+                            cells.push((cell, chunk.get_element(LocalCell::new(x, y))));
+                        }
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    /// Blows up everything within `radius` of `center`, with a power that falls off linearly
+    /// towards the edge:
+    ///
+    /// * Explosives (see [`ElementKind::blast`]) are used up and go off at the start of the next
+    ///   tick, so explosions chain.
+    /// * Elements whose [hardness](ElementRegistry::hardness) is below the power that reaches
+    ///   them break into their [rubble](ElementKind::rubble), or are destroyed.
+    /// * Everything else, flying particles included, is pushed away from the center.
+    /// * Rigid bodies in the way are broken apart.
+    pub fn explode(&mut self, center: Vec2, radius: f32, power: f32) {
+        let radius = radius.min(MAX_EXPLOSION_RADIUS);
+        if radius <= 0.0 {
+            return;
+        }
+
+        let push = |position: Vec2| {
+            let offset = position - center;
+            let strength = power * (1.0 - offset.length() / radius);
+            (strength, offset.normalize_or_zero() * strength * PUSH)
+        };
+
+        self.break_bodies(center, radius);
+
+        for (cell, element) in self.cells_in_radius(center, radius) {
+            if element.kind == ElementKind::Air {
+                continue;
+            }
+
+            let cell_center = cell.0.as_vec2() + Vec2::splat(0.5);
+            if let Some((radius, power)) = element.kind.blast() {
+                self.set_element(cell, Element::default());
+                self.explosions.push(Explosion {
+                    center: cell_center,
+                    radius,
+                    power,
+                });
+                continue;
+            }
+
+            let (strength, velocity) = push(cell_center);
+            let replacement = if strength > self.elements.hardness(element.kind) as f32 {
+                match element.kind.rubble() {
+                    Some(rubble) => Element {
+                        velocity,
+                        ..self.elements.element(rubble)
+                    },
+                    None => Element::default(),
+                }
+            } else {
+                Element {
+                    velocity: element.velocity + velocity,
+                    ..element
+                }
+            };
+            self.set_element(cell, replacement);
+        }
+
+        for particle in self.particles.iter_mut() {
+            if particle.position.distance(center) <= radius {
+                particle.velocity += push(particle.position).1;
+            }
+        }
+    }
+
+    /// Sets off the explosions queued since the last tick.
+    pub(super) fn tick_explosions(&mut self) {
+        for explosion in std::mem::take(&mut self.explosions) {
+            self.explode(explosion.center, explosion.radius, explosion.power);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Which way elements fall, for the whole world and inside gravity zones.
+//!
+//! Gravity always points along one of the axes of the grid, so the movement rules of the
+//! elements can simply be turned around: "down" is [`GravityDirection::down`] and elements
+//! spread along [`GravityDirection::side`] when they can't fall.
+
+use std::sync::Arc;
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum GravityDirection {
+    #[default]
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+impl GravityDirection {
+    pub const ALL: [GravityDirection; 4] = [Self::Down, Self::Up, Self::Left, Self::Right];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Down => "Down",
+            Self::Up => "Up",
+            Self::Left => "Left",
+            Self::Right => "Right",
+        }
+    }
+
+    /// The offset of the cell an element falls into, with cell `y` growing downwards.
+    pub const fn down(&self) -> IVec2 {
+        match self {
+            Self::Down => IVec2::new(0, 1),
+            Self::Up => IVec2::new(0, -1),
+            Self::Left => IVec2::new(-1, 0),
+            Self::Right => IVec2::new(1, 0),
+        }
+    }
+
+    /// The axis elements slide and flow along when they can't fall, at a right angle to
+    /// [`GravityDirection::down`].
+    pub const fn side(&self) -> IVec2 {
+        match self {
+            Self::Down | Self::Up => IVec2::new(1, 0),
+            Self::Left | Self::Right => IVec2::new(0, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gravity {
+    pub direction: GravityDirection,
+    /// How much elements speed up every tick while falling, zero for weightlessness.
+    pub strength: f32,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            direction: GravityDirection::Down,
+            strength: 0.5,
+        }
+    }
+}
+
+impl Gravity {
+    pub const ZERO: Gravity = Gravity {
+        direction: GravityDirection::Down,
+        strength: 0.0,
+    };
+
+    pub fn is_zero(&self) -> bool {
+        self.strength == 0.0
+    }
+
+    /// The acceleration of gravity in cells per tick², for things that move freely.
+    pub fn vector(&self) -> Vec2 {
+        self.direction.down().as_vec2() * self.strength
+    }
+}
+
+/// A region of the world with a gravity of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GravityZone {
+    /// World cells, `max` exclusive.
+    pub area: Rect,
+    pub gravity: Gravity,
+}
+
+/// The gravity of the world and of its zones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GravityField {
+    pub world: Gravity,
+    /// Where zones overlap, the one added last wins.
+    pub zones: Vec<GravityZone>,
+}
+
+impl GravityField {
+    pub fn at(&self, cell: WorldCell) -> Gravity {
+        self.zones
+            .iter()
+            .rev()
+            .find(|zone| zone.area.contains(cell.0))
+            .map_or(self.world, |zone| zone.gravity)
+    }
+}
+
+/// Scales the component of `velocity` along an axis of the grid, like its sideways speed.
+pub fn scale_along(velocity: &mut Vec2, axis: IVec2, factor: f32) {
+    if axis.x != 0 {
+        velocity.x *= factor;
+    } else {
+        velocity.y *= factor;
+    }
+}
+
+impl Sandbox {
+    pub fn set_gravity(&mut self, gravity: Gravity) {
+        Arc::make_mut(&mut self.gravity).world = gravity;
+        self.wake_everything();
+    }
+
+    pub fn add_gravity_zone(&mut self, zone: GravityZone) {
+        Arc::make_mut(&mut self.gravity).zones.push(zone);
+        self.wake_everything();
+    }
+
+    /// Removes the gravity zones covering `cell`, returning whether there were any.
+    pub fn remove_gravity_zones_at(&mut self, cell: WorldCell) -> bool {
+        let count = self.gravity.zones.len();
+        Arc::make_mut(&mut self.gravity)
+            .zones
+            .retain(|zone| !zone.area.contains(cell.0));
+        if self.gravity.zones.len() == count {
+            return false;
+        }
+
+        self.wake_everything();
+        true
+    }
+
+    /// Marks every chunk as dirty, so resting elements notice that gravity changed.
+    fn wake_everything(&mut self) {
+        for chunk in self.chunks.values() {
+            chunk.write().mark_dirty_everything();
+        }
+    }
+}
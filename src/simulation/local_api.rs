@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use bevy::math::{IVec2, Vec2};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
@@ -6,62 +8,145 @@ use crate::{
         directions::{self, dir_to_index},
         math,
     },
-    constants::CHUNK_SIZE_I32,
-    coordinates,
+    coordinates::{ChunkPos, LocalCell, WorldCell},
 };
 
 use super::*;
 
+/// How far from the chunk at its center a [`LocalApi`] is sure to reach, in cells. Chunks are
+/// bigger than this, so anything this close is in one of the neighbouring chunks it holds.
+pub const MAX_REACH: i32 = 32;
+
+/// What every [`LocalApi`] of a sandbox shares, whatever chunk it works on.
+#[derive(Clone)]
+pub struct WorldContext {
+    pub elements: Arc<ElementRegistry>,
+    pub gravity: Arc<GravityField>,
+    pub settings: WorldSettings,
+}
+
+/// Access to the chunk at `center` and its neighbours while their elements are updated.
+///
+/// The chunks are locked for as long as the API lives, so looking at a cell doesn't take a lock
+/// every time. Nothing else can get to them meanwhile: drop the API before going through the
+/// [`Sandbox`] again.
 #[allow(unused)]
 pub struct LocalApi {
-    pub center: IVec2,
-    pub chunks: Vec<Option<SharedChunk>>,
-    pub new_chunks: Vec<usize>,
+    pub center: ChunkPos,
+    chunks: [Option<OwnedChunk>; 9],
+    /// Chunks created through the API, handed over to the [`Sandbox`] afterwards.
+    pub new_chunks: Vec<SharedChunk>,
     pub element: (Element, IVec2),
-    pub wframe: u8,
+    pub wframe: u64,
+    pub elements: Arc<ElementRegistry>,
+    pub gravity: Arc<GravityField>,
+    settings: WorldSettings,
+    /// Elements that left the grid as [`Particle`]s, handed over to the [`Sandbox`] afterwards.
+    pub launched: Vec<Particle>,
+    /// Explosions set off through the API, they go off at the start of the next tick.
+    pub explosions: Vec<Explosion>,
+    /// Cells whose kind was changed on purpose through [`LocalApi::set_element`], as the old
+    /// and the new kind, so the [`ElementCensus`] doesn't take them for a bug.
+    pub conversions: Vec<(ElementKind, ElementKind)>,
     rng: StdRng,
 }
 
 impl LocalApi {
     pub fn new(
-        center: IVec2,
-        wframe: u8,
+        center: ChunkPos,
+        wframe: u64,
         element: (Element, IVec2),
-        chunks: Vec<Option<SharedChunk>>,
+        chunks: [Option<SharedChunk>; 9],
+        context: WorldContext,
+        seed: u64,
     ) -> Self {
         Self {
             center,
-            chunks,
+            chunks: chunks.map(|chunk| chunk.map(|chunk| chunk.lock())),
             element,
             wframe,
-            new_chunks: Vec::with_capacity(8),
-            rng: StdRng::from_os_rng(),
+            elements: context.elements,
+            gravity: context.gravity,
+            settings: context.settings,
+            new_chunks: Vec::new(),
+            launched: Vec::new(),
+            explosions: Vec::new(),
+            conversions: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Another API on the same element, for code that needs to own one for a while. It takes
+    /// over the chunks until it is brought back with [`LocalApi::join`].
+    pub fn fork(&mut self) -> Self {
+        Self {
+            center: self.center,
+            chunks: std::mem::take(&mut self.chunks),
+            new_chunks: Vec::new(),
+            element: self.element,
+            wframe: self.wframe,
+            elements: self.elements.clone(),
+            gravity: self.gravity.clone(),
+            settings: self.settings,
+            launched: Vec::new(),
+            explosions: Vec::new(),
+            conversions: Vec::new(),
+            rng: StdRng::seed_from_u64(self.rng.random()),
         }
     }
 
-    fn inner_get_element(&self, chunk_index: usize, element_position: IVec2) -> Element {
+    pub fn join(&mut self, fork: &mut LocalApi) {
+        self.element = fork.element;
+        self.chunks = std::mem::take(&mut fork.chunks);
+        self.new_chunks.append(&mut fork.new_chunks);
+        self.launched.append(&mut fork.launched);
+        self.explosions.append(&mut fork.explosions);
+        self.conversions.append(&mut fork.conversions);
+    }
+
+    fn inner_get_element(&self, chunk_index: usize, element_position: LocalCell) -> Element {
         match &self.chunks[chunk_index] {
-            None => return Element::default(),
-            Some(shared_chunk) => *shared_chunk.read().get_element(element_position),
+            None => Element::default(),
+            Some(chunk) => chunk.get_element(element_position),
         }
     }
 
-    fn inner_chunk_index_and_element_position(&self, relative_position: IVec2) -> (usize, IVec2) {
-        // Treat the (position) like a world position - it is actually an element position that can reference outside positions.
-        // For example: (0, 0) would refer to the element at (0, 0) in the chunk at the center.
-        // ^ (-1, 0) would refer to the element at it's left, on the left chunk.
-        let chunk_relative_position =
-            coordinates::world_to_chunk_position(relative_position.as_vec2());
+    fn inner_get_kind(&self, chunk_index: usize, element_position: LocalCell) -> ElementKind {
+        match &self.chunks[chunk_index] {
+            None => ElementKind::Air,
+            Some(chunk) => chunk.kind(element_position),
+        }
+    }
 
-        let chunk_index =
-            ((chunk_relative_position.x + 1) + (chunk_relative_position.y + 1) * 3) as usize;
+    fn chunk_mut(&mut self, chunk_index: usize) -> Option<&mut Chunk> {
+        self.chunks.get_mut(chunk_index)?.as_deref_mut()
+    }
 
-        let element_position = coordinates::world_to_element_position(relative_position.as_vec2());
-        (chunk_index, element_position)
+    /// Where a position relative to the chunk at the center is, or nothing if it is past the
+    /// neighbouring chunks: those are out of reach, like cells where there is no chunk.
+    fn inner_chunk_index_and_element_position(
+        &self,
+        relative_position: IVec2,
+    ) -> Option<(usize, LocalCell)> {
+        // The (position) is an element position in the chunk at the center that can reference outside positions.
+        // For example: (0, 0) would refer to the element at (0, 0) in the chunk at the center.
+        // ^ (-1, 0) would refer to the element at it's left, on the left chunk.
+        let (chunk_direction, element_position) =
+            LocalCell(relative_position).split(self.settings.chunk_size);
+        if chunk_direction.abs().max_element() > 1 {
+            return None;
+        }
+        Some((dir_to_index(chunk_direction), element_position))
     }
 
     fn chunk_index_exists(&self, chunk_index: usize) -> bool {
-        self.chunks.len() > chunk_index && self.chunks[chunk_index].is_some()
+        self.chunks.get(chunk_index).is_some_and(Option::is_some)
+    }
+
+    /// Whether there is a chunk within reach at `position`.
+    fn chunk_exists(&self, position: IVec2) -> bool {
+        self.inner_chunk_index_and_element_position(position)
+            .is_some_and(|(chunk_index, _)| self.chunk_index_exists(chunk_index))
     }
 
     pub fn random_direction(&mut self) -> i32 {
@@ -72,158 +157,268 @@ impl LocalApi {
         }
     }
 
+    /// The world position of a position relative to the chunk, wrapped around in a
+    /// [wrapping](WorldBounds::Wrapping) world.
+    fn world_cell(&self, position: IVec2) -> WorldCell {
+        let cell = self
+            .center
+            .cell(LocalCell(position), self.settings.chunk_size);
+        self.settings.wrap(cell)
+    }
+
+    /// Whether `position` is inside the [bounds](WorldBounds) of the world. Nothing moves to or
+    /// is set outside of them.
+    pub fn in_bounds(&self, position: IVec2) -> bool {
+        self.settings.bounds.contains(self.world_cell(position))
+    }
+
+    /// The gravity where the held element is.
+    pub fn gravity(&self) -> Gravity {
+        self.gravity.at(self.world_cell(self.element.1))
+    }
+
+    /// True with the given probability, between 0 and 1.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.rng.random_bool(probability.clamp(0.0, 1.0))
+    }
+
     pub fn can_move_to(&self, position: IVec2) -> bool {
-        let (chunk_index, element_position) = self.inner_chunk_index_and_element_position(position);
-        if !self.chunk_index_exists(chunk_index) {
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(position)
+        else {
+            return false;
+        };
+        if !self.chunk_index_exists(chunk_index) || !self.in_bounds(position) {
             return false;
         }
 
-        let dest = self.inner_get_element(chunk_index, element_position);
-        dest.kind == ElementKind::Air || dest.kind.density() < self.element.0.kind.density()
+        let dest = self.inner_get_kind(chunk_index, element_position);
+        dest == ElementKind::Air
+            || self.elements.density(dest) < self.elements.density(self.element.0.kind)
     }
 
+    /// The element at `position`, or air where there is no chunk yet or it is out of reach.
     pub fn get_element(&self, position: IVec2) -> Element {
-        let (chunk_index, element_position) = self.inner_chunk_index_and_element_position(position);
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(position)
+        else {
+            return Element::default();
+        };
         if !self.chunk_index_exists(chunk_index) {
-            return Element {
-                wframe: 0,
-                color: (245, 226, 24),
-                velocity: Vec2::ZERO,
-                kind: ElementKind::Sand,
-            };
+            return Element::default();
         }
 
         self.inner_get_element(chunk_index, element_position)
     }
 
-    pub fn set_wframe(&mut self, wframe: u8) {
-        let (chunk_index, element_position) =
-            self.inner_chunk_index_and_element_position(self.element.1);
-        if !self.chunk_index_exists(chunk_index) {
+    pub fn set_wframe(&mut self, wframe: u64) {
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(self.element.1)
+        else {
             return;
+        };
+        if let Some(chunk) = self.chunk_mut(chunk_index) {
+            chunk.set_wframe(element_position, wframe);
         }
-
-        self.chunks[chunk_index]
-            .as_ref()
-            .unwrap()
-            .write()
-            .set_wframe(element_position, wframe);
     }
 
     pub fn swap_elements(&mut self, source: IVec2, target: IVec2, source_element: Option<Element>) {
-        let (source_chunk_index, ..) = self.inner_chunk_index_and_element_position(source);
-        let (target_chunk_index, ..) = self.inner_chunk_index_and_element_position(target);
-
-        if !self.chunk_index_exists(source_chunk_index) {
-            return;
-        }
-
-        if !self.chunk_index_exists(target_chunk_index) {
+        // Callers only move elements into cells they checked, so a missing chunk here would
+        // silently drop the element on one side of the swap.
+        let both_exist = self.chunk_exists(source) && self.chunk_exists(target);
+        debug_assert!(
+            both_exist,
+            "swapping {source} with {target} reaches a missing chunk"
+        );
+        if !both_exist || !self.in_bounds(source) || !self.in_bounds(target) {
             return;
         }
 
         let source_element = source_element.unwrap_or(self.get_element(source));
         let target_element = self.get_element(target);
 
-        self.set_element(source, target_element);
-        self.set_element(target, source_element);
+        self.put_element(source, target_element);
+        self.put_element(target, source_element);
     }
 
+    /// Puts `element` at `position`, creating its chunk if there is none. A change of kind is
+    /// recorded in [`LocalApi::conversions`]: moving elements around goes through
+    /// [`LocalApi::swap_elements`] instead.
     pub fn set_element(&mut self, position: IVec2, element: Element) {
-        let (chunk_index, element_position) = self.inner_chunk_index_and_element_position(position);
-        if !self.chunk_index_exists(chunk_index) {
-            let chunk_world_position =
-                self.center + coordinates::world_to_chunk_position(position.as_vec2());
+        if !self.in_bounds(position)
+            || self
+                .inner_chunk_index_and_element_position(position)
+                .is_none()
+        {
+            return;
+        }
 
-            let chunk = SharedChunk::new(Chunk::new(chunk_world_position));
-            self.new_chunks.push(chunk_index);
+        let previous = self.get_element(position).kind;
+        if previous != element.kind {
+            self.conversions.push((previous, element.kind));
+        }
+        self.put_element(position, element);
+    }
 
-            self.chunks[chunk_index] = Some(chunk);
+    fn put_element(&mut self, position: IVec2, element: Element) {
+        if !self.in_bounds(position) {
+            return;
+        }
+
+        let size = self.settings.chunk_size;
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(position)
+        else {
+            return;
+        };
+        if !self.chunk_index_exists(chunk_index) {
+            let (chunk_direction, _) = LocalCell(position).split(size);
+            let position = self
+                .settings
+                .wrap_chunk(self.center.offset(chunk_direction));
+            let chunk = self.settings.new_chunk(position, &self.elements);
+            let chunk = SharedChunk::new(chunk);
+            self.chunks[chunk_index] = Some(chunk.lock());
+            self.new_chunks.push(chunk);
         }
 
         // Mark neighboring chunks as dirty when setting elements on the edge
-        if element_position.x == 0 {
-            if let Some(chunk) = self.chunks[dir_to_index(directions::VEC_LEFT)].as_ref() {
-                chunk
-                    .write()
-                    .mark_point_dirty(IVec2::new(CHUNK_SIZE_I32 - 1, element_position.y));
+        if element_position.0.x == 0 {
+            if let Some(chunk) = self.chunk_mut(dir_to_index(directions::VEC_LEFT)) {
+                chunk.mark_point_dirty(LocalCell::new(size.0 - 1, element_position.0.y));
             }
-        } else if element_position.x == CHUNK_SIZE_I32 - 1 {
-            if let Some(chunk) = self.chunks[dir_to_index(directions::VEC_RIGHT)].as_ref() {
-                chunk
-                    .write()
-                    .mark_point_dirty(IVec2::new(0, element_position.y));
+        } else if element_position.0.x == size.0 - 1 {
+            if let Some(chunk) = self.chunk_mut(dir_to_index(directions::VEC_RIGHT)) {
+                chunk.mark_point_dirty(LocalCell::new(0, element_position.0.y));
             }
         }
 
-        if element_position.y == 0 {
-            if let Some(chunk) = self.chunks[dir_to_index(directions::VEC_UP)].as_ref() {
-                chunk
-                    .write()
-                    .mark_point_dirty(IVec2::new(element_position.x, CHUNK_SIZE_I32 - 1));
+        if element_position.0.y == 0 {
+            if let Some(chunk) = self.chunk_mut(dir_to_index(directions::VEC_UP)) {
+                chunk.mark_point_dirty(LocalCell::new(element_position.0.x, size.0 - 1));
             }
-        } else if element_position.y == CHUNK_SIZE_I32 - 1 {
-            if let Some(chunk) = self.chunks[dir_to_index(directions::VEC_DOWN)].as_ref() {
-                chunk
-                    .write()
-                    .mark_point_dirty(IVec2::new(element_position.x, 0));
+        } else if element_position.0.y == size.0 - 1 {
+            if let Some(chunk) = self.chunk_mut(dir_to_index(directions::VEC_DOWN)) {
+                chunk.mark_point_dirty(LocalCell::new(element_position.0.x, 0));
             }
         }
 
-        self.chunks[chunk_index]
-            .as_ref()
-            .unwrap()
-            .write()
-            .set_element(element_position, element);
+        if let Some(chunk) = self.chunk_mut(chunk_index) {
+            chunk.set_element(element_position, element);
+        }
     }
 
-    pub fn mark_element_dirty(&mut self) {
-        let (chunk_index, element_position) =
-            self.inner_chunk_index_and_element_position(self.element.1);
-        if !self.chunk_index_exists(chunk_index) {
+    /// Sets an element like [`LocalApi::set_element`], but as a change of kind rather than a
+    /// move: the behaviour hooks of the old and new element are run.
+    pub fn replace_element(&mut self, position: IVec2, element: Element) {
+        if !self.in_bounds(position) {
+            return;
+        }
+
+        let previous = self.get_element(position);
+        if previous.kind == element.kind {
+            self.set_element(position, element);
             return;
         }
 
-        self.chunks[chunk_index]
-            .as_ref()
-            .unwrap()
-            .write()
-            .mark_point_dirty(element_position);
+        self.run_hook(position, previous, |behavior, api| {
+            behavior.on_destroyed(api)
+        });
+        self.set_element(position, element);
+        self.run_hook(position, element, |behavior, api| behavior.on_placed(api));
     }
 
-    pub fn update_element(&mut self, callback: impl FnOnce(&mut Element)) {
-        let (chunk_index, element_position) =
-            self.inner_chunk_index_and_element_position(self.element.1);
-        if !self.chunk_index_exists(chunk_index) {
+    /// Runs a hook of the behaviour of `element` at `position`, as if it was the element held by
+    /// the API.
+    fn run_hook(
+        &mut self,
+        position: IVec2,
+        element: Element,
+        hook: impl FnOnce(&dyn ElementBehavior, &mut LocalApi),
+    ) {
+        let elements = self.elements.clone();
+        let Some(behavior) = elements.behavior(element.kind) else {
             return;
-        }
+        };
+
+        let held = std::mem::replace(&mut self.element, (element, position));
+        hook(behavior.as_ref(), self);
+        self.element = held;
+    }
 
-        let mut chunk = self.chunks[chunk_index].as_ref().unwrap().write();
-        let element = chunk.get_element_mut(element_position);
-        element.wframe = self.wframe;
+    pub fn mark_element_dirty(&mut self) {
+        self.mark_dirty(self.element.1);
+    }
 
-        callback(element);
+    /// Makes sure the element at `position` gets updated next tick, like an element next to one
+    /// that moved.
+    pub fn mark_dirty(&mut self, position: IVec2) {
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(position)
+        else {
+            return;
+        };
+        if let Some(chunk) = self.chunk_mut(chunk_index) {
+            chunk.mark_point_dirty(element_position);
+        }
+    }
 
-        self.element.0 = *element;
+    pub fn update_element(&mut self, callback: impl FnOnce(&mut Element)) {
+        let Some((chunk_index, element_position)) =
+            self.inner_chunk_index_and_element_position(self.element.1)
+        else {
+            return;
+        };
+        let wframe = self.wframe;
+        if let Some(chunk) = self.chunk_mut(chunk_index) {
+            self.element.0 = chunk.update_element(element_position, |element| {
+                element.wframe = wframe;
+                callback(element);
+            });
+        }
     }
 
+    /// Speeds up the held element, up to [`MAX_GRID_SPEED`]. Elements that are already faster,
+    /// like the ones thrown by an explosion, keep their speed. Keeps the element awake, unless
+    /// it is [resting](Element::is_resting) and pushed against something it can't move into.
     pub fn accelerate(&mut self, x: f32, y: f32) {
+        let limit = |speed: f32, acceleration: f32| {
+            let max = MAX_GRID_SPEED.max(speed.abs());
+            (speed + acceleration).clamp(-max, max)
+        };
+
         self.update_element(|element| {
-            element.velocity.x = (element.velocity.x + x).clamp(-10.0, 10.0);
-            element.velocity.y = (element.velocity.y + y).clamp(-10.0, 10.0);
+            element.velocity.x = limit(element.velocity.x, x);
+            element.velocity.y = limit(element.velocity.y, y);
         });
 
+        // A resting element pushed against what blocks it stays asleep
+        if self.element.0.is_resting() {
+            let step =
+                |acceleration: f32| (acceleration > 0.0) as i32 - (acceleration < 0.0) as i32;
+            let ahead = self.element.1 + IVec2::new(step(x), step(y));
+            if !self.can_move_to(ahead) {
+                return;
+            }
+            self.update_element(|element| element.rest = 0);
+        }
+
         self.mark_element_dirty();
     }
 
+    /// Moves the held element along its velocity, stopping at the first cell it can't move to.
+    /// Elements faster than [`MAX_GRID_SPEED`] are launched as particles instead.
     pub fn move_element(&mut self) {
-        let (chunk_index, element_position) =
-            self.inner_chunk_index_and_element_position(self.element.1);
-        if !self.chunk_index_exists(chunk_index) {
+        if !self.chunk_exists(self.element.1) {
+            return;
+        }
+
+        if self.element.0.velocity.abs().max_element() > MAX_GRID_SPEED {
+            self.launch();
             return;
         }
 
-        let start_position = element_position;
+        let start_position = self.element.1;
         let end_position = start_position + self.element.0.velocity.as_ivec2();
 
         if start_position == end_position {
@@ -232,6 +427,7 @@ impl LocalApi {
 
         let iter = math::GridLineIterator::new(start_position, end_position);
         let mut destination = start_position;
+        let mut obstacle = None;
 
         for pos in iter {
             if pos == start_position {
@@ -241,22 +437,28 @@ impl LocalApi {
             if self.can_move_to(pos) {
                 destination = pos;
             } else {
+                obstacle = Some(pos);
                 break;
             }
         }
 
         // Move the element
         if destination != start_position {
+            self.update_element(|element| element.rest = 0);
             self.swap_elements(start_position, destination, Some(self.element.0));
             self.element.1 = destination;
 
-            // If moved along the X axis and not the Y axis, apply some friction
-            if destination.x != start_position.x && destination.y == start_position.y {
-                self.update_element(|element| element.velocity.x *= 0.8);
+            // If moved sideways and not along gravity, apply some friction
+            let gravity = self.gravity();
+            let (down, side) = (gravity.direction.down(), gravity.direction.side());
+            let moved = destination - start_position;
+            if !gravity.is_zero() && moved.dot(down) == 0 && moved.dot(side) != 0 {
+                self.update_element(|element| scale_along(&mut element.velocity, side, 0.8));
             }
         } else {
             // Decelerate the element a lot with a little bit of bouncing
             self.update_element(|element| {
+                element.rest = element.rest.saturating_add(1);
                 element.velocity *= -0.1;
 
                 if element.velocity.length() < f32::EPSILON {
@@ -264,5 +466,48 @@ impl LocalApi {
                 }
             });
         }
+
+        if let Some(obstacle) = obstacle {
+            self.contact(obstacle);
+        }
+    }
+
+    /// Takes the held element off the grid and lets it fly as a [`Particle`] with its velocity.
+    pub fn launch(&mut self) {
+        let (element, position) = self.element;
+        if element.kind == ElementKind::Air {
+            return;
+        }
+
+        // Still counted as a particle, so not a conversion
+        self.put_element(position, Element::default());
+        self.launched.push(Particle {
+            position: self.world_cell(position).0.as_vec2() + Vec2::splat(0.5),
+            velocity: element.velocity,
+            element,
+        });
+        self.element.0 = Element::default();
+    }
+
+    /// Sets off an explosion centered on the cell at `position`. It can reach far beyond the
+    /// chunks of the API, so it goes off at the start of the next tick, see [`Sandbox::explode`].
+    pub fn explode(&mut self, position: IVec2, radius: f32, power: f32) {
+        self.explosions.push(Explosion {
+            center: self.world_cell(position).0.as_vec2() + Vec2::splat(0.5),
+            radius,
+            power,
+        });
+    }
+
+    fn contact(&mut self, other: IVec2) {
+        // Running into the end of the loaded world isn't a contact
+        if !self.chunk_exists(other) {
+            return;
+        }
+
+        let elements = self.elements.clone();
+        if let Some(behavior) = elements.behavior(self.element.0.kind) {
+            behavior.on_contact(self, other);
+        }
     }
 }
@@ -1,8 +1,45 @@
+mod behavior;
+mod census;
 mod chunk;
+mod delta;
+mod emitter;
+mod explosion;
+mod gravity;
 mod local_api;
+mod network;
+mod particle;
 pub mod plugin;
+mod registry;
+mod remote;
+mod replay;
+mod rewind;
+mod rigid_body;
 mod sandbox;
+mod save;
+pub mod scripting;
+mod snapshot;
+mod world;
 
+pub use behavior::*;
+pub use census::*;
 pub use chunk::*;
-pub use local_api::LocalApi;
+pub use delta::*;
+pub use emitter::*;
+pub use explosion::*;
+pub use gravity::*;
+pub use local_api::{LocalApi, WorldContext, MAX_REACH};
+pub use network::*;
+pub use particle::*;
+pub use registry::*;
+pub use remote::*;
+pub use replay::*;
+pub use rewind::*;
+pub use rigid_body::*;
 pub use sandbox::Sandbox;
+pub use save::*;
+pub use scripting::ScriptError;
+pub use snapshot::*;
+pub use world::*;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,449 @@
+//! Sharing one world between several instances of the game over TCP, on one machine or a LAN.
+//!
+//! One instance hosts: it runs the simulation as usual and applies the [`Edit`]s its clients
+//! send it along with its own. After every frame it sends the clients [deltas](ChunkDelta) of
+//! the cells that changed, which are all inside the dirty rects of their chunks. Clients don't
+//! simulate at all, they show what the host sends them and forward what their user does.
+//!
+//! Messages are [bincode](https://docs.rs/bincode) encoded and prefixed with their length.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::{
+    ecs::resource::Resource,
+    log::info,
+    math::{IVec2, Vec2},
+};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{common::Rect, coordinates::ChunkPos};
+
+use super::*;
+
+/// The port hosts listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Messages of the host longer than this are taken for garbage, it fits the welcome of a world
+/// of a few thousand chunks.
+const MAX_HOST_MESSAGE_LEN: usize = 64 << 20;
+/// Edits of the clients longer than this are taken for garbage, it fits loading a world of
+/// about a thousand chunks.
+const MAX_EDIT_LEN: usize = 16 << 20;
+
+/// How long the host waits on a client that doesn't take what it sends before dropping it.
+const SEND_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HostMessage {
+    /// The whole world, sent to a client when it joins.
+    Welcome {
+        /// The names of the registered kinds, which the client needs too.
+        kinds: Vec<String>,
+        chunk_size: i32,
+        update: WorldUpdate,
+    },
+    Update(WorldUpdate),
+}
+
+/// What changed in the world since the last update, see [`Sandbox::apply_update`].
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct WorldUpdate {
+    pub wframe: u64,
+    pub active: bool,
+    /// The gravity of the world and its zones, which clients draw.
+    pub gravity: GravityField,
+    /// Every emitter, which clients draw too.
+    pub emitters: Vec<Emitter>,
+    /// [Quantised](Precision::Quantised), against what the clients got before.
+    pub chunks: Vec<ChunkDelta>,
+    /// The chunks that are gone, like after loading a world.
+    pub removed: Vec<IVec2>,
+    /// Where every flying particle is, with its colour.
+    pub particles: Vec<(Vec2, (u8, u8, u8))>,
+}
+
+/// Whether this instance hosts the world or joined one.
+#[derive(Debug, Resource)]
+pub enum Network {
+    Host(Host),
+    Client(Client),
+}
+
+/// Runs the world for its clients, see the [module docs](self).
+#[derive(Debug)]
+pub struct Host {
+    address: SocketAddr,
+    // Locked to share the host between systems, only ever taken through `&mut self`
+    joined: Mutex<Receiver<TcpStream>>,
+    edits: Mutex<Receiver<Edit>>,
+    clients: Vec<TcpStream>,
+    /// The chunks of the sandbox the clients know, with what they last got of them as the
+    /// baseline of the next deltas. Chunks that were replaced since, by loading or rewinding,
+    /// are sent whole.
+    known: HashMap<ChunkPos, (SharedChunk, Chunk)>,
+    /// The tick the clients last got, and whether the world was edited since.
+    sent_wframe: u64,
+    edited: bool,
+}
+
+impl Host {
+    /// Starts listening for clients in the background.
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let (joined_sender, joined) = mpsc::channel();
+        let (edit_sender, edits) = mpsc::channel();
+        thread::spawn(move || accept_clients(listener, joined_sender, edit_sender));
+
+        Ok(Self {
+            address,
+            joined: Mutex::new(joined),
+            edits: Mutex::new(edits),
+            clients: Vec::new(),
+            known: HashMap::new(),
+            sent_wframe: 0,
+            edited: false,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Sends the whole world to the clients that joined since the last call, and returns the
+    /// edits the clients sent in the meantime, in the order they arrived.
+    pub fn receive(&mut self, sandbox: &Sandbox) -> Vec<Edit> {
+        let joined = self.joined.get_mut().try_iter().collect::<Vec<_>>();
+        if !joined.is_empty() {
+            // Brings the baselines up to date, so whoever joins gets the same as the others
+            self.edited = true;
+            self.send_changes(sandbox);
+
+            let mut positions = self.known.keys().copied().collect::<Vec<_>>();
+            positions.sort_by_key(|position| (position.0.y, position.0.x));
+            let chunks = positions
+                .into_iter()
+                .map(|position| ChunkDelta::whole(&self.known[&position].1, Precision::Quantised))
+                .collect();
+
+            let elements = &sandbox.elements;
+            let welcome = HostMessage::Welcome {
+                kinds: elements
+                    .kinds()
+                    .map(|kind| elements.name(kind).to_string())
+                    .collect(),
+                chunk_size: sandbox.chunk_size().0,
+                update: WorldUpdate::new(sandbox, chunks, Vec::new()),
+            };
+            let bytes = encode(&welcome);
+            for mut stream in joined {
+                match write_frame(&mut stream, &bytes) {
+                    Ok(()) => {
+                        info!("{} joined", peer(&stream));
+                        self.clients.push(stream);
+                    }
+                    Err(err) => info!("{} couldn't join: {err}", peer(&stream)),
+                }
+            }
+        }
+
+        self.edits.get_mut().try_iter().collect()
+    }
+
+    /// Makes the next [`Host::send_changes`] look for changes even if no tick ran since, call
+    /// it after applying edits.
+    pub fn mark_edited(&mut self) {
+        self.edited = true;
+    }
+
+    /// Sends the clients everything that changed since the last call. Every change is within
+    /// the dirty rects of its chunk until the chunk ticks twice, so this has to be called after
+    /// every tick.
+    pub fn send_changes(&mut self, sandbox: &Sandbox) {
+        let changed = self.edited || sandbox.wframe != self.sent_wframe;
+        let update = self.update(sandbox, |chunk| {
+            if !changed {
+                return None;
+            }
+            let dirty = chunk.current_dirty_rect.union(&chunk.next_dirty_rect);
+            (!dirty.is_empty()).then_some(dirty)
+        });
+        self.sent_wframe = sandbox.wframe;
+        self.edited = false;
+        if !changed && update.chunks.is_empty() && update.removed.is_empty() {
+            return;
+        }
+
+        let bytes = encode(&HostMessage::Update(update));
+        self.clients
+            .retain_mut(|stream| match write_frame(stream, &bytes) {
+                Ok(()) => true,
+                Err(err) => {
+                    info!("{} left: {err}", peer(stream));
+                    false
+                }
+            });
+    }
+
+    /// The update with the chunks the clients don't know yet, and the cells of the others
+    /// inside their `changed` area that differ from the baseline.
+    fn update(
+        &mut self,
+        sandbox: &Sandbox,
+        changed: impl Fn(&Chunk) -> Option<Rect>,
+    ) -> WorldUpdate {
+        let mut removed = Vec::new();
+        self.known.retain(|position, _| {
+            let kept = sandbox.chunks.contains_key(position);
+            if !kept {
+                removed.push(position.0);
+            }
+            kept
+        });
+
+        let mut positions = sandbox.chunks.keys().copied().collect::<Vec<_>>();
+        positions.sort_by_key(|position| (position.0.y, position.0.x));
+
+        let mut chunks = Vec::new();
+        for position in positions {
+            let shared = &sandbox.chunks[&position];
+            let chunk = shared.read();
+            let delta = match self.known.get_mut(&position) {
+                Some((known, baseline)) if known.ptr_eq(shared) => changed(&chunk)
+                    .map(|area| {
+                        ChunkDelta::encode(&chunk, Some(baseline), area, Precision::Quantised)
+                    })
+                    .filter(|delta| delta.cells() > 0),
+                _ => {
+                    let baseline = Chunk::new(position, chunk.size());
+                    self.known.insert(position, (shared.clone(), baseline));
+                    Some(ChunkDelta::whole(&chunk, Precision::Quantised))
+                }
+            };
+
+            if let Some(delta) = delta {
+                let (_, baseline) = self.known.get_mut(&position).unwrap();
+                delta
+                    .apply(baseline)
+                    .expect("deltas always apply to their baseline");
+                chunks.push(delta);
+            }
+        }
+
+        WorldUpdate::new(sandbox, chunks, removed)
+    }
+}
+
+impl WorldUpdate {
+    fn new(sandbox: &Sandbox, chunks: Vec<ChunkDelta>, removed: Vec<IVec2>) -> Self {
+        Self {
+            wframe: sandbox.wframe,
+            active: sandbox.active,
+            gravity: (*sandbox.gravity).clone(),
+            emitters: sandbox.emitters.clone(),
+            chunks,
+            removed,
+            particles: sandbox
+                .particles
+                .iter()
+                .map(|particle| (particle.position, particle.element.color))
+                .collect(),
+        }
+    }
+}
+
+fn accept_clients(listener: TcpListener, joined: Sender<TcpStream>, edits: Sender<Edit>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let reader = stream.try_clone().and_then(|reader| {
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(SEND_TIMEOUT))?;
+            Ok(reader)
+        });
+        let Ok(mut reader) = reader else {
+            continue;
+        };
+
+        let edits = edits.clone();
+        thread::spawn(move || {
+            while let Ok(edit) = receive::<Edit>(&mut reader, MAX_EDIT_LEN) {
+                if edits.send(edit).is_err() {
+                    break;
+                }
+            }
+        });
+        if joined.send(stream).is_err() {
+            break;
+        }
+    }
+}
+
+/// Shows the world of a [`Host`], see the [module docs](self).
+#[derive(Debug)]
+pub struct Client {
+    stream: TcpStream,
+    messages: Mutex<Receiver<io::Result<HostMessage>>>,
+}
+
+impl Client {
+    /// Joins the host at `address`, its world shows up with the first [`Client::receive`].
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || loop {
+            let message = receive::<HostMessage>(&mut reader, MAX_HOST_MESSAGE_LEN);
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Self {
+            stream,
+            messages: Mutex::new(messages),
+        })
+    }
+
+    /// Sends an edit for the host to apply.
+    pub fn send(&mut self, edit: &Edit) -> io::Result<()> {
+        write_frame(&mut self.stream, &encode(edit))
+    }
+
+    /// Applies whatever the host sent since the last call. Fails once the host is gone, or if
+    /// its world can't be shown here.
+    pub fn receive(&mut self, sandbox: &mut Sandbox) -> io::Result<()> {
+        // The cells of the last updates were drawn by now
+        for chunk in sandbox.chunks.values() {
+            let mut chunk = chunk.write();
+            chunk.current_dirty_rect.clear();
+            chunk.next_dirty_rect.clear();
+        }
+
+        for message in self.messages.get_mut().try_iter() {
+            let shown = match message? {
+                HostMessage::Welcome {
+                    kinds,
+                    chunk_size,
+                    update,
+                } => {
+                    let elements = &sandbox.elements;
+                    if !elements
+                        .kinds()
+                        .map(|kind| elements.name(kind))
+                        .eq(kinds.iter().map(String::as_str))
+                    {
+                        return Err(invalid("The host has other elements"));
+                    }
+                    if chunk_size != sandbox.chunk_size().0 {
+                        return Err(invalid("The host has chunks of another size"));
+                    }
+
+                    sandbox.chunks.clear();
+                    sandbox.fresh_chunks.clear();
+                    sandbox.apply_update(&update)
+                }
+                HostMessage::Update(update) => sandbox.apply_update(&update),
+            };
+            shown.map_err(|err| invalid(&err.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Sandbox {
+    /// Shows an update from the host, see [`Client`]. The updated cells are left in the current
+    /// dirty rects of their chunks, to be drawn. Fails on the first malformed delta.
+    pub fn apply_update(&mut self, update: &WorldUpdate) -> Result<(), SaveError> {
+        for position in update.removed.iter() {
+            self.chunks.remove(&ChunkPos(*position));
+        }
+
+        let size = self.chunk_size();
+        for delta in update.chunks.iter() {
+            let position = ChunkPos(delta.position);
+            let shared = match self.chunks.get(&position) {
+                Some(shared) => shared.clone(),
+                None => self.add_chunk(Chunk::new(position, size)),
+            };
+            let mut chunk = shared.write();
+            let area = delta.apply(&mut chunk)?;
+            chunk.current_dirty_rect = chunk.current_dirty_rect.union(&area);
+        }
+
+        self.wframe = update.wframe;
+        self.active = update.active;
+        if *self.gravity != update.gravity {
+            self.gravity = Arc::new(update.gravity.clone());
+        }
+        self.emitters = update.emitters.clone();
+        self.particles = update
+            .particles
+            .iter()
+            .map(|(position, color)| Particle {
+                position: *position,
+                velocity: Vec2::ZERO,
+                element: Element {
+                    color: *color,
+                    ..Default::default()
+                },
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+fn encode(message: &impl Serialize) -> Vec<u8> {
+    bincode::serialize(message).expect("messages always serialize")
+}
+
+fn write_frame(stream: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    stream.write_all(bytes)
+}
+
+/// Reads a message of at most `max_len` bytes. The bytes are only allocated as they come in,
+/// so a peer can't make us allocate what it claims it will send.
+fn receive<T: DeserializeOwned>(stream: &mut impl Read, max_len: usize) -> io::Result<T> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(invalid("Message too long"));
+    }
+
+    let mut bytes = Vec::new();
+    stream.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    bincode::deserialize(&bytes).map_err(|err| invalid(&err.to_string()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn peer(stream: &TcpStream) -> String {
+    stream
+        .peer_addr()
+        .map_or_else(|_| "A client".to_string(), |address| address.to_string())
+}
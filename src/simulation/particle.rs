@@ -0,0 +1,124 @@
+//! Elements flying off the grid.
+//!
+//! A cell can only move along grid lines, which looks blocky for an element thrown out of a
+//! splash or an explosion and can't carry it over an obstacle in an arc. So an element faster
+//! than [`MAX_GRID_SPEED`] leaves the grid and flies as a [`Particle`], with a continuous
+//! position and ballistic physics, until it hits something and merges back into the nearest
+//! free cell.
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::coordinates::WorldCell;
+
+use super::*;
+
+/// The fastest an element moves through the grid, in cells per tick along either axis.
+pub const MAX_GRID_SPEED: f32 = 10.0;
+
+const DRAG: f32 = 0.99;
+/// The most a particle moves between two checks of the grid, so it can't skip over a cell.
+const MAX_STEP: f32 = 0.5;
+/// How far from where it hit something a particle looks for a free cell to land in.
+const LANDING_REACH: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Particle {
+    /// Continuous world cell coordinates, the cell `(x, y)` covers `x..x + 1` and `y..y + 1`.
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub element: Element,
+}
+
+impl Particle {
+    pub fn cell(&self) -> WorldCell {
+        WorldCell(self.position.floor().as_ivec2())
+    }
+}
+
+impl Sandbox {
+    /// Throws the element at `cell` off the grid. Returns whether there was one to throw.
+    pub fn launch(&mut self, cell: WorldCell, velocity: Vec2) -> bool {
+        let Some(element) = self
+            .get_element(cell)
+            .filter(|element| element.kind != ElementKind::Air)
+        else {
+            return false;
+        };
+
+        self.write_element(cell, Element::default());
+        self.throw(cell, element, velocity);
+        true
+    }
+
+    /// Throws an element that isn't in the grid from the middle of `cell`.
+    pub fn throw(&mut self, cell: WorldCell, element: Element, velocity: Vec2) {
+        self.particles.push(Particle {
+            position: cell.0.as_vec2() + Vec2::splat(0.5),
+            velocity,
+            element,
+        });
+    }
+
+    pub(super) fn tick_particles(&mut self) {
+        for mut particle in std::mem::take(&mut self.particles) {
+            particle.velocity += self.gravity.at(particle.cell()).vector();
+            particle.velocity *= DRAG;
+
+            let steps = (particle.velocity.length() / MAX_STEP).ceil().max(1.0) as usize;
+            let step = particle.velocity / steps as f32;
+
+            let mut hit = false;
+            for _ in 0..steps {
+                let next = particle.position + step;
+                let cell = WorldCell(next.floor().as_ivec2());
+                if !self.is_free(cell) {
+                    hit = true;
+                    break;
+                }
+                particle.position = next;
+            }
+            // Past the side of a wrapping world, it flies on from the other side
+            particle.position = self.settings().wrap_point(particle.position);
+
+            if hit {
+                if self.land(&particle) {
+                    continue;
+                }
+                particle.velocity = Vec2::ZERO;
+            }
+
+            self.particles.push(particle);
+        }
+    }
+
+    fn is_free(&self, cell: WorldCell) -> bool {
+        self.get_element(cell)
+            .is_some_and(|element| element.kind == ElementKind::Air)
+    }
+
+    /// Puts a particle back into the nearest free cell. Returns false if there is none nearby,
+    /// then it stays in the air and tries again next tick.
+    fn land(&mut self, particle: &Particle) -> bool {
+        let center = particle.cell().0;
+        let landing = (0..=LANDING_REACH)
+            .flat_map(|radius| rigid_body::ring(center, radius))
+            .find(|cell| self.is_free(*cell));
+
+        let Some(landing) = landing else {
+            return false;
+        };
+
+        let velocity = particle
+            .velocity
+            .clamp(Vec2::splat(-MAX_GRID_SPEED), Vec2::splat(MAX_GRID_SPEED));
+        self.write_element(
+            landing,
+            Element {
+                velocity,
+                ..particle.element
+            },
+        );
+        true
+    }
+}
@@ -1,4 +1,10 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use bevy::{
     image::ImageSampler,
@@ -11,16 +17,28 @@ use bevy::{
 use bevy_egui::EguiContexts;
 
 use crate::{
-    common::math,
-    constants::{CHUNK_SIZE, RESOLUTION},
-    coordinates::{chunk_to_world_position, world_to_chunk_position, world_to_element_position},
+    controls::{Action, ActionState},
+    coordinates::{ChunkPos, ScreenPos},
 };
 
 use super::*;
 
+pub use crate::coordinates::Resolution;
+
+/// The explosion set off by [`Action::Detonate`], about as big as a stick of TNT.
+const DETONATION_RADIUS: f32 = 10.0;
+const DETONATION_POWER: f32 = 150.0;
+
 #[derive(Debug, Clone, PartialEq, Eq, Component)]
 pub struct WorldChunk {
-    pub position: IVec2,
+    pub position: ChunkPos,
+}
+
+/// Draws the chunk it's a child of once more, `offset` world widths to the side, so the
+/// camera sees across the seam of a [wrapping](WorldBounds::Wrapping) world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct WrappedCopy {
+    pub offset: i32,
 }
 
 #[derive(Component)]
@@ -31,26 +49,115 @@ pub struct MainCameraState {
     pub velocity: Vec2,
 }
 
-#[derive(Resource)]
-pub struct Resolution(pub f32);
+/// Draws one of the [`Particle`]s of the sandbox on top of the chunks.
+#[derive(Component)]
+pub struct ParticleSprite;
+
+/// A change the user made to the world. It is applied by [`apply_edits`], or sent to the host
+/// when playing on someone else's [network](Network).
+#[derive(Debug, Clone, Event)]
+pub struct UserEdit(pub Edit);
 
 #[derive(Resource)]
-pub struct LastMousePosition(pub IVec2);
+pub struct LastMousePosition(pub ScreenPos);
 
 #[derive(Resource)]
 pub struct SelectedElement(pub ElementKind);
 
+/// While set, the left mouse button places this emitter instead of the selected element.
+#[derive(Default, Resource)]
+pub struct SelectedEmitter(pub Option<EmitterPreset>);
+
+/// When each element script was last loaded, to reload the ones that changed.
+#[derive(Debug, Default, Resource)]
+pub struct ScriptWatcher {
+    pub modified: HashMap<PathBuf, SystemTime>,
+}
+
+/// Lets plugins add elements while the app is built, e.g. to ship a pack of elements as a crate:
+///
+/// ```ignore
+/// app.register_element(
+///     CustomElement {
+///         name: "Lava".to_string(),
+///         category: ElementCategory::Liquid,
+///         color: (207, 16, 32),
+///         density: 80,
+///         hardness: 20,
+///     },
+///     LavaBehavior,
+/// );
+/// ```
+pub trait ElementAppExt {
+    fn register_element(
+        &mut self,
+        element: CustomElement,
+        behavior: impl ElementBehavior,
+    ) -> &mut Self;
+
+    /// Replaces the behaviour of an element, built-in elements included.
+    fn set_element_behavior(
+        &mut self,
+        kind: ElementKind,
+        behavior: impl ElementBehavior,
+    ) -> &mut Self;
+}
+
+impl ElementAppExt for App {
+    fn register_element(
+        &mut self,
+        element: CustomElement,
+        behavior: impl ElementBehavior,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ElementRegistry>()
+            .register_with_behavior(element, behavior);
+        self
+    }
+
+    fn set_element_behavior(
+        &mut self,
+        kind: ElementKind,
+        behavior: impl ElementBehavior,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ElementRegistry>()
+            .set_behavior(kind, behavior);
+        self
+    }
+}
+
 pub struct SimulationPlugin;
 
 impl Plugin for SimulationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_simulation)
+        let mut settings = *app.world_mut().get_resource_or_init::<WorldSettings>();
+        if let Err(err) = settings.validate() {
+            error!("Invalid WorldSettings, using the defaults: {err}");
+            settings = WorldSettings::default();
+            app.insert_resource(settings);
+        }
+        app.add_systems(Startup, (setup_simulation, reload_element_scripts).chain())
+            .add_systems(
+                Update,
+                (
+                    serve_remote_control,
+                    apply_edits,
+                    // Clients show the world of the host instead
+                    tick_simulation
+                        .run_if(on_timer(Duration::from_millis(30)))
+                        .run_if(not(is_client)),
+                    sync_network,
+                )
+                    .chain()
+                    .before(render_simulation),
+            )
             .add_systems(
                 Update,
-                tick_simulation.run_if(on_timer(Duration::from_millis(30))),
+                reload_element_scripts.run_if(on_timer(Duration::from_secs(1))),
             )
             .add_systems(PreUpdate, create_fresh_chunks)
-            .add_systems(Update, (draw, render_simulation).chain())
+            .add_systems(Update, (draw, render_simulation, render_particles).chain())
             .add_systems(
                 Update,
                 (
@@ -58,16 +165,39 @@ impl Plugin for SimulationPlugin {
                     toggle_active,
                     walk_camera,
                     change_selected_element,
+                    detach_body,
+                    detonate,
+                    save_or_load_world,
+                    record_or_play_replay.run_if(not(is_client)),
+                    draw_emitters,
                 ),
             )
             .add_systems(PostUpdate, update_last_mouse_position)
-            .insert_resource(Resolution(RESOLUTION as f32))
-            .insert_resource(LastMousePosition(IVec2::ZERO))
-            .insert_resource(SelectedElement(ElementKind::Sand));
+            .add_event::<UserEdit>()
+            .insert_resource(Resolution(settings.resolution))
+            .insert_resource(LastMousePosition(ScreenPos::default()))
+            .insert_resource(SelectedElement(ElementKind::Sand))
+            .init_resource::<SelectedEmitter>()
+            .init_resource::<ScriptWatcher>()
+            .init_resource::<Replay>()
+            .init_resource::<Rewind>()
+            .init_resource::<ElementRegistry>();
     }
 }
 
-pub fn setup_simulation(mut commands: Commands) {
+pub fn setup_simulation(
+    mut commands: Commands,
+    elements: Res<ElementRegistry>,
+    settings: Res<WorldSettings>,
+    resolution: Res<Resolution>,
+) {
+    // Looking at the middle of the chunks the world starts with
+    let center = match settings.bounds {
+        WorldBounds::Finite { area, .. } => (area.min + area.max).as_vec2() / 2.0,
+        WorldBounds::Unbounded | WorldBounds::Wrapping { .. } => {
+            Vec2::new(1.0, -1.0) * settings.chunk_size.0 as f32 / 2.0
+        }
+    };
     commands
         .spawn(Camera2d)
         .insert(MainCameraState {
@@ -76,19 +206,25 @@ pub fn setup_simulation(mut commands: Commands) {
             max_speed: 1000.0,
             velocity: Vec2::ZERO,
         })
-        .insert(Transform::from_xyz(
-            (CHUNK_SIZE as f32 * RESOLUTION as f32) / 2.0,
-            (CHUNK_SIZE as f32 * RESOLUTION as f32) / 2.0,
-            100.0,
+        .insert(Transform::from_translation(
+            ScreenPos::from_world_point(center, &resolution)
+                .0
+                .extend(100.0),
         ));
 
-    commands.insert_resource(Sandbox::new());
+    // The sandbox owns the registry from now on, scripts are reloaded into its copy
+    let mut sandbox = Sandbox::with_settings(rand::random(), *settings);
+    sandbox.elements = Arc::new(elements.clone());
+    commands.insert_resource(sandbox);
+    commands.remove_resource::<ElementRegistry>();
 }
 
 pub fn zoom_camera(
+    sandbox: Res<Sandbox>,
     mut resolution: ResMut<Resolution>,
     mut scroll_event: EventReader<MouseWheel>,
     mut q_chunks: Query<(&WorldChunk, &mut Transform, &mut Sprite)>,
+    mut q_copies: Query<(&WrappedCopy, &mut Transform, &mut Sprite), Without<WorldChunk>>,
 ) {
     use bevy::input::mouse::MouseScrollUnit;
 
@@ -104,43 +240,191 @@ pub fn zoom_camera(
 
     resolution.0 = (resolution.0 + delta).clamp(0.3, 20.0);
 
+    let chunk_size = sandbox.chunk_size();
     for (WorldChunk { position }, mut transform, mut sprite) in q_chunks.iter_mut() {
-        sprite.custom_size = Some(Vec2::splat(resolution.0 * CHUNK_SIZE as f32));
-        transform.translation = chunk_to_world_position(*position).extend(1.0) * resolution.0;
+        sprite.custom_size = Some(Vec2::splat(resolution.0 * chunk_size.0 as f32));
+        transform.translation = position
+            .to_screen_pos(chunk_size, &resolution)
+            .0
+            .extend(1.0);
+    }
+
+    let width = sandbox.settings().width().unwrap_or_default();
+    for (copy, mut transform, mut sprite) in q_copies.iter_mut() {
+        sprite.custom_size = Some(Vec2::splat(resolution.0 * chunk_size.0 as f32));
+        transform.translation.x = (copy.offset * width) as f32 * resolution.0;
+    }
+}
+
+pub fn is_client(network: Option<Res<Network>>) -> bool {
+    matches!(network.as_deref(), Some(Network::Client(_)))
+}
+
+/// Applies the edits of the user, and of the clients when hosting, see [`Replay::apply`].
+/// Clients send theirs to the host.
+pub fn apply_edits(
+    mut sandbox: ResMut<Sandbox>,
+    mut replay: ResMut<Replay>,
+    mut network: Option<ResMut<Network>>,
+    mut edits: EventReader<UserEdit>,
+) {
+    let mut edits = edits
+        .read()
+        .map(|UserEdit(edit)| edit.clone())
+        .collect::<Vec<_>>();
+    if let Some(Network::Host(host)) = network.as_deref_mut() {
+        edits.append(&mut host.receive(&sandbox));
+    }
+
+    for edit in edits {
+        if let Err(err) = apply_edit(&mut sandbox, &mut replay, network.as_deref_mut(), edit) {
+            error!("{err}");
+        }
+    }
+}
+
+/// Answers the requests of other programs, see [`RemoteControl`]. Their edits are applied
+/// right away, so that the next request already sees them.
+pub fn serve_remote_control(
+    mut sandbox: ResMut<Sandbox>,
+    mut replay: ResMut<Replay>,
+    mut rewind: ResMut<Rewind>,
+    mut network: Option<ResMut<Network>>,
+    remote: Option<ResMut<RemoteControl>>,
+) {
+    let Some(mut remote) = remote else {
+        return;
+    };
+
+    remote.serve(|request| {
+        answer_remote(
+            &mut sandbox,
+            &mut replay,
+            &mut rewind,
+            network.as_deref_mut(),
+            request,
+        )
+    });
+}
+
+/// Carries out a request of the [`RemoteControl`]: its edits are applied like the user's, and
+/// its ticks are recorded and sent to the clients like any other.
+pub fn answer_remote(
+    sandbox: &mut Sandbox,
+    replay: &mut Replay,
+    rewind: &mut Rewind,
+    mut network: Option<&mut Network>,
+    request: Request,
+) -> Result<serde_json::Value, String> {
+    match sandbox.answer(request)? {
+        Answer::Done(result) => Ok(result),
+        Answer::Edit(edit) => {
+            apply_edit(sandbox, replay, network, edit)?;
+            Ok(serde_json::Value::Null)
+        }
+        Answer::Step(ticks) => {
+            if let Some(Network::Client(_)) = network.as_deref() {
+                return Err("Clients don't simulate, step the host instead".to_string());
+            }
+
+            let active = sandbox.active;
+            sandbox.active = true;
+            for _ in 0..ticks {
+                tick(sandbox, replay, rewind);
+                // The clients can't miss a tick, see `Host::send_changes`
+                if let Some(Network::Host(host)) = network.as_deref_mut() {
+                    host.send_changes(sandbox);
+                }
+            }
+            sandbox.active = active;
+            Ok(serde_json::Value::Null)
+        }
     }
 }
 
-pub fn toggle_active(mut sandbox: ResMut<Sandbox>, keyboard: Res<ButtonInput<KeyCode>>) {
-    if keyboard.just_pressed(KeyCode::Space) {
-        sandbox.active = !sandbox.active;
+/// Applies an edit like [`apply_edits`], or sends it to the host when playing on someone
+/// else's network.
+fn apply_edit(
+    sandbox: &mut Sandbox,
+    replay: &mut Replay,
+    network: Option<&mut Network>,
+    edit: Edit,
+) -> Result<(), String> {
+    match network {
+        Some(Network::Client(client)) => {
+            return client
+                .send(&edit)
+                .map_err(|err| format!("Failed to send {edit:?} to the host: {err}"));
+        }
+        Some(Network::Host(host)) => host.mark_edited(),
+        None => {}
+    }
+
+    replay
+        .apply(sandbox, edit)
+        .map_err(|err| format!("Failed to change the world: {err}"))
+}
+
+/// Sends the clients what changed when hosting, or shows what the host sent. A client that
+/// loses the host carries on with the world on its own.
+pub fn sync_network(
+    mut commands: Commands,
+    mut sandbox: ResMut<Sandbox>,
+    network: Option<ResMut<Network>>,
+) {
+    let Some(mut network) = network else {
+        return;
+    };
+
+    match &mut *network {
+        Network::Host(host) => host.send_changes(&sandbox),
+        Network::Client(client) => {
+            if let Err(err) = client.receive(&mut sandbox) {
+                error!("Lost the host: {err}");
+                commands.remove_resource::<Network>();
+            }
+        }
+    }
+}
+
+pub fn toggle_active(
+    sandbox: Res<Sandbox>,
+    mut edits: EventWriter<UserEdit>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::TogglePause) {
+        edits.write(UserEdit(Edit::SetPaused(sandbox.active)));
     }
 }
 
 pub fn walk_camera(
     mut camera: Query<(&mut MainCameraState, &mut Transform)>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    sandbox: Res<Sandbox>,
+    actions: Res<ActionState>,
+    resolution: Res<Resolution>,
     time: Res<Time>,
 ) {
     let Ok((mut camera_state, mut transform)) = camera.single_mut() else {
         return;
     };
 
-    let mut direction = Vec2::ZERO;
-    if keyboard.pressed(KeyCode::KeyW) {
+    let mut direction = actions.camera_axis;
+    if actions.pressed(Action::MoveCameraUp) {
         direction.y += 1.0;
     }
-    if keyboard.pressed(KeyCode::KeyS) {
+    if actions.pressed(Action::MoveCameraDown) {
         direction.y -= 1.0;
     }
-    if keyboard.pressed(KeyCode::KeyA) {
+    if actions.pressed(Action::MoveCameraLeft) {
         direction.x -= 1.0;
     }
-    if keyboard.pressed(KeyCode::KeyD) {
+    if actions.pressed(Action::MoveCameraRight) {
         direction.x += 1.0;
     }
 
     let acceleration = camera_state.acceleration;
-    let direction = direction.normalize_or_zero();
+    // Not normalized so a slightly tilted stick moves the camera slowly
+    let direction = direction.clamp_length_max(1.0);
     let friction = camera_state.friction;
     if direction == Vec2::ZERO {
         camera_state.velocity = camera_state.velocity.lerp(Vec2::ZERO, friction);
@@ -152,19 +436,40 @@ pub fn walk_camera(
     }
 
     transform.translation += (camera_state.velocity * time.delta_secs()).extend(0.0);
+
+    // Jumps back by a whole world width at the seam, where the chunks look the same thanks to
+    // their wrapped copies
+    if let Some(width) = sandbox.settings().width() {
+        let width = width as f32 * resolution.0;
+        transform.translation.x = transform.translation.x.rem_euclid(width);
+    }
 }
 
 pub fn create_fresh_chunks(
     mut sandbox: ResMut<Sandbox>,
     mut commands: Commands,
+    q_chunks: Query<(Entity, &WorldChunk)>,
     mut images: ResMut<Assets<Image>>,
     resolution: Res<Resolution>,
 ) {
-    for fresh_chunk_position in sandbox.fresh_chunks.drain(..) {
+    if sandbox.fresh_chunks.is_empty() {
+        return;
+    }
+
+    // Loading a world replaces its chunks, their old sprites go
+    for (entity, WorldChunk { position }) in q_chunks.iter() {
+        if sandbox.get_chunk(*position).is_none() || sandbox.fresh_chunks.contains(position) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let chunk_size = sandbox.chunk_size();
+    let width = sandbox.settings().width();
+    for fresh_chunk_position in std::mem::take(&mut sandbox.fresh_chunks) {
         let mut image = Image::new_fill(
             Extent3d {
-                height: CHUNK_SIZE as u32,
-                width: CHUNK_SIZE as u32,
+                height: chunk_size.0 as u32,
+                width: chunk_size.0 as u32,
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
@@ -173,37 +478,188 @@ pub fn create_fresh_chunks(
             Default::default(),
         );
         image.sampler = ImageSampler::nearest();
+        // A chunk may be asleep from the start, like the ones put back by rewinding
+        if let Some(chunk) = sandbox.get_chunk(fresh_chunk_position) {
+            copy_colors(&mut image, &chunk);
+        }
 
-        let handle = images.add(image);
+        let sprite = Sprite {
+            image: images.add(image),
+            anchor: Anchor::TopLeft,
+            custom_size: Some(Vec2::splat(resolution.0 * chunk_size.0 as f32)),
+            ..Default::default()
+        };
 
-        commands
-            .spawn(WorldChunk {
+        let mut chunk = commands.spawn((
+            WorldChunk {
                 position: fresh_chunk_position,
-            })
-            .insert(Sprite {
-                image: handle,
-                anchor: Anchor::TopLeft,
-                custom_size: Some(Vec2::splat(resolution.0 * CHUNK_SIZE as f32)),
-                ..Default::default()
-            })
-            .insert(Transform::from_translation(
-                chunk_to_world_position(fresh_chunk_position).extend(1.0) * resolution.0,
-            ));
+            },
+            sprite.clone(),
+            Transform::from_translation(
+                fresh_chunk_position
+                    .to_screen_pos(chunk_size, &resolution)
+                    .0
+                    .extend(1.0),
+            ),
+        ));
+
+        // The copies share the image, so they are updated along with the chunk
+        if let Some(width) = width {
+            chunk.with_children(|parent| {
+                for offset in [-1, 1] {
+                    parent.spawn((
+                        WrappedCopy { offset },
+                        sprite.clone(),
+                        Transform::from_xyz((offset * width) as f32 * resolution.0, 0.0, 0.0),
+                    ));
+                }
+            });
+        }
     }
 }
 
 pub fn change_selected_element(
     mut selected_element: ResMut<SelectedElement>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    mut selected_emitter: ResMut<SelectedEmitter>,
+    sandbox: Res<Sandbox>,
+    actions: Res<ActionState>,
 ) {
-    if keyboard.just_pressed(KeyCode::Digit1) {
-        selected_element.0 = ElementKind::Sand;
+    for (action, kind) in [
+        (Action::SelectSand, ElementKind::Sand),
+        (Action::SelectStone, ElementKind::Stone),
+        (Action::SelectWater, ElementKind::Water),
+    ] {
+        if actions.just_pressed(action) {
+            selected_element.0 = kind;
+            selected_emitter.0 = None;
+        }
     }
-    if keyboard.just_pressed(KeyCode::Digit2) {
-        selected_element.0 = ElementKind::Stone;
+
+    let step = if actions.just_pressed(Action::SelectNextElement) {
+        1
+    } else if actions.just_pressed(Action::SelectPreviousElement) {
+        -1
+    } else {
+        return;
+    };
+
+    // Air is left out, erasing has its own mouse button
+    let placeable = sandbox
+        .elements
+        .kinds()
+        .filter(|kind| *kind != ElementKind::Air)
+        .collect::<Vec<_>>();
+    let index = placeable
+        .iter()
+        .position(|kind| *kind == selected_element.0)
+        .unwrap_or(0) as i32;
+    selected_element.0 = placeable[(index + step).rem_euclid(placeable.len() as i32) as usize];
+    selected_emitter.0 = None;
+}
+
+/// Turns the solid under the cursor into a rigid body.
+pub fn detach_body(
+    mut edits: EventWriter<UserEdit>,
+    actions: Res<ActionState>,
+    last_mouse_position: Res<LastMousePosition>,
+    resolution: Res<Resolution>,
+) {
+    if actions.just_pressed(Action::DetachBody) {
+        let cell = last_mouse_position.0.to_world_cell(&resolution).0;
+        edits.write(UserEdit(Edit::DetachBody { cell }));
     }
-    if keyboard.just_pressed(KeyCode::Digit3) {
-        selected_element.0 = ElementKind::Water;
+}
+
+pub fn detonate(
+    mut edits: EventWriter<UserEdit>,
+    actions: Res<ActionState>,
+    last_mouse_position: Res<LastMousePosition>,
+    resolution: Res<Resolution>,
+) {
+    if actions.just_pressed(Action::Detonate) {
+        let cell = last_mouse_position.0.to_world_cell(&resolution);
+        let center = cell.0.as_vec2() + Vec2::splat(0.5);
+        let explosion = Edit::Explode {
+            center,
+            radius: DETONATION_RADIUS,
+            power: DETONATION_POWER,
+        };
+        edits.write(UserEdit(explosion));
+    }
+}
+
+/// Writes the world to [`SAVE_PATH`] or replaces it with the one saved there.
+pub fn save_or_load_world(
+    sandbox: Res<Sandbox>,
+    mut edits: EventWriter<UserEdit>,
+    actions: Res<ActionState>,
+) {
+    let path = Path::new(SAVE_PATH);
+    if actions.just_pressed(Action::QuickSave) {
+        match sandbox.save().write(path) {
+            Ok(()) => info!("Saved the world to {SAVE_PATH}"),
+            Err(err) => error!("Failed to save {SAVE_PATH}: {err}"),
+        }
+    }
+
+    if actions.just_pressed(Action::QuickLoad) {
+        match WorldSave::read(path) {
+            Ok(save) => {
+                info!("Loading the world from {SAVE_PATH}");
+                edits.write(UserEdit(Edit::Load(Box::new(save))));
+            }
+            Err(err) => error!("Failed to load {SAVE_PATH}: {err}"),
+        }
+    }
+}
+
+/// Starts and stops recording a replay to [`REPLAY_PATH`], or plays it back.
+pub fn record_or_play_replay(
+    mut sandbox: ResMut<Sandbox>,
+    mut replay: ResMut<Replay>,
+    actions: Res<ActionState>,
+) {
+    let path = Path::new(REPLAY_PATH);
+    if actions.just_pressed(Action::ToggleRecording) {
+        if let Replay::Recording(_) = *replay {
+            let recording = replay.stop(&sandbox).unwrap();
+            match recording.write(path) {
+                Ok(()) => info!(
+                    "Saved the replay of ticks {} to {} to {REPLAY_PATH}",
+                    recording.start, recording.end
+                ),
+                Err(err) => error!("Failed to save {REPLAY_PATH}: {err}"),
+            }
+        } else {
+            *replay = Replay::Recording(Recording::start(&mut sandbox, rand::random()));
+            info!("Recording a replay from tick {}", sandbox.wframe);
+        }
+    }
+
+    if actions.just_pressed(Action::PlayReplay) {
+        let played =
+            Recording::read(path).and_then(|recording| replay.play(&mut sandbox, recording));
+        match played {
+            Ok(()) => info!("Playing the replay from {REPLAY_PATH}"),
+            Err(err) => error!("Failed to play {REPLAY_PATH}: {err}"),
+        }
+    }
+}
+
+/// Outlines every emitter, in the colour of what it spawns.
+pub fn draw_emitters(mut gizmos: Gizmos, sandbox: Res<Sandbox>, resolution: Res<Resolution>) {
+    for emitter in sandbox.emitters.iter() {
+        let color = match emitter.action {
+            EmitterAction::Spawn { element, .. } => {
+                let (r, g, b) = sandbox.elements.base_color(element);
+                Color::srgb_u8(r, g, b)
+            }
+            EmitterAction::Drain => Color::srgb_u8(160, 40, 200),
+        };
+
+        let min = ScreenPos::from_world_point(emitter.area.min.as_vec2(), &resolution).0;
+        let max = ScreenPos::from_world_point(emitter.area.max.as_vec2(), &resolution).0;
+        gizmos.rect_2d((min + max) / 2.0, (max - min).abs(), color);
     }
 }
 
@@ -223,18 +679,16 @@ pub fn update_last_mouse_position(
         return;
     };
 
-    let Ok(mouse_position) = camera
-        .viewport_to_world(camera_transform, cursor_position)
-        .map(|p| p.origin.truncate())
+    let Some(mouse_position) = ScreenPos::from_viewport(camera, camera_transform, cursor_position)
     else {
         return;
     };
 
-    last_mouse_position.0 = mouse_position.as_ivec2();
+    last_mouse_position.0 = mouse_position;
 }
 
 pub fn draw(
-    mut sandbox: ResMut<Sandbox>,
+    mut edits: EventWriter<UserEdit>,
     mut egui_ctx: EguiContexts,
     last_mouse_position: Res<LastMousePosition>,
     mouse_input: Res<ButtonInput<MouseButton>>,
@@ -242,13 +696,22 @@ pub fn draw(
     q_camera: Query<(&Camera, &GlobalTransform)>,
     resolution: Res<Resolution>,
     selected_element: Res<SelectedElement>,
+    selected_emitter: Res<SelectedEmitter>,
 ) {
     if egui_ctx.ctx_mut().wants_pointer_input() {
         return;
     }
 
+    if let Some(preset) = selected_emitter.0 {
+        if mouse_input.just_pressed(MouseButton::Left) {
+            let cell = last_mouse_position.0.to_world_cell(&resolution).0;
+            edits.write(UserEdit(Edit::PlaceEmitter { preset, cell }));
+        }
+    }
+
     let is_deleting = mouse_input.pressed(MouseButton::Right);
-    if !mouse_input.pressed(MouseButton::Left) && !is_deleting {
+    let is_drawing = mouse_input.pressed(MouseButton::Left) && selected_emitter.0.is_none();
+    if !is_drawing && !is_deleting {
         return;
     }
 
@@ -263,55 +726,75 @@ pub fn draw(
         return;
     };
 
-    let Ok(mouse_position) = camera
-        .viewport_to_world(camera_transform, cursor_position)
-        .map(|p| p.origin.truncate())
+    let Some(mouse_position) = ScreenPos::from_viewport(camera, camera_transform, cursor_position)
     else {
         return;
     };
 
-    let iter = math::GridLineIterator::new(last_mouse_position.0, mouse_position.as_ivec2());
-    for pos in iter {
-        let mut pos = pos.as_vec2();
-        pos /= resolution.0;
-        pos.y *= -1.0;
-
-        let chunk_position = world_to_chunk_position(pos);
-
-        let chunk = match sandbox.get_shared_chunk(chunk_position) {
-            Some(chunk) => chunk,
-            None => {
-                let shared = sandbox.add_chunk(Chunk::new(chunk_position));
-                shared
-            }
-        };
+    let stroke = Edit::Stroke {
+        from: last_mouse_position.0.to_world_cell(&resolution).0,
+        to: mouse_position.to_world_cell(&resolution).0,
+        element: if is_deleting {
+            ElementKind::Air
+        } else {
+            selected_element.0
+        },
+    };
+    edits.write(UserEdit(stroke));
+}
 
-        let local_position = world_to_element_position(pos);
+/// Loads the element scripts that are new or changed since the last call.
+pub fn reload_element_scripts(mut sandbox: ResMut<Sandbox>, mut watcher: ResMut<ScriptWatcher>) {
+    let Ok(entries) = fs::read_dir(scripting::SCRIPTS_DIR) else {
+        return;
+    };
 
-        if !chunk.read().is_empty(local_position) && !is_deleting {
+    let mut changed = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "rhai") {
             continue;
         }
 
-        let element_kind = if is_deleting {
-            ElementKind::Air
-        } else {
-            selected_element.0
+        let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) else {
+            continue;
         };
+        if watcher.modified.insert(path.clone(), modified) != Some(modified) {
+            changed.push(path);
+        }
+    }
 
-        chunk.write().set_element(
-            local_position,
-            Element {
-                wframe: 0,
-                color: element_kind.base_color(),
-                velocity: Vec2::ZERO,
-                kind: element_kind,
-            },
-        );
+    if changed.is_empty() {
+        return;
+    }
+
+    changed.sort();
+    let mut elements = (*sandbox.elements).clone();
+    for path in changed {
+        match elements.load_script(&path) {
+            Ok(kind) => info!("Loaded {} from {}", elements.name(kind), path.display()),
+            Err(err) => error!("Failed to load {}: {err}", path.display()),
+        }
     }
+    sandbox.elements = Arc::new(elements);
+}
+
+pub fn tick_simulation(
+    mut sandbox: ResMut<Sandbox>,
+    mut replay: ResMut<Replay>,
+    mut rewind: ResMut<Rewind>,
+) {
+    tick(&mut sandbox, &mut replay, &mut rewind);
 }
 
-pub fn tick_simulation(mut sandbox: ResMut<Sandbox>) {
+/// Plays back what's due and ticks, keeping a snapshot to rewind to.
+fn tick(sandbox: &mut Sandbox, replay: &mut Replay, rewind: &mut Rewind) {
+    replay.advance(sandbox);
+    let wframe = sandbox.wframe;
     sandbox.tick();
+    if sandbox.wframe != wframe {
+        rewind.record(sandbox);
+    }
 }
 
 pub fn render_simulation(
@@ -321,24 +804,72 @@ pub fn render_simulation(
 ) {
     for (chunk, sprite) in chunks.iter_mut() {
         let image = images.get_mut(&sprite.image).unwrap();
-        let chunk = sandbox.get_chunk(chunk.position).unwrap();
+        // Chunks that were replaced by loading a world lose their sprite with the next fresh ones
+        let Some(chunk) = sandbox.get_chunk(chunk.position) else {
+            continue;
+        };
         if !chunk.active() {
             continue;
         }
 
-        let data = image.data.as_mut().unwrap();
+        copy_colors(image, &chunk);
+    }
+}
+
+fn copy_colors(image: &mut Image, chunk: &Chunk) {
+    let data = image.data.as_mut().unwrap();
 
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                let element = chunk.get_element(IVec2::new(x as i32, y as i32));
-                let color = element.color;
+    // Chunks store their cells row by row like the image, so this is a straight copy
+    for (pixel, color) in data.chunks_exact_mut(4).zip(chunk.colors()) {
+        pixel.copy_from_slice(&[color.0, color.1, color.2, 255]);
+    }
+}
 
-                let index = (y * CHUNK_SIZE + x) * 4;
-                data[index] = color.0;
-                data[index + 1] = color.1;
-                data[index + 2] = color.2;
-                data[index + 3] = 255;
+/// Moves a sprite over every flying particle, reusing the sprites of the previous frame.
+pub fn render_particles(
+    mut commands: Commands,
+    sandbox: Res<Sandbox>,
+    mut sprites: Query<(Entity, &mut Sprite, &mut Transform), With<ParticleSprite>>,
+    camera: Query<&Transform, (With<MainCameraState>, Without<ParticleSprite>)>,
+    resolution: Res<Resolution>,
+) {
+    let camera_x = camera
+        .single()
+        .map_or(0.0, |camera| camera.translation.x / resolution.0);
+
+    let mut sprites = sprites.iter_mut();
+    for particle in sandbox.particles.iter() {
+        let (r, g, b) = particle.element.color;
+        let color = Color::srgb_u8(r, g, b);
+        let position = Vec2::new(
+            sandbox.settings().closest_x(particle.position.x, camera_x),
+            particle.position.y,
+        );
+        let translation = ScreenPos::from_world_point(position, &resolution)
+            .0
+            .extend(2.0);
+
+        match sprites.next() {
+            Some((_, mut sprite, mut transform)) => {
+                sprite.color = color;
+                sprite.custom_size = Some(Vec2::splat(resolution.0));
+                transform.translation = translation;
+            }
+            None => {
+                commands.spawn((
+                    ParticleSprite,
+                    Sprite {
+                        color,
+                        custom_size: Some(Vec2::splat(resolution.0)),
+                        ..Default::default()
+                    },
+                    Transform::from_translation(translation),
+                ));
             }
         }
     }
+
+    for (entity, ..) in sprites {
+        commands.entity(entity).despawn();
+    }
 }
@@ -0,0 +1,160 @@
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+
+use bevy::ecs::resource::Resource;
+use rhai::Engine;
+
+use super::*;
+
+/// An element that isn't built into the game, like the ones defined by scripts or plugins.
+#[derive(Debug, Clone)]
+pub struct CustomElement {
+    pub name: String,
+    pub category: ElementCategory,
+    pub color: (u8, u8, u8),
+    pub density: u8,
+    /// See [`ElementKind::hardness`].
+    pub hardness: u8,
+}
+
+/// Every element kind the simulation knows about, built-in or custom.
+///
+/// Custom kinds are identified by their name: registering an element with the name of an
+/// existing one replaces it but keeps its [`ElementKind`], so the world stays valid.
+///
+/// Plugins add to it through [`ElementAppExt`](super::plugin::ElementAppExt) while the app is
+/// built, the [`Sandbox`] owns it afterwards.
+#[derive(Clone, Resource)]
+pub struct ElementRegistry {
+    custom: Vec<CustomElement>,
+    behaviors: HashMap<ElementKind, Arc<dyn ElementBehavior>>,
+    engine: Arc<Engine>,
+}
+
+impl Default for ElementRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            custom: Vec::new(),
+            behaviors: HashMap::new(),
+            engine: Arc::new(scripting::engine()),
+        };
+        registry.set_behavior(ElementKind::Sand, SandBehavior);
+        registry.set_behavior(ElementKind::Water, LiquidBehavior(Liquid::WATER));
+        registry.set_behavior(ElementKind::Oil, LiquidBehavior(Liquid::OIL));
+        registry.set_behavior(ElementKind::Honey, LiquidBehavior(Liquid::HONEY));
+        registry.set_behavior(ElementKind::Gunpowder, SandBehavior);
+        registry
+    }
+}
+
+impl fmt::Debug for ElementRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut behaviors = self.behaviors.keys().collect::<Vec<_>>();
+        behaviors.sort();
+
+        f.debug_struct("ElementRegistry")
+            .field("custom", &self.custom)
+            .field("behaviors", &behaviors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ElementRegistry {
+    pub fn register(&mut self, element: CustomElement) -> ElementKind {
+        match self
+            .custom
+            .iter()
+            .position(|other| other.name == element.name)
+        {
+            Some(index) => {
+                self.custom[index] = element;
+                ElementKind::Custom(index as u16)
+            }
+            None => {
+                self.custom.push(element);
+                ElementKind::Custom(self.custom.len() as u16 - 1)
+            }
+        }
+    }
+
+    pub fn register_with_behavior(
+        &mut self,
+        element: CustomElement,
+        behavior: impl ElementBehavior,
+    ) -> ElementKind {
+        let kind = self.register(element);
+        self.set_behavior(kind, behavior);
+        kind
+    }
+
+    /// Replaces the behaviour of a kind, built-in kinds included.
+    pub fn set_behavior(&mut self, kind: ElementKind, behavior: impl ElementBehavior) {
+        self.behaviors.insert(kind, Arc::new(behavior));
+    }
+
+    pub fn behavior(&self, kind: ElementKind) -> Option<&Arc<dyn ElementBehavior>> {
+        self.behaviors.get(&kind)
+    }
+
+    /// Compiles and registers an element script, see [`scripting`] for the format.
+    pub fn load_script(&mut self, path: &Path) -> Result<ElementKind, ScriptError> {
+        let source = std::fs::read_to_string(path)?;
+        self.load_script_source(&source)
+    }
+
+    pub fn load_script_source(&mut self, source: &str) -> Result<ElementKind, ScriptError> {
+        let (element, script) = scripting::compile(&self.engine, source)?;
+        Ok(self.register_with_behavior(element, script))
+    }
+
+    pub fn get(&self, kind: ElementKind) -> Option<&CustomElement> {
+        match kind {
+            ElementKind::Custom(index) => self.custom.get(index as usize),
+            _ => None,
+        }
+    }
+
+    pub fn kind_by_name(&self, name: &str) -> Option<ElementKind> {
+        self.kinds().find(|kind| self.name(*kind) == name)
+    }
+
+    /// The built-in kinds followed by the custom ones, in registration order.
+    pub fn kinds(&self) -> impl Iterator<Item = ElementKind> + '_ {
+        ElementKind::ALL
+            .into_iter()
+            .chain((0..self.custom.len()).map(|index| ElementKind::Custom(index as u16)))
+    }
+
+    pub fn name(&self, kind: ElementKind) -> &str {
+        self.get(kind)
+            .map_or(kind.name(), |element| element.name.as_str())
+    }
+
+    pub fn category(&self, kind: ElementKind) -> ElementCategory {
+        self.get(kind)
+            .map_or(kind.category(), |element| element.category)
+    }
+
+    pub fn base_color(&self, kind: ElementKind) -> (u8, u8, u8) {
+        self.get(kind)
+            .map_or(kind.base_color(), |element| element.color)
+    }
+
+    pub fn density(&self, kind: ElementKind) -> u8 {
+        self.get(kind)
+            .map_or(kind.density(), |element| element.density)
+    }
+
+    pub fn hardness(&self, kind: ElementKind) -> u8 {
+        self.get(kind)
+            .map_or(kind.hardness(), |element| element.hardness)
+    }
+
+    /// A fresh, motionless element of the given kind.
+    pub fn element(&self, kind: ElementKind) -> Element {
+        Element {
+            color: self.base_color(kind),
+            kind,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Driving a running sandbox from other programs, like analysis scripts, over a local socket.
+//!
+//! Every request is a line of JSON naming its `command`, and is answered with a line holding
+//! either its `result` or an `error`:
+//!
+//! ```text
+//! {"command": "fill", "min": [0, 0], "max": [10, 10], "element": "Sand"}
+//! {"result": null}
+//! {"command": "get_element", "x": 3, "y": 4}
+//! {"result": {"kind": "Sand", "color": [194, 178, 128], "velocity": [0.0, 0.0]}}
+//! ```
+//!
+//! Requests are answered between frames, in the order they arrive. Those that change the world
+//! become [`Edit`]s like everything the user does, see [`Sandbox::answer`] and
+//! [`answer_remote`](super::plugin::answer_remote).
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    ecs::resource::Resource,
+    image::Image,
+    math::IVec2,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    common::Rect,
+    coordinates::{LocalCell, WorldCell},
+};
+
+use super::*;
+
+/// The port the remote control listens on unless told otherwise.
+pub const DEFAULT_REMOTE_PORT: u16 = 7879;
+
+/// The most ticks a single step runs, 30 seconds of simulation. The window doesn't update
+/// until they are done, so longer runs take several steps.
+const MAX_STEP: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Pause,
+    Resume,
+    /// Runs a number of ticks (one unless given, at most 1000) right away, even while paused.
+    Step {
+        ticks: Option<u32>,
+    },
+    GetElement {
+        x: i32,
+        y: i32,
+    },
+    SetElement {
+        x: i32,
+        y: i32,
+        element: String,
+    },
+    /// Fills the cells from `min` to `max` (exclusive).
+    Fill {
+        min: IVec2,
+        max: IVec2,
+        element: String,
+    },
+    /// Replaces the world with the one saved at `path`, the quick save unless given. Paths
+    /// are relative to the working directory and can't leave it, for all commands.
+    Load {
+        path: Option<PathBuf>,
+    },
+    Save {
+        path: Option<PathBuf>,
+    },
+    /// Draws the cells from `min` to `max` (exclusive), or of every chunk unless given, into a
+    /// PNG with one pixel per cell.
+    Screenshot {
+        path: PathBuf,
+        min: Option<IVec2>,
+        max: Option<IVec2>,
+    },
+    Stats,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Result(Value),
+    Error(String),
+}
+
+/// What it takes to answer a [`Request`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Answer {
+    /// The request only looks at the world, this is its result.
+    Done(Value),
+    /// Applied like the edits of the user, answered with `null`.
+    Edit(Edit),
+    /// Runs this many ticks, answered with `null`.
+    Step(u32),
+}
+
+/// Listens for the requests of other programs, see the [module docs](self).
+#[derive(Debug, Resource)]
+pub struct RemoteControl {
+    address: SocketAddr,
+    // Locked to share the remote control between systems, only ever taken through `&mut self`
+    calls: Mutex<Receiver<(Request, Sender<Response>)>>,
+}
+
+impl RemoteControl {
+    /// Starts listening in the background. Anyone who can reach `address` controls the
+    /// sandbox, so it had better be a local one.
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let (sender, calls) = mpsc::channel();
+        thread::spawn(move || accept_connections(listener, sender));
+
+        Ok(Self {
+            address,
+            calls: Mutex::new(calls),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Answers the requests that came in since the last call, in the order they arrived.
+    pub fn serve(&mut self, mut answer: impl FnMut(Request) -> Result<Value, String>) {
+        for (request, reply) in self.calls.get_mut().try_iter() {
+            let response = match answer(request) {
+                Ok(result) => Response::Result(result),
+                Err(err) => Response::Error(err),
+            };
+            // Whoever asked may be gone already
+            let _ = reply.send(response);
+        }
+    }
+}
+
+fn accept_connections(listener: TcpListener, calls: Sender<(Request, Sender<Response>)>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let calls = calls.clone();
+        thread::spawn(move || {
+            // Ends with the connection, or once the remote control is dropped
+            let _ = answer_connection(stream, calls);
+        });
+    }
+}
+
+fn answer_connection(
+    mut stream: TcpStream,
+    calls: Sender<(Request, Sender<Response>)>,
+) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(err) if err.is_data() => {
+                write_response(
+                    &mut stream,
+                    &Response::Error(format!("Not a request: {err}")),
+                )?;
+                continue;
+            }
+            Err(err) => {
+                // Whatever is talking doesn't even send JSON, like a browser that was tricked
+                // into posting to the socket, so the rest of what it sends isn't read at all
+                let response = Response::Error(format!("Not a request: {err}"));
+                return write_response(&mut stream, &response);
+            }
+        };
+
+        let (reply, response) = mpsc::channel();
+        if calls.send((request, reply)).is_err() {
+            break;
+        }
+        let Ok(response) = response.recv() else {
+            break;
+        };
+        write_response(&mut stream, &response)?;
+    }
+    Ok(())
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let mut line = serde_json::to_string(response).expect("responses always serialize");
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+impl Sandbox {
+    /// Works out the answer to a request of the [remote control](RemoteControl). Requests
+    /// that change the world are turned into edits, for the caller to apply.
+    pub fn answer(&self, request: Request) -> Result<Answer, String> {
+        let answer = match request {
+            Request::Pause => Answer::Edit(Edit::SetPaused(true)),
+            Request::Resume => Answer::Edit(Edit::SetPaused(false)),
+            Request::Step { ticks } => {
+                let ticks = ticks.unwrap_or(1);
+                if ticks > MAX_STEP {
+                    return Err(format!(
+                        "{ticks} ticks are too many at once, step at most {MAX_STEP}"
+                    ));
+                }
+                Answer::Step(ticks)
+            }
+            Request::GetElement { x, y } => {
+                let element = self.get_element(WorldCell::new(x, y));
+                Answer::Done(element.map_or(Value::Null, |element| {
+                    let (r, g, b) = element.color;
+                    json!({
+                        "kind": self.elements.name(element.kind),
+                        "color": [r, g, b],
+                        "velocity": [element.velocity.x, element.velocity.y],
+                    })
+                }))
+            }
+            Request::SetElement { x, y, element } => Answer::Edit(Edit::Fill {
+                min: IVec2::new(x, y),
+                max: IVec2::new(x, y).saturating_add(IVec2::ONE),
+                element: self.kind_named(&element)?,
+            }),
+            Request::Fill { min, max, element } => {
+                if checked_area(Rect::new(min, max))? == IVec2::ZERO {
+                    return Ok(Answer::Done(Value::Null));
+                }
+                Answer::Edit(Edit::Fill {
+                    min,
+                    max,
+                    element: self.kind_named(&element)?,
+                })
+            }
+            Request::Load { path } => {
+                let path = local_path(path.unwrap_or_else(|| PathBuf::from(SAVE_PATH)))?;
+                let save = WorldSave::read(&path)
+                    .map_err(|err| format!("Failed to load {}: {err}", path.display()))?;
+                Answer::Edit(Edit::Load(Box::new(save)))
+            }
+            Request::Save { path } => {
+                let path = local_path(path.unwrap_or_else(|| PathBuf::from(SAVE_PATH)))?;
+                self.save()
+                    .write(&path)
+                    .map_err(|err| format!("Failed to save {}: {err}", path.display()))?;
+                Answer::Done(Value::Null)
+            }
+            Request::Screenshot { path, min, max } => {
+                let area = match (min, max) {
+                    (Some(min), Some(max)) => Rect::new(min, max),
+                    (None, None) => self.area(),
+                    _ => return Err("A screenshot needs both min and max, or neither".into()),
+                };
+                self.screenshot(area, &local_path(path)?)?;
+                Answer::Done(Value::Null)
+            }
+            Request::Stats => Answer::Done(self.stats()),
+        };
+        Ok(answer)
+    }
+
+    fn kind_named(&self, name: &str) -> Result<ElementKind, String> {
+        self.elements
+            .kind_by_name(name)
+            .ok_or_else(|| format!("There is no element called {name}"))
+    }
+
+    /// The cells of every chunk.
+    fn area(&self) -> Rect {
+        let size = self.chunk_size();
+        self.chunks.keys().fold(Rect::empty(), |area, position| {
+            let min = position.cell(LocalCell::new(0, 0), size).0;
+            area.union(&Rect::new(min, min + size.0))
+        })
+    }
+
+    fn screenshot(&self, area: Rect, path: &Path) -> Result<(), String> {
+        let size = checked_area(area)?;
+        if size == IVec2::ZERO {
+            return Err("There is nothing to draw".into());
+        }
+
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: size.x as u32,
+                height: size.y as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        // Cells without a chunk stay transparent
+        let data = image.data.as_mut().unwrap();
+        for (cell, element) in self.iter_region(area) {
+            let offset = cell.0 - area.min;
+            let index = (offset.y * size.x + offset.x) as usize * 4;
+            let (r, g, b) = element.color;
+            data[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+        }
+
+        image
+            .try_into_dynamic()
+            .map_err(|err| err.to_string())?
+            .save(path)
+            .map_err(|err| format!("Failed to save {}: {err}", path.display()))
+    }
+
+    fn stats(&self) -> Value {
+        let awake = self
+            .chunks
+            .values()
+            .filter(|chunk| chunk.read().active())
+            .count();
+        let elements = ElementCensus::take(self)
+            .totals()
+            .into_iter()
+            .map(|(kind, count)| (self.elements.name(kind).to_string(), count))
+            .collect::<BTreeMap<_, _>>();
+
+        json!({
+            "tick": self.wframe,
+            "paused": !self.active,
+            "chunks": self.chunks.len(),
+            "awake_chunks": awake,
+            "particles": self.particles.len(),
+            "bodies": self.bodies.len(),
+            "emitters": self.emitters.len(),
+            "elements": elements,
+        })
+    }
+}
+
+/// Refuses paths that reach outside of the working directory, whoever can send requests
+/// shouldn't get to read or overwrite any file the sandbox can.
+fn local_path(path: PathBuf) -> Result<PathBuf, String> {
+    let is_local = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_local {
+        return Err(format!(
+            "{} is outside of the working directory",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
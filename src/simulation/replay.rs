@@ -0,0 +1,294 @@
+//! Recording what the user does to the world and playing it back.
+//!
+//! The simulation is deterministic for a given seed, so a recording only needs the world it
+//! started from, the seed and every [`Edit`] with the tick it was made after. Played back, it
+//! runs exactly like the session it was recorded in, which turns a bug report into a repro.
+
+use std::{fs, path::Path};
+
+use bevy::{
+    ecs::resource::Resource,
+    log::{error, info},
+    math::{I64Vec2, IVec2, Vec2},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{math, Rect},
+    coordinates::WorldCell,
+};
+
+use super::*;
+
+/// Where the replay is recorded to and played back from, next to the quick save.
+pub const REPLAY_PATH: &str = "replay.ron";
+
+/// Edits and screenshots that reach more cells than this are taken for garbage rather than
+/// carried out, they would create chunks all the way.
+const MAX_AREA: i64 = 1 << 24;
+
+/// A change made to the world from outside of the simulation. Everything the user does goes
+/// through [`Sandbox::apply`], so that it can be recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Edit {
+    /// Draws `element` into the free cells along the line from `from` to `to`, like a stroke
+    /// of the brush. Air erases whatever is in the way, emitters included.
+    Stroke {
+        from: IVec2,
+        to: IVec2,
+        element: ElementKind,
+    },
+    /// Replaces every cell from `min` to `max` (exclusive) with `element`, whatever was there.
+    Fill {
+        min: IVec2,
+        max: IVec2,
+        element: ElementKind,
+    },
+    PlaceEmitter {
+        preset: EmitterPreset,
+        cell: IVec2,
+    },
+    DetachBody {
+        cell: IVec2,
+    },
+    Explode {
+        center: Vec2,
+        radius: f32,
+        power: f32,
+    },
+    SetPaused(bool),
+    SetGravity(Gravity),
+    /// Replaces the whole world, like loading the quick save.
+    Load(Box<WorldSave>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEdit {
+    /// The tick the edit was made after, it is played back right before the next one.
+    pub tick: u64,
+    pub edit: Edit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    /// Seeds the simulation from the start of the recording.
+    pub seed: u64,
+    /// The tick the recording started after.
+    pub start: u64,
+    /// The tick the recording was stopped after.
+    pub end: u64,
+    /// Whether the simulation was paused when the recording started.
+    pub paused: bool,
+    /// The names of the registered kinds when the recording started. They have to match when
+    /// it is played back, as the world and the edits refer to custom kinds by their index.
+    pub kinds: Vec<String>,
+    /// The world when the recording started, exactly.
+    pub world: Snapshot,
+    /// In the order they were made.
+    pub edits: Vec<RecordedEdit>,
+}
+
+impl Recording {
+    /// Starts recording the sandbox from a [`Snapshot`] of it. Only its random numbers are
+    /// reseeded, as there is no getting at their state, the world itself is left as it is.
+    pub fn start(sandbox: &mut Sandbox, seed: u64) -> Self {
+        sandbox.reseed(seed);
+        let elements = &sandbox.elements;
+        Self {
+            seed,
+            start: sandbox.wframe,
+            end: sandbox.wframe,
+            paused: !sandbox.active,
+            kinds: elements
+                .kinds()
+                .map(|kind| elements.name(kind).to_string())
+                .collect(),
+            world: sandbox.snapshot(),
+            edits: Vec::new(),
+        }
+    }
+
+    /// Puts the sandbox back into the state the recording started from.
+    pub fn restore(&self, sandbox: &mut Sandbox) -> Result<(), SaveError> {
+        let elements = &sandbox.elements;
+        if !elements
+            .kinds()
+            .map(|kind| elements.name(kind))
+            .eq(self.kinds.iter().map(String::as_str))
+        {
+            return Err(SaveError::Invalid(
+                "The replay was recorded with other elements".to_string(),
+            ));
+        }
+
+        sandbox.restore(&self.world)?;
+        sandbox.active = !self.paused;
+        sandbox.reseed(self.seed);
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        let contents = fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(|err| SaveError::Format(err.to_string()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        let contents = ron::to_string(self).map_err(|err| SaveError::Format(err.to_string()))?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Whether the user's edits are being recorded or a recording is played back.
+#[derive(Debug, Default, Resource)]
+pub enum Replay {
+    #[default]
+    Off,
+    Recording(Recording),
+    Playing {
+        recording: Recording,
+        /// The index of the next edit to play.
+        next: usize,
+    },
+}
+
+impl Replay {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, Self::Playing { .. })
+    }
+
+    /// Plays `recording` back from its start, see [`Replay::advance`].
+    pub fn play(&mut self, sandbox: &mut Sandbox, recording: Recording) -> Result<(), SaveError> {
+        recording.restore(sandbox)?;
+        *self = Self::Playing { recording, next: 0 };
+        Ok(())
+    }
+
+    /// Applies an edit made by the user, recording it if a recording is running. While a
+    /// recording is played back, the user can only pause, which doesn't change what happens.
+    pub fn apply(&mut self, sandbox: &mut Sandbox, edit: Edit) -> Result<(), SaveError> {
+        match self {
+            Self::Off => sandbox.apply(&edit),
+            Self::Recording(recording) => {
+                sandbox.apply(&edit)?;
+                recording.edits.push(RecordedEdit {
+                    tick: sandbox.wframe,
+                    edit,
+                });
+                Ok(())
+            }
+            Self::Playing { .. } => match edit {
+                Edit::SetPaused(_) => sandbox.apply(&edit),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Stops recording or playing, returning the recording.
+    pub fn stop(&mut self, sandbox: &Sandbox) -> Option<Recording> {
+        match std::mem::take(self) {
+            Self::Off => None,
+            Self::Recording(mut recording) => {
+                recording.end = sandbox.wframe;
+                Some(recording)
+            }
+            Self::Playing { recording, .. } => Some(recording),
+        }
+    }
+
+    /// Plays back the edits due before the next tick. Playback stops once the recording is
+    /// over, the simulation carries on from there.
+    pub fn advance(&mut self, sandbox: &mut Sandbox) {
+        let Self::Playing { recording, next } = self else {
+            return;
+        };
+
+        while let Some(recorded) = recording.edits.get(*next) {
+            if recorded.tick > sandbox.wframe {
+                break;
+            }
+
+            // Only the edits that worked were recorded
+            if let Err(err) = sandbox.apply(&recorded.edit) {
+                error!("Failed to play back {:?}: {err}", recorded.edit);
+            }
+            *next += 1;
+        }
+
+        if *next == recording.edits.len() && sandbox.wframe >= recording.end {
+            info!("Finished playing the replay at tick {}", sandbox.wframe);
+            *self = Self::Off;
+        }
+    }
+}
+
+impl Sandbox {
+    /// Makes a change to the world, see [`Edit`]. Only loading a world and edits that reach too
+    /// many cells can fail, and then the world is left as it was.
+    pub fn apply(&mut self, edit: &Edit) -> Result<(), SaveError> {
+        match edit {
+            Edit::Stroke { from, to, element } => {
+                let length = (to.as_i64vec2() - from.as_i64vec2()).abs().max_element();
+                if length >= MAX_AREA {
+                    return Err(SaveError::Invalid(format!(
+                        "A stroke from {from} to {to} is too long"
+                    )));
+                }
+                for cell in math::GridLineIterator::new(*from, *to) {
+                    let cell = WorldCell(cell);
+                    if *element == ElementKind::Air {
+                        self.remove_emitters_at(cell);
+                    } else if self
+                        .get_element(cell)
+                        .is_some_and(|element| element.kind != ElementKind::Air)
+                    {
+                        continue;
+                    }
+
+                    let element = self.elements.element(*element);
+                    self.set_element(cell, element);
+                }
+            }
+            Edit::Fill { min, max, element } => {
+                let area = Rect::new(*min, *max);
+                checked_area(area).map_err(SaveError::Invalid)?;
+                let element = self.elements.element(*element);
+                self.fill_rect(area, element);
+            }
+            Edit::PlaceEmitter { preset, cell } => {
+                let cell = self.settings().wrap(WorldCell(*cell));
+                let emitter = preset.emitter(cell, &self.gravity);
+                self.emitters.push(emitter);
+            }
+            Edit::DetachBody { cell } => {
+                if !self.detach_body(WorldCell(*cell)) {
+                    info!("Nothing to detach at {}", WorldCell(*cell));
+                }
+            }
+            Edit::Explode {
+                center,
+                radius,
+                power,
+            } => self.explode(*center, *radius, *power),
+            Edit::SetPaused(paused) => self.active = !paused,
+            Edit::SetGravity(gravity) => self.set_gravity(*gravity),
+            Edit::Load(save) => self.load(save)?,
+        }
+        Ok(())
+    }
+}
+
+/// The size of `area`, if it isn't too big. Empty areas have no size at all.
+pub(super) fn checked_area(area: Rect) -> Result<IVec2, String> {
+    let size = (area.max.as_i64vec2() - area.min.as_i64vec2()).max(I64Vec2::ZERO);
+    if size.x.saturating_mul(size.y) > MAX_AREA {
+        return Err(format!(
+            "{} by {} cells are too many at once",
+            size.x, size.y
+        ));
+    }
+    if size.min_element() == 0 {
+        return Ok(IVec2::ZERO);
+    }
+    Ok(size.as_ivec2())
+}
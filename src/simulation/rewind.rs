@@ -0,0 +1,82 @@
+//! Going back in time: [snapshots](Snapshot) of the whole [`Sandbox`], taken every few ticks,
+//! that the simulation can be put back to and resumed from.
+
+use std::collections::VecDeque;
+
+use bevy::ecs::resource::Resource;
+use rand::rngs::StdRng;
+
+use super::*;
+
+/// A [`Snapshot`] along with the random numbers of the sandbox, so that the simulation resumes
+/// from it exactly like it ran the first time.
+#[derive(Debug, Clone)]
+pub struct RewindPoint {
+    pub snapshot: Snapshot,
+    rng: StdRng,
+}
+
+/// The snapshots of the last stretch of the simulation, see [`Rewind::record`].
+#[derive(Debug, Resource)]
+pub struct Rewind {
+    /// How many ticks apart the snapshots are.
+    pub interval: u64,
+    /// How many snapshots are kept, the oldest ones are dropped first.
+    pub capacity: usize,
+    points: VecDeque<RewindPoint>,
+}
+
+impl Default for Rewind {
+    /// At the usual 30 ms per tick, a snapshot every 0.3 seconds for the last 30 seconds.
+    fn default() -> Self {
+        Self::new(10, 100)
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity,
+            points: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn points(&self) -> &VecDeque<RewindPoint> {
+        &self.points
+    }
+
+    /// Takes a snapshot if one is due, call it after every tick that ran. Once the simulation
+    /// was rewound and moves on, the snapshots of the future it left behind are dropped.
+    pub fn record(&mut self, sandbox: &Sandbox) {
+        if !sandbox.wframe.is_multiple_of(self.interval) {
+            return;
+        }
+
+        while self
+            .points
+            .back()
+            .is_some_and(|last| last.snapshot.wframe >= sandbox.wframe)
+        {
+            self.points.pop_back();
+        }
+
+        self.points.push_back(RewindPoint {
+            snapshot: sandbox.snapshot(),
+            rng: sandbox.rng.clone(),
+        });
+        while self.points.len() > self.capacity {
+            self.points.pop_front();
+        }
+    }
+
+    /// Rewinds the sandbox to the point at `index`, see [`Sandbox::restore`].
+    pub fn restore(&self, sandbox: &mut Sandbox, index: usize) {
+        if let Some(point) = self.points.get(index) {
+            sandbox
+                .restore(&point.snapshot)
+                .expect("a sandbox always fits its own snapshots");
+            sandbox.rng = point.rng.clone();
+        }
+    }
+}
@@ -0,0 +1,652 @@
+//! Rigid bodies made of cells, like a chunk of stone that broke loose.
+//!
+//! A body keeps a copy of its cells and writes them into the grid at its current position and
+//! rotation on every tick, so the chunks stay the source of truth for everything else: elements
+//! flow around bodies, and a body cell that gets replaced in the grid is gone from the body too.
+//!
+//! Positions are continuous world cell coordinates, where the cell `(x, y)` covers
+//! `x..x + 1` and `y..y + 1`, and velocities are in cells (or radians) per tick.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::coordinates::WorldCell;
+
+use super::*;
+
+/// Bodies fall slower than loose elements, which makes them look heavy.
+const GRAVITY_SCALE: f32 = 0.6;
+const MAX_SPEED: f32 = 10.0;
+const MAX_ANGULAR_SPEED: f32 = 0.3;
+const ANGULAR_DAMPING: f32 = 0.98;
+const RESTITUTION: f32 = 0.2;
+const FRICTION: f32 = 0.6;
+/// How far the outline may travel between two collision checks.
+const MAX_STEP: f32 = 0.5;
+/// How often a body may hit something in a single tick.
+const MAX_BOUNCES: usize = 4;
+/// Slower impacts don't bounce, so resting bodies don't jitter.
+const BOUNCE_SPEED: f32 = 1.0;
+/// Bodies slower than this (at their furthest cell) stop.
+const REST_SPEED: f32 = 0.01;
+/// How often the impulses of all contact points are refined against each other.
+const CONTACT_ITERATIONS: usize = 8;
+/// Where the outline is drawn between a cell of the body (1) and an empty one (0). Above 0.5 so
+/// the outline vertices lie inside the outermost cells, which is what collisions are about.
+const OUTLINE_ISO: f32 = 0.75;
+
+/// Groups smaller than this aren't worth a body, they fall apart into loose elements instead.
+pub const MIN_BODY_CELLS: usize = 4;
+/// Groups bigger than this are considered anchored to the world and can't be detached.
+pub const MAX_BODY_CELLS: usize = 4096;
+
+const NEIGHBOURS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RigidBody {
+    /// The center of mass.
+    pub position: Vec2,
+    pub rotation: f32,
+    pub velocity: Vec2,
+    pub angular_velocity: f32,
+    /// The center of every cell relative to the center of mass, before rotation.
+    cells: Vec<(Vec2, Element)>,
+    /// Where every cell was written into the grid, in the same order as `cells`.
+    placed: Vec<WorldCell>,
+    /// The marching squares outline of the cells, relative to the center of mass.
+    outline: Vec<(Vec2, Vec2)>,
+    /// The vertices of the outline, tested against the grid for collisions.
+    probes: Vec<Vec2>,
+    inertia: f32,
+    /// How far the furthest cell is from the center of mass.
+    radius: f32,
+}
+
+impl RigidBody {
+    /// Creates a body from cells that are already in the grid.
+    pub fn new(cells: Vec<(WorldCell, Element)>, velocity: Vec2, angular_velocity: f32) -> Self {
+        let center = |cell: WorldCell| cell.0.as_vec2() + Vec2::splat(0.5);
+        let position =
+            cells.iter().map(|(cell, _)| center(*cell)).sum::<Vec2>() / cells.len() as f32;
+
+        let offsets = cells
+            .iter()
+            .map(|(cell, element)| (center(*cell) - position, *element))
+            .collect::<Vec<_>>();
+
+        // A cell is a unit square with its own moment of inertia of 1/6
+        let inertia = offsets
+            .iter()
+            .map(|(offset, _)| offset.length_squared() + 1.0 / 6.0)
+            .sum();
+        let radius = offsets
+            .iter()
+            .map(|(offset, _)| offset.length() + 0.5)
+            .fold(0.0, f32::max);
+
+        let outline = marching_squares(&cells.iter().map(|(cell, _)| cell.0).collect())
+            .into_iter()
+            .map(|(a, b)| (a - position, b - position))
+            .collect::<Vec<_>>();
+
+        let mut probes = Vec::new();
+        let mut seen = HashSet::new();
+        for point in outline.iter().flat_map(|(a, b)| [*a, *b]) {
+            if seen.insert((point * 4.0).round().as_ivec2()) {
+                probes.push(point);
+            }
+        }
+
+        Self {
+            position,
+            rotation: 0.0,
+            velocity,
+            angular_velocity,
+            placed: cells.iter().map(|(cell, _)| *cell).collect(),
+            cells: offsets,
+            outline,
+            probes,
+            inertia,
+            radius,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The cells of the body where they are in the grid.
+    pub fn cells(&self) -> impl Iterator<Item = (WorldCell, Element)> + '_ {
+        self.placed
+            .iter()
+            .zip(self.cells.iter())
+            .map(|(cell, (_, element))| (*cell, *element))
+    }
+
+    /// The collision outline in world space.
+    pub fn outline(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        self.outline.iter().map(|(a, b)| {
+            (
+                self.to_world(self.position, self.rotation, *a),
+                self.to_world(self.position, self.rotation, *b),
+            )
+        })
+    }
+
+    pub fn point_velocity(&self, point: Vec2) -> Vec2 {
+        self.velocity + (point - self.position).perp() * self.angular_velocity
+    }
+
+    fn to_world(&self, position: Vec2, rotation: f32, offset: Vec2) -> Vec2 {
+        position + Vec2::from_angle(rotation).rotate(offset)
+    }
+
+    /// The grid cell of every body cell for a position and rotation. Cells that round to the same
+    /// grid cell are moved to the nearest free one, so the body never loses any.
+    fn targets(&self, position: Vec2, rotation: f32) -> Vec<WorldCell> {
+        let mut used = HashSet::with_capacity(self.cells.len());
+        self.cells
+            .iter()
+            .map(|(offset, _)| {
+                let point = self.to_world(position, rotation, *offset);
+                let cell = WorldCell(point.floor().as_ivec2());
+                let target = if used.contains(&cell) {
+                    nearest_unused(point, &used)
+                } else {
+                    cell
+                };
+                used.insert(target);
+                target
+            })
+            .collect()
+    }
+
+    fn apply_impulse(&mut self, offset: Vec2, impulse: Vec2) {
+        self.velocity += impulse / self.cells.len() as f32;
+        self.angular_velocity += offset.perp_dot(impulse) / self.inertia;
+    }
+
+    /// How hard the body resists being pushed at `offset` along `direction`.
+    fn effective_mass(&self, offset: Vec2, direction: Vec2) -> f32 {
+        let arm = offset.perp_dot(direction);
+        1.0 / (1.0 / self.cells.len() as f32 + arm * arm / self.inertia)
+    }
+
+    /// Bounces off everything it touches at once. `contacts` are points with the normal of what
+    /// was hit there, pointing away from it.
+    ///
+    /// The impulses are found iteratively, so a body resting on two points or wedged between two
+    /// walls gets pushed out of all of them, not just out of the first one.
+    fn collide(&mut self, contacts: &[(Vec2, Vec2)]) {
+        // Only what was moving into its contact bounces, and only when it was fast
+        let targets = contacts
+            .iter()
+            .map(|(point, normal)| {
+                let normal_speed = self.point_velocity(*point).dot(*normal);
+                if normal_speed < -BOUNCE_SPEED {
+                    -RESTITUTION * normal_speed
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut normal_impulses = vec![0.0; contacts.len()];
+        let mut friction_impulses = vec![0.0; contacts.len()];
+        for _ in 0..CONTACT_ITERATIONS {
+            for (index, (point, normal)) in contacts.iter().enumerate() {
+                let offset = *point - self.position;
+
+                // Contacts can only push, so the total impulse never goes below zero
+                let normal_speed = self.point_velocity(*point).dot(*normal);
+                let total = (normal_impulses[index]
+                    + (targets[index] - normal_speed) * self.effective_mass(offset, *normal))
+                .max(0.0);
+                self.apply_impulse(offset, *normal * (total - normal_impulses[index]));
+                normal_impulses[index] = total;
+
+                let tangent = normal.perp();
+                let limit = FRICTION * normal_impulses[index];
+                let tangent_speed = self.point_velocity(*point).dot(tangent);
+                let total = (friction_impulses[index]
+                    - tangent_speed * self.effective_mass(offset, tangent))
+                .clamp(-limit, limit);
+                self.apply_impulse(offset, tangent * (total - friction_impulses[index]));
+                friction_impulses[index] = total;
+            }
+        }
+    }
+}
+
+fn nearest_unused(point: Vec2, used: &HashSet<WorldCell>) -> WorldCell {
+    let center = point.floor().as_ivec2();
+    for radius in 1.. {
+        let closest = ring(center, radius)
+            .filter(|cell| !used.contains(cell))
+            .min_by(|a, b| {
+                let distance = |cell: &WorldCell| (cell.0.as_vec2() + 0.5).distance_squared(point);
+                distance(a).total_cmp(&distance(b))
+            });
+        if let Some(cell) = closest {
+            return cell;
+        }
+    }
+    unreachable!()
+}
+
+/// The cells at exactly `radius` steps (in the chessboard metric) from `center`.
+pub(super) fn ring(center: IVec2, radius: i32) -> impl Iterator<Item = WorldCell> {
+    (-radius..=radius)
+        .flat_map(move |y| (-radius..=radius).map(move |x| IVec2::new(x, y)))
+        .filter(move |offset| offset.x.abs() == radius || offset.y.abs() == radius)
+        .map(move |offset| WorldCell(center + offset))
+}
+
+/// The outline of a set of cells as line segments, sampling every cell at its center.
+fn marching_squares(cells: &HashSet<IVec2>) -> Vec<(Vec2, Vec2)> {
+    let Some(min) = cells.iter().copied().reduce(IVec2::min) else {
+        return Vec::new();
+    };
+    let max = cells.iter().copied().fold(min, IVec2::max);
+
+    let sample = |cell: IVec2| cells.contains(&cell);
+    let center = |cell: IVec2| cell.as_vec2() + Vec2::splat(0.5);
+    // The point on the edge between a cell of the body and an empty one
+    let edge = |a: IVec2, b: IVec2| {
+        let (inside, outside) = if sample(a) { (a, b) } else { (b, a) };
+        center(inside).lerp(center(outside), 1.0 - OUTLINE_ISO)
+    };
+
+    let mut segments = Vec::new();
+    for y in min.y - 1..=max.y {
+        for x in min.x - 1..=max.x {
+            let top_left = IVec2::new(x, y);
+            let top_right = top_left + IVec2::X;
+            let bottom_left = top_left + IVec2::Y;
+            let bottom_right = top_left + IVec2::ONE;
+
+            let case = (sample(top_left) as u8) << 3
+                | (sample(top_right) as u8) << 2
+                | (sample(bottom_right) as u8) << 1
+                | sample(bottom_left) as u8;
+
+            let top = || edge(top_left, top_right);
+            let right = || edge(top_right, bottom_right);
+            let bottom = || edge(bottom_left, bottom_right);
+            let left = || edge(top_left, bottom_left);
+
+            match case {
+                0 | 15 => {}
+                1 | 14 => segments.push((left(), bottom())),
+                2 | 13 => segments.push((bottom(), right())),
+                3 | 12 => segments.push((left(), right())),
+                4 | 11 => segments.push((top(), right())),
+                6 | 9 => segments.push((top(), bottom())),
+                7 | 8 => segments.push((left(), top())),
+                5 => {
+                    segments.push((left(), top()));
+                    segments.push((bottom(), right()));
+                }
+                10 => {
+                    segments.push((top(), right()));
+                    segments.push((left(), bottom()));
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+    segments
+}
+
+/// 4-connected groups of cells.
+fn connected_groups(cells: Vec<(WorldCell, Element)>) -> Vec<Vec<(WorldCell, Element)>> {
+    let mut remaining = cells.into_iter().collect::<HashMap<_, _>>();
+    let mut starts = remaining.keys().copied().collect::<Vec<_>>();
+    starts.sort_by_key(|cell| (cell.0.y, cell.0.x));
+
+    let mut groups = Vec::new();
+    for start in starts {
+        let Some(element) = remaining.remove(&start) else {
+            continue;
+        };
+
+        let mut group = vec![(start, element)];
+        let mut index = 0;
+        while index < group.len() {
+            let cell = group[index].0;
+            for neighbour in NEIGHBOURS.map(|offset| WorldCell(cell.0 + offset)) {
+                if let Some(element) = remaining.remove(&neighbour) {
+                    group.push((neighbour, element));
+                }
+            }
+            index += 1;
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+enum Obstacle {
+    None,
+    /// Liquids and gases are pushed out of the way.
+    Displaceable(Element),
+    /// Solids, powders, other bodies and the end of the world.
+    Blocking,
+}
+
+impl Sandbox {
+    /// Turns the solid cells connected to `cell` into a rigid body. Returns whether it worked,
+    /// it doesn't if the group is too small or too big (see [`MAX_BODY_CELLS`]).
+    pub fn detach_body(&mut self, cell: WorldCell) -> bool {
+        let taken = self
+            .bodies
+            .iter()
+            .flat_map(|body| body.placed.iter().copied())
+            .collect::<HashSet<_>>();
+        let is_solid = |cell: WorldCell| {
+            self.get_element(cell).filter(|element| {
+                !taken.contains(&cell)
+                    && self.elements.category(element.kind) == ElementCategory::Solid
+            })
+        };
+
+        let Some(element) = is_solid(cell) else {
+            return false;
+        };
+
+        let mut group = vec![(cell, element)];
+        let mut visited = HashSet::from([cell]);
+        let mut index = 0;
+        while index < group.len() {
+            if group.len() > MAX_BODY_CELLS {
+                return false;
+            }
+
+            let cell = group[index].0;
+            for neighbour in NEIGHBOURS.map(|offset| WorldCell(cell.0 + offset)) {
+                if visited.insert(neighbour) {
+                    if let Some(element) = is_solid(neighbour) {
+                        group.push((neighbour, element));
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        if group.len() < MIN_BODY_CELLS {
+            return false;
+        }
+
+        self.bodies.push(RigidBody::new(group, Vec2::ZERO, 0.0));
+        true
+    }
+
+    /// Breaks the bodies that have cells within `radius` of `center`: those cells become loose
+    /// elements and whatever is left of each body splits into its connected pieces.
+    pub fn break_bodies(&mut self, center: Vec2, radius: f32) {
+        for body in std::mem::take(&mut self.bodies) {
+            let (broken, kept): (Vec<_>, Vec<_>) = body.cells().partition(|(cell, _)| {
+                (cell.0.as_vec2() + 0.5).distance_squared(center) <= radius * radius
+            });
+
+            if broken.is_empty() {
+                self.bodies.push(body);
+                continue;
+            }
+
+            self.release(&body, &broken);
+            let pieces = self.split(&body, kept);
+            self.bodies.extend(pieces);
+        }
+    }
+
+    pub(super) fn tick_bodies(&mut self) {
+        for body in std::mem::take(&mut self.bodies) {
+            let bodies = self.tick_body(body);
+            self.bodies.extend(bodies);
+        }
+    }
+
+    /// Moves a body, returning what's left of it.
+    fn tick_body(&mut self, mut body: RigidBody) -> Vec<RigidBody> {
+        // Cells that were replaced in the grid (erased, dissolved...) aren't part of the body anymore
+        let (kept, lost): (Vec<_>, Vec<_>) = body.cells().partition(|(cell, element)| {
+            self.get_element(*cell)
+                .is_some_and(|other| other.kind == element.kind)
+        });
+        if !lost.is_empty() {
+            return self.split(&body, kept);
+        }
+
+        let own = body.placed.iter().copied().collect::<HashSet<_>>();
+
+        let center = WorldCell(body.position.floor().as_ivec2());
+        body.velocity += self.gravity.at(center).vector() * GRAVITY_SCALE;
+        body.velocity = body.velocity.clamp_length_max(MAX_SPEED);
+        body.angular_velocity =
+            (body.angular_velocity * ANGULAR_DAMPING).clamp(-MAX_ANGULAR_SPEED, MAX_ANGULAR_SPEED);
+
+        // Move until something is hit, bounce off it and carry on with what's left of the tick
+        let mut remaining = 1.0;
+        for _ in 0..MAX_BOUNCES {
+            let (travelled, contacts) = self.sweep(&mut body, remaining, &own);
+            if contacts.is_empty() {
+                break;
+            }
+
+            body.collide(&contacts);
+            remaining -= travelled;
+        }
+
+        // Whatever is left of the impulses only makes a resting body creep
+        if body.velocity.length() + body.angular_velocity.abs() * body.radius < REST_SPEED {
+            body.velocity = Vec2::ZERO;
+            body.angular_velocity = 0.0;
+        }
+
+        // Cells thrown off leave the body right away, where they were meant to go may be an
+        // element of the same kind that mustn't be taken for theirs
+        let thrown = self.place(&mut body);
+        if !thrown.is_empty() {
+            let kept = body
+                .cells()
+                .enumerate()
+                .filter(|(index, _)| !thrown.contains(index))
+                .map(|(_, cell)| cell)
+                .collect();
+            return self.split(&body, kept);
+        }
+        vec![body]
+    }
+
+    /// Moves a body along its velocity for `duration` ticks or until its outline would overlap
+    /// something, in steps small enough that it can't tunnel through thin walls. Returns the
+    /// time travelled and the outline points that hit something, with the normals of what they
+    /// hit.
+    fn sweep(
+        &self,
+        body: &mut RigidBody,
+        duration: f32,
+        own: &HashSet<WorldCell>,
+    ) -> (f32, Vec<(Vec2, Vec2)>) {
+        let travel =
+            (body.velocity.length() + body.angular_velocity.abs() * body.radius) * duration;
+        let steps = (travel / MAX_STEP).ceil().max(1.0) as usize;
+        let step = duration / steps as f32;
+
+        for index in 0..steps {
+            let position = body.position + body.velocity * step;
+            let rotation = body.rotation + body.angular_velocity * step;
+
+            // A probe that is already inside something (cells are placed rounded, so it happens)
+            // only counts once it moves on into another cell, or the body could never get out.
+            // The normal points back through the side of the cell it entered by.
+            let contacts = body
+                .probes
+                .iter()
+                .filter_map(|probe| {
+                    let from = body.to_world(body.position, body.rotation, *probe);
+                    let to = body.to_world(position, rotation, *probe);
+                    let (from_cell, to_cell) = (from.floor().as_ivec2(), to.floor().as_ivec2());
+                    let blocked = from_cell != to_cell
+                        && matches!(self.obstacle(WorldCell(to_cell), own), Obstacle::Blocking);
+                    blocked.then(|| (to, (from_cell - to_cell).as_vec2().normalize()))
+                })
+                .collect::<Vec<_>>();
+            if !contacts.is_empty() {
+                return (index as f32 * step, contacts);
+            }
+
+            body.position = position;
+            body.rotation = rotation;
+        }
+        (duration, Vec::new())
+    }
+
+    fn obstacle(&self, cell: WorldCell, own: &HashSet<WorldCell>) -> Obstacle {
+        if own.contains(&cell) {
+            return Obstacle::None;
+        }
+
+        match self.get_element(cell) {
+            None => Obstacle::Blocking,
+            Some(element) => match self.elements.category(element.kind) {
+                _ if element.kind == ElementKind::Air => Obstacle::None,
+                ElementCategory::Liquid | ElementCategory::Gas => Obstacle::Displaceable(element),
+                _ => Obstacle::Blocking,
+            },
+        }
+    }
+
+    /// Writes a body back into the grid where it moved to. Returns the index of every cell
+    /// that found no room and was thrown off as a particle instead.
+    fn place(&mut self, body: &mut RigidBody) -> Vec<usize> {
+        let targets = body.targets(body.position, body.rotation);
+        if targets == body.placed {
+            return Vec::new();
+        }
+
+        for cell in body.placed.iter() {
+            self.write_element(*cell, Element::default());
+        }
+
+        // The cells the body just left. There are always at least as many of them as cells that
+        // can't be used as targets, so every element (of the body or pushed away) finds a place.
+        let target_set = targets.iter().copied().collect::<HashSet<_>>();
+        let mut vacated = body
+            .placed
+            .iter()
+            .copied()
+            .filter(|cell| !target_set.contains(cell))
+            .collect::<Vec<_>>();
+        let no_cells = HashSet::new();
+
+        let mut placed = vec![None; targets.len()];
+        for (index, target) in targets.iter().enumerate() {
+            match self.obstacle(*target, &no_cells) {
+                Obstacle::None => {}
+                Obstacle::Displaceable(element) => {
+                    let Some(destination) = self.free_cell(*target, &target_set, &mut vacated)
+                    else {
+                        continue;
+                    };
+                    self.write_element(destination, element);
+                }
+                Obstacle::Blocking => continue,
+            }
+
+            self.write_element(*target, body.cells[index].1);
+            placed[index] = Some(*target);
+        }
+
+        // Cells whose target was blocked go wherever there is room left
+        let mut thrown = Vec::new();
+        for (index, target) in targets.iter().enumerate() {
+            if placed[index].is_some() {
+                continue;
+            }
+
+            let Some(destination) = self.free_cell(*target, &target_set, &mut vacated) else {
+                // Flies off with the body's momentum and lands wherever there is room
+                let velocity = body.point_velocity(target.0.as_vec2() + 0.5);
+                self.throw(*target, body.cells[index].1, velocity);
+                thrown.push(index);
+                continue;
+            };
+            self.write_element(destination, body.cells[index].1);
+            placed[index] = Some(destination);
+        }
+
+        body.placed = placed
+            .into_iter()
+            .zip(targets)
+            .map(|(placed, target)| placed.unwrap_or(target))
+            .collect();
+        thrown
+    }
+
+    /// An empty cell next to `cell` that isn't a target of the body, or one of the cells it
+    /// vacated.
+    fn free_cell(
+        &self,
+        cell: WorldCell,
+        targets: &HashSet<WorldCell>,
+        vacated: &mut Vec<WorldCell>,
+    ) -> Option<WorldCell> {
+        let is_free = |cell: &WorldCell| {
+            self.get_element(*cell)
+                .is_some_and(|element| element.kind == ElementKind::Air)
+        };
+
+        let nearby = (1..=2)
+            .flat_map(|radius| ring(cell.0, radius))
+            .find(|cell| !targets.contains(cell) && is_free(cell));
+        if nearby.is_some() {
+            return nearby;
+        }
+
+        while let Some(vacated_cell) = vacated.pop() {
+            if is_free(&vacated_cell) {
+                return Some(vacated_cell);
+            }
+        }
+        None
+    }
+
+    /// Turns cells of a body into loose elements that keep the body's momentum.
+    fn release(&mut self, body: &RigidBody, cells: &[(WorldCell, Element)]) {
+        for (cell, element) in cells {
+            let velocity = body.point_velocity(cell.0.as_vec2() + 0.5);
+            self.write_element(
+                *cell,
+                Element {
+                    velocity,
+                    ..*element
+                },
+            );
+        }
+    }
+
+    /// Makes a body out of every connected piece of `cells` that is big enough, and loose
+    /// elements out of the rest.
+    fn split(&mut self, body: &RigidBody, cells: Vec<(WorldCell, Element)>) -> Vec<RigidBody> {
+        let mut pieces = Vec::new();
+        for group in connected_groups(cells) {
+            if group.len() < MIN_BODY_CELLS {
+                self.release(body, &group);
+                continue;
+            }
+
+            let piece = RigidBody::new(group, Vec2::ZERO, body.angular_velocity);
+            let velocity = body.point_velocity(piece.position);
+            pieces.push(RigidBody { velocity, ..piece });
+        }
+        pieces
+    }
+}
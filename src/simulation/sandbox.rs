@@ -1,54 +1,132 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use bevy::{ecs::resource::Resource, math::*};
+use bevy::{ecs::resource::Resource, log::error, math::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{common::directions::DIRECTIONS, simulation::*};
+use crate::{
+    common::{directions::DIRECTIONS, Rect},
+    coordinates::{ChunkPos, ChunkSize, LocalCell, WorldCell},
+    simulation::*,
+};
 
 #[derive(Debug, Clone, Resource)]
 pub struct Sandbox {
-    pub wframe: u8,
-    pub chunks: HashMap<IVec2, SharedChunk>,
-    pub fresh_chunks: Vec<IVec2>,
+    /// The number of the current tick, counting from 1 so that it never matches the `wframe`
+    /// of an element that was never updated. It would take billions of years to wrap around.
+    pub wframe: u64,
+    pub chunks: HashMap<ChunkPos, SharedChunk>,
+    pub fresh_chunks: Vec<ChunkPos>,
     pub active: bool,
+    /// Compare element counts before and after every tick and log any element that was
+    /// created or destroyed, other than on purpose through [`LocalApi::set_element`]. Off by
+    /// default since it copies every chunk twice per tick; toggle it from the diagnostics window.
+    pub check_conservation: bool,
+    /// Shared with every [`LocalApi`], replaced as a whole when scripts are reloaded.
+    pub elements: Arc<ElementRegistry>,
+    /// Shared with every [`LocalApi`] like the elements. Changed through
+    /// [`Sandbox::set_gravity`] and [`Sandbox::add_gravity_zone`], which wake up the world.
+    pub gravity: Arc<GravityField>,
+    pub bodies: Vec<RigidBody>,
+    /// Elements flying off the grid, see [`Particle`].
+    pub particles: Vec<Particle>,
+    /// Explosions waiting to go off at the start of the next tick, like the ones set off by
+    /// another explosion.
+    pub explosions: Vec<Explosion>,
+    /// Sources and sinks of elements, see [`Emitter`].
+    pub emitters: Vec<Emitter>,
+    /// Whether the chunks remember which cells keep them awake, see [`Chunk::wakers`].
+    track_wakers: bool,
+    settings: WorldSettings,
+    pub(super) rng: StdRng,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Sandbox {
     pub fn new() -> Self {
+        Self::with_seed(rand::rng().random())
+    }
+
+    /// Creates a sandbox whose simulation is fully reproducible for a given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_settings(seed, WorldSettings::default())
+    }
+
+    /// Creates a sandbox laid out by `settings`, like [`Sandbox::with_seed`].
+    ///
+    /// Panics if the settings are invalid, see [`Sandbox::try_with_settings`].
+    pub fn with_settings(seed: u64, settings: WorldSettings) -> Self {
+        Self::try_with_settings(seed, settings).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Creates a sandbox laid out by `settings`, or fails if [`WorldSettings::validate`] does.
+    pub fn try_with_settings(seed: u64, settings: WorldSettings) -> Result<Self, String> {
+        settings.validate()?;
         let mut sandbox = Self {
             wframe: 0,
             chunks: HashMap::new(),
             fresh_chunks: Vec::with_capacity(16),
             active: true,
+            check_conservation: false,
+            elements: Arc::default(),
+            gravity: Arc::default(),
+            bodies: Vec::new(),
+            particles: Vec::new(),
+            explosions: Vec::new(),
+            emitters: Vec::new(),
+            track_wakers: false,
+            settings,
+            rng: StdRng::seed_from_u64(seed),
         };
 
-        for x in -1..=1 {
-            for y in 0..=2 {
-                sandbox.add_chunk(Chunk::new((x, y).into()));
-            }
+        for position in settings.initial_chunks() {
+            let chunk = settings.new_chunk(position, &sandbox.elements);
+            sandbox.add_chunk(chunk);
         }
 
-        sandbox
+        Ok(sandbox)
+    }
+
+    /// Restarts the random numbers of the simulation, as if it was created with `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
 
-    pub fn get_shared_chunk(&self, position: IVec2) -> Option<SharedChunk> {
-        self.chunks.get(&position).cloned()
+    pub fn settings(&self) -> &WorldSettings {
+        &self.settings
     }
 
-    pub fn get_chunk(&self, position: IVec2) -> Option<ReadableChunk> {
+    pub fn chunk_size(&self) -> ChunkSize {
+        self.settings.chunk_size
+    }
+
+    /// The chunk at `position`, which in a [wrapping](WorldBounds::Wrapping) world may be
+    /// past either side of it.
+    pub fn get_shared_chunk(&self, position: ChunkPos) -> Option<SharedChunk> {
         self.chunks
-            .get(&position)
+            .get(&self.settings.wrap_chunk(position))
+            .cloned()
+    }
+
+    pub fn get_chunk(&self, position: ChunkPos) -> Option<ReadableChunk> {
+        self.chunks
+            .get(&self.settings.wrap_chunk(position))
             .map(|shared_chunk| shared_chunk.read())
     }
 
-    #[allow(unused)]
-    pub fn get_chunk_mut(&self, position: IVec2) -> Option<WritableChunk> {
+    pub fn get_chunk_mut(&self, position: ChunkPos) -> Option<WritableChunk> {
         self.chunks
-            .get(&position)
+            .get(&self.settings.wrap_chunk(position))
             .map(|shared_chunk| shared_chunk.write())
     }
 
-    pub fn add_chunk(&mut self, chunk: Chunk) -> SharedChunk {
+    pub fn add_chunk(&mut self, mut chunk: Chunk) -> SharedChunk {
         let chunk_position = chunk.position;
+        chunk.track_wakers(self.track_wakers);
 
         let shared_chunk = SharedChunk::new(chunk);
         self.chunks.insert(chunk_position, shared_chunk.clone());
@@ -57,91 +135,323 @@ impl Sandbox {
         shared_chunk
     }
 
-    pub fn mark_chunks_surrounding_as_dirty(&mut self, position: IVec2) {
+    /// Returns the element at a world position, or `None` if its chunk doesn't exist or it's
+    /// outside of the [bounds](WorldBounds).
+    pub fn get_element(&self, position: WorldCell) -> Option<Element> {
+        if !self.settings.bounds.contains(position) {
+            return None;
+        }
+
+        let size = self.chunk_size();
+        let chunk = self.get_chunk(position.chunk(size))?;
+        Some(chunk.get_element(position.local(size)))
+    }
+
+    /// Sets the element at a world position, creating its chunk if needed. Positions outside
+    /// of the [bounds](WorldBounds) are left alone.
+    ///
+    /// Runs the `on_destroyed` hook of the element it replaces and the `on_placed` hook of the
+    /// new one, unless both are of the same kind.
+    pub fn set_element(&mut self, position: WorldCell, element: Element) {
+        if !self.settings.bounds.contains(position) {
+            return;
+        }
+
+        let position = self.settings.wrap(position);
+        let previous = self.get_element(position);
+        if let Some(previous) = previous.filter(|previous| previous.kind != element.kind) {
+            self.run_hook(position, previous, |behavior, api| {
+                behavior.on_destroyed(api)
+            });
+        }
+
+        self.write_element(position, element);
+
+        if previous.is_none_or(|previous| previous.kind != element.kind) {
+            self.run_hook(position, element, |behavior, api| behavior.on_placed(api));
+        }
+    }
+
+    /// Sets an element without running any hook, for moving elements around rather than
+    /// creating or destroying them.
+    pub(super) fn write_element(&mut self, position: WorldCell, element: Element) {
+        if !self.settings.bounds.contains(position) {
+            return;
+        }
+
+        let position = self.settings.wrap(position);
+        let chunk_position = position.chunk(self.chunk_size());
+        let element_position = position.local(self.chunk_size());
+
+        let chunk = match self.get_shared_chunk(chunk_position) {
+            Some(chunk) => chunk,
+            None => self.add_chunk(self.settings.new_chunk(chunk_position, &self.elements)),
+        };
+
+        chunk.write().set_element(element_position, element);
+        self.mark_edge_neighbours_dirty(chunk_position, element_position);
+    }
+
+    /// Sets every element inside `rect` (world positions, `max` exclusive).
+    pub fn fill_rect(&mut self, rect: Rect, element: Element) {
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                self.set_element(WorldCell::new(x, y), element);
+            }
+        }
+    }
+
+    /// Iterates row by row over the elements inside `rect` (world positions, `max` exclusive),
+    /// skipping positions whose chunk doesn't exist.
+    pub fn iter_region(&self, rect: Rect) -> impl Iterator<Item = (WorldCell, Element)> + '_ {
+        (rect.min.y..rect.max.y)
+            .flat_map(move |y| (rect.min.x..rect.max.x).map(move |x| WorldCell::new(x, y)))
+            .filter_map(|position| Some((position, self.get_element(position)?)))
+    }
+
+    /// Replaces every element in the world that matches `predicate`, returning how many were
+    /// replaced. Unlike [`Sandbox::set_element`], it doesn't run any behaviour hooks.
+    pub fn replace_where(
+        &mut self,
+        mut predicate: impl FnMut(&Element) -> bool,
+        replacement: Element,
+    ) -> usize {
+        let mut replaced = 0;
+        let mut edges = Vec::new();
+
+        for (chunk_position, chunk) in self.chunks.iter() {
+            let mut chunk = chunk.write();
+            let area = self.settings.local_area(*chunk_position);
+            for x in area.min.x..area.max.x {
+                for y in area.min.y..area.max.y {
+                    let element_position = LocalCell::new(x, y);
+                    if !predicate(&chunk.get_element(element_position)) {
+                        continue;
+                    }
+
+                    chunk.set_element(element_position, replacement);
+                    replaced += 1;
+
+                    if self.edge_direction(element_position) != IVec2::ZERO {
+                        edges.push((*chunk_position, element_position));
+                    }
+                }
+            }
+        }
+
+        for (chunk_position, element_position) in edges {
+            self.mark_edge_neighbours_dirty(chunk_position, element_position);
+        }
+
+        replaced
+    }
+
+    /// The direction (in chunk space) of the chunk edge an element sits on, or zero.
+    fn edge_direction(&self, element_position: LocalCell) -> IVec2 {
+        let edge = |value: i32| {
+            if value == 0 {
+                -1
+            } else if value == self.chunk_size().0 - 1 {
+                1
+            } else {
+                0
+            }
+        };
+
+        // Element positions grow downwards while chunk positions grow upwards
+        IVec2::new(edge(element_position.0.x), -edge(element_position.0.y))
+    }
+
+    /// Wakes up the neighbouring chunks that touch an element on a chunk edge, so elements
+    /// resting on the other side react to the change.
+    fn mark_edge_neighbours_dirty(&self, chunk_position: ChunkPos, element_position: LocalCell) {
+        let edge = self.edge_direction(element_position);
+        if edge == IVec2::ZERO {
+            return;
+        }
+
+        for dir in DIRECTIONS.iter() {
+            if *dir == IVec2::ZERO
+                || (dir.x != 0 && dir.x != edge.x)
+                || (dir.y != 0 && dir.y != edge.y)
+            {
+                continue;
+            }
+
+            if let Some(mut chunk) = self.get_chunk_mut(chunk_position.offset(*dir)) {
+                let (_, neighbour_position) =
+                    LocalCell(element_position.0 + IVec2::new(dir.x, -dir.y))
+                        .split(self.chunk_size());
+                chunk.mark_point_dirty(neighbour_position);
+            }
+        }
+    }
+
+    /// Starts or stops remembering which cells keep each chunk awake, which costs a little
+    /// time every tick.
+    pub fn track_wakers(&mut self, track: bool) {
+        self.track_wakers = track;
+        for chunk in self.chunks.values() {
+            chunk.write().track_wakers(track);
+        }
+    }
+
+    pub fn mark_chunks_surrounding_as_dirty(&mut self, position: ChunkPos) {
         for dir in DIRECTIONS.iter() {
             if dir == &IVec2::ZERO {
                 continue;
             }
 
-            let chunk_position = position + *dir;
-            if let Some(chunk) = self.chunks.get(&chunk_position) {
-                chunk.write().mark_dirty_everything();
+            if let Some(mut chunk) = self.get_chunk_mut(position.offset(*dir)) {
+                chunk.mark_dirty_everything();
             }
         }
     }
 
+    /// An API for the chunk at `position` and its neighbours, which stay locked until it's
+    /// dropped.
+    fn local_api(&self, position: ChunkPos, seed: u64) -> LocalApi {
+        let chunks = DIRECTIONS.map(|dir| self.get_shared_chunk(position.offset(dir)));
+
+        LocalApi::new(
+            position,
+            self.wframe,
+            Default::default(),
+            chunks,
+            self.context(),
+            seed,
+        )
+    }
+
+    /// What the [`LocalApi`]s of this sandbox share.
+    pub fn context(&self) -> WorldContext {
+        WorldContext {
+            elements: self.elements.clone(),
+            gravity: self.gravity.clone(),
+            settings: self.settings,
+        }
+    }
+
+    /// Adds the chunks created through a [`LocalApi`].
+    fn insert_new_chunks(&mut self, chunks: Vec<SharedChunk>) {
+        for chunk in chunks.into_iter() {
+            let pos = {
+                let mut chunk = chunk.write();
+                chunk.track_wakers(self.track_wakers);
+                chunk.position
+            };
+            self.chunks.insert(pos, chunk);
+            self.fresh_chunks.push(pos);
+            self.mark_chunks_surrounding_as_dirty(pos);
+        }
+    }
+
+    /// Runs a hook of the behaviour of `element`, which is (or was) at `position`.
+    fn run_hook(
+        &mut self,
+        position: WorldCell,
+        element: Element,
+        hook: impl FnOnce(&dyn ElementBehavior, &mut LocalApi),
+    ) {
+        let elements = self.elements.clone();
+        let Some(behavior) = elements.behavior(element.kind) else {
+            return;
+        };
+
+        // Seeded from the position so hooks don't shift the random stream of the ticks
+        let seed = ((position.0.x as u32 as u64) << 32) | position.0.y as u32 as u64;
+        let size = self.chunk_size();
+        let mut api = self.local_api(position.chunk(size), seed);
+        api.element = (element, position.local(size).0);
+        hook(behavior.as_ref(), &mut api);
+
+        let new_chunks = std::mem::take(&mut api.new_chunks);
+        self.particles.append(&mut api.launched);
+        self.explosions.append(&mut api.explosions);
+        drop(api);
+        self.insert_new_chunks(new_chunks);
+    }
+
     pub fn tick(&mut self) {
         if !self.active {
             return;
         }
-        self.wframe = self.wframe.wrapping_add(1);
+
+        // Explosions and emitters create and destroy elements on purpose, so they run before
+        // the census is taken
+        self.tick_explosions();
+        self.tick_emitters();
+        let census = self.check_conservation.then(|| ElementCensus::take(self));
+        self.wframe += 1;
         let wframe = self.wframe;
 
-        let chunk_positions = self.chunks.keys().copied().collect::<Vec<_>>();
+        self.tick_bodies();
+
+        // Cloned once, so ticking elements doesn't touch the reference count
+        let elements = self.elements.clone();
+
+        // Sorted so that the update order (and with it the RNG stream) doesn't depend on the hash map
+        let mut chunk_positions = self.chunks.keys().copied().collect::<Vec<_>>();
+        chunk_positions.sort_by_key(|pos| (pos.0.y, pos.0.x));
 
         // Tick every chunk
         let mut new_chunks = Vec::with_capacity(16);
+        let mut conversions = Vec::new();
         for pos in chunk_positions.iter() {
             let chunk = self.chunks.get(pos).unwrap().read();
-            let dirty = chunk.dirty_rect();
             if !chunk.active() {
                 continue;
             }
 
+            // Cells outside of the chunk or the bounds are never updated
+            let dirty = chunk
+                .dirty_rect()
+                .intersection(&self.settings.local_area(*pos));
+
             // Important because Rust don't automatically drop this until the next line
             drop(chunk);
 
-            let unsafe_chunk_list = DIRECTIONS
-                .map(|dir| match self.chunks.get(&(*pos + dir)) {
-                    Some(cell) => Some(cell.clone()),
-                    None => None,
-                })
-                .into_iter()
-                .collect::<Vec<Option<SharedChunk>>>();
-            let mut local_api = LocalApi::new(*pos, wframe, Default::default(), unsafe_chunk_list);
-
-            if wframe % 2 == 0 {
-                for x in dirty.min.x..dirty.max.x {
-                    for y in dirty.min.y..dirty.max.y {
-                        tick_element((x, y).into(), &mut local_api);
+            let seed = self.rng.random();
+            let mut local_api = self.local_api(*pos, seed);
+
+            // Row by row like the chunk stores its cells, alternating the direction along the
+            // rows so elements don't drift to one side
+            for y in dirty.min.y..dirty.max.y {
+                if wframe.is_multiple_of(2) {
+                    for x in dirty.min.x..dirty.max.x {
+                        tick_element((x, y).into(), &mut local_api, &elements);
                     }
-                }
-            } else {
-                for x in (dirty.min.x..dirty.max.x).rev() {
-                    for y in dirty.min.y..dirty.max.y {
-                        tick_element((x, y).into(), &mut local_api);
+                } else {
+                    for x in (dirty.min.x..dirty.max.x).rev() {
+                        tick_element((x, y).into(), &mut local_api, &elements);
                     }
                 }
             }
 
-            let mut chunk = self.chunks.get(pos).unwrap().write();
-            chunk.current_dirty_rect = chunk.next_dirty_rect;
-            chunk.next_dirty_rect.clear();
-            drop(chunk);
-
-            for chunk_index in local_api.new_chunks.drain(..) {
-                let chunk = local_api.chunks[chunk_index].clone().unwrap();
-                new_chunks.push(chunk);
-            }
+            new_chunks.append(&mut local_api.new_chunks);
+            self.particles.append(&mut local_api.launched);
+            self.explosions.append(&mut local_api.explosions);
+            conversions.append(&mut local_api.conversions);
 
+            // Lets go of the chunks
             drop(local_api);
+
+            self.chunks.get(pos).unwrap().write().swap_dirty_rects();
         }
 
-        // Add new chunks
-        for chunk in new_chunks.into_iter() {
-            let pos = chunk.read().position;
-            self.chunks.insert(pos, chunk);
-            self.fresh_chunks.push(pos);
-            self.mark_chunks_surrounding_as_dirty(pos);
+        self.insert_new_chunks(new_chunks);
+        self.tick_particles();
+
+        if let Some(mut census) = census {
+            census.expect(&conversions);
+            for discrepancy in census.compare(&ElementCensus::take(self)) {
+                error!("Tick {wframe} broke element conservation: {discrepancy}");
+            }
         }
     }
 }
 
-fn tick_element(position: IVec2, api: &mut LocalApi) {
-    if Chunk::is_outside(position) {
-        return;
-    }
-
+fn tick_element(position: IVec2, api: &mut LocalApi, elements: &ElementRegistry) {
     let mut element = api.get_element(position);
     if element.kind == ElementKind::Air || api.wframe == element.wframe {
         return;
@@ -152,72 +462,7 @@ fn tick_element(position: IVec2, api: &mut LocalApi) {
     api.element.1 = position;
     api.set_wframe(api.wframe);
 
-    match api.element.0.kind {
-        ElementKind::Sand => tick_sand(position, api),
-        ElementKind::Water => tick_water(position, api),
-        _ => {}
+    if let Some(behavior) = elements.behavior(element.kind) {
+        behavior.tick(api);
     }
 }
-
-fn tick_sand(position: IVec2, api: &mut LocalApi) {
-    let dir = if api.element.0.velocity.x == 0.0 {
-        api.random_direction()
-    } else {
-        api.element.0.velocity.x.signum() as i32
-    };
-
-    let can_move_down = api.can_move_to((position.x, position.y + 1).into());
-
-    if can_move_down {
-        api.update_element(|element| element.velocity.x *= 0.6);
-        api.accelerate(0.0, 0.5);
-    } else if api.can_move_to((position.x + dir, position.y + 1).into())
-        && api.can_move_to((position.x + dir, position.y).into())
-    {
-        api.accelerate(0.6 * (dir as f32), 0.5);
-    } else if api.can_move_to((position.x - dir, position.y + 1).into())
-        && api.can_move_to((position.x - dir, position.y).into())
-    {
-        api.accelerate(0.6 * (-dir as f32), 0.5);
-    }
-
-    api.move_element();
-}
-
-fn tick_water(position: IVec2, api: &mut LocalApi) {
-    let dir = api.random_direction();
-
-    let can_move_down = api.can_move_to((position.x, position.y + 1).into());
-
-    if can_move_down {
-        api.update_element(|element| element.velocity.x *= 0.4);
-        api.accelerate(0.0, 0.5);
-    } else if api.can_move_to((position.x + dir, position.y + 1).into())
-        && api.can_move_to((position.x + dir, position.y).into())
-    {
-        api.accelerate(0.5 * (dir as f32), 0.5);
-    } else if api.can_move_to((position.x - dir, position.y + 1).into())
-        && api.can_move_to((position.x - dir, position.y).into())
-    {
-        api.accelerate(0.5 * (-dir as f32), 0.5);
-    } else {
-        let dir = if api.element.0.velocity.x == 0.0 {
-            api.random_direction()
-        } else {
-            api.element.0.velocity.x.signum() as i32
-        };
-
-        let mut speed = 0.6;
-        if api.get_element((position.x, position.y + 1).into()).kind == api.element.0.kind {
-            speed += 0.5;
-        }
-
-        if api.can_move_to(((position.x + dir), position.y).into()) {
-            api.accelerate(speed * (dir as f32), 0.0);
-        } else if api.can_move_to(((position.x - dir), position.y).into()) {
-            api.accelerate(speed * (-dir as f32), 0.0);
-        }
-    }
-
-    api.move_element();
-}
@@ -0,0 +1,248 @@
+//! Saving the world to a file and loading it back.
+//!
+//! A save holds the elements of every chunk, the [emitters](Emitter) and the
+//! [gravity](GravityField). Rigid bodies are saved
+//! as the cells they cover, elements flying as particles and queued explosions are left out.
+
+use std::{fmt, fs, path::Path, sync::Arc};
+
+use bevy::math::{IVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Rect, coordinates::ChunkPos};
+
+use super::*;
+
+/// Where the quick save goes, next to the executable like the input bindings.
+pub const SAVE_PATH: &str = "world.ron";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldSave {
+    /// The names of the element kinds used by the save. Cells refer to kinds by their index in
+    /// here, so custom kinds survive being registered in a different order.
+    pub kinds: Vec<String>,
+    pub chunks: Vec<SavedChunk>,
+    pub emitters: Vec<SavedEmitter>,
+    /// Missing from saves made before gravity could be changed.
+    #[serde(default)]
+    pub gravity: Gravity,
+    #[serde(default)]
+    pub gravity_zones: Vec<SavedGravityZone>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedChunk {
+    pub position: IVec2,
+    /// Indices into [`WorldSave::kinds`], row by row.
+    pub cells: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedEmitter {
+    pub min: IVec2,
+    pub max: IVec2,
+    pub action: SavedEmitterAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SavedEmitterAction {
+    Spawn {
+        element: u16,
+        interval: u32,
+        velocity: Vec2,
+    },
+    Drain,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedGravityZone {
+    pub min: IVec2,
+    pub max: IVec2,
+    pub gravity: Gravity,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(String),
+    Invalid(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => err.fmt(f),
+            Self::Format(message) => message.fmt(f),
+            Self::Invalid(message) => message.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl WorldSave {
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        let contents = fs::read_to_string(path)?;
+        ron::from_str(&contents).map_err(|err| SaveError::Format(err.to_string()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        let contents = ron::to_string(self).map_err(|err| SaveError::Format(err.to_string()))?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+impl Sandbox {
+    pub fn save(&self) -> WorldSave {
+        let elements = &*self.elements;
+        let kinds = elements.kinds().collect::<Vec<_>>();
+        let index =
+            |kind: ElementKind| kinds.iter().position(|other| *other == kind).unwrap() as u16;
+
+        // Sorted so the same world always makes the same save
+        let mut positions = self.chunks.keys().copied().collect::<Vec<_>>();
+        positions.sort_by_key(|position| (position.0.y, position.0.x));
+
+        let chunks = positions
+            .into_iter()
+            .map(|position| {
+                let chunk = self.get_chunk(position).unwrap();
+                let cells = chunk.kinds().iter().map(|kind| index(*kind)).collect();
+                SavedChunk {
+                    position: position.0,
+                    cells,
+                }
+            })
+            .collect();
+
+        let emitters = self
+            .emitters
+            .iter()
+            .map(|emitter| SavedEmitter {
+                min: emitter.area.min,
+                max: emitter.area.max,
+                action: match emitter.action {
+                    EmitterAction::Spawn {
+                        element,
+                        interval,
+                        velocity,
+                    } => SavedEmitterAction::Spawn {
+                        element: index(element),
+                        interval,
+                        velocity,
+                    },
+                    EmitterAction::Drain => SavedEmitterAction::Drain,
+                },
+            })
+            .collect();
+
+        WorldSave {
+            kinds: kinds
+                .iter()
+                .map(|kind| elements.name(*kind).to_string())
+                .collect(),
+            chunks,
+            emitters,
+            gravity: self.gravity.world,
+            gravity_zones: self
+                .gravity
+                .zones
+                .iter()
+                .map(|zone| SavedGravityZone {
+                    min: zone.area.min,
+                    max: zone.area.max,
+                    gravity: zone.gravity,
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the whole world with a saved one. Fails without touching the world if the save
+    /// uses an element that isn't registered, or has chunks outside the bounds of the world.
+    pub fn load(&mut self, save: &WorldSave) -> Result<(), SaveError> {
+        let kinds = save
+            .kinds
+            .iter()
+            .map(|name| {
+                self.elements
+                    .kind_by_name(name)
+                    .ok_or_else(|| SaveError::Invalid(format!("Unknown element {name}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let kind = |index: u16| {
+            kinds
+                .get(index as usize)
+                .copied()
+                .ok_or_else(|| SaveError::Invalid(format!("Unknown element index {index}")))
+        };
+
+        let size = self.chunk_size();
+        let mut chunks = Vec::with_capacity(save.chunks.len());
+        for saved in save.chunks.iter() {
+            if !self.settings().contains_chunk(ChunkPos(saved.position)) {
+                return Err(SaveError::Invalid(format!(
+                    "Chunk {} is outside of the world",
+                    saved.position
+                )));
+            }
+            if saved.cells.len() != size.cells() {
+                return Err(SaveError::Invalid(format!(
+                    "Chunk {} has {} cells instead of {}",
+                    saved.position,
+                    saved.cells.len(),
+                    size.cells()
+                )));
+            }
+
+            let mut chunk = Chunk::new(ChunkPos(saved.position), size);
+            for (cell, index) in saved.cells.iter().enumerate() {
+                chunk.set_element(size.to_position(cell), self.elements.element(kind(*index)?));
+            }
+            chunks.push(chunk);
+        }
+
+        let mut emitters = Vec::with_capacity(save.emitters.len());
+        for saved in save.emitters.iter() {
+            let action = match saved.action {
+                SavedEmitterAction::Spawn {
+                    element,
+                    interval,
+                    velocity,
+                } => EmitterAction::Spawn {
+                    element: kind(element)?,
+                    interval,
+                    velocity,
+                },
+                SavedEmitterAction::Drain => EmitterAction::Drain,
+            };
+            emitters.push(Emitter::new(Rect::new(saved.min, saved.max), action));
+        }
+
+        self.chunks.clear();
+        self.fresh_chunks.clear();
+        self.bodies.clear();
+        self.particles.clear();
+        self.explosions.clear();
+        self.emitters = emitters;
+        self.gravity = Arc::new(GravityField {
+            world: save.gravity,
+            zones: save
+                .gravity_zones
+                .iter()
+                .map(|zone| GravityZone {
+                    area: Rect::new(zone.min, zone.max),
+                    gravity: zone.gravity,
+                })
+                .collect(),
+        });
+        for chunk in chunks {
+            self.add_chunk(chunk);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+//! Elements whose behaviour is written in [Rhai](https://rhai.rs) instead of Rust.
+//!
+//! Every `.rhai` file in [`SCRIPTS_DIR`] defines one element with a few top-level constants and
+//! a `tick` function that is called for every element of that kind, once per tick:
+//!
+//! ```rhai
+//! const NAME = "Snow";
+//! const COLOR = [235, 240, 250];
+//! const DENSITY = 40;
+//! const CATEGORY = "powder"; // optional: powder, liquid, gas, solid or special
+//! const HARDNESS = 10; // optional, how much of an explosion it withstands
+//!
+//! fn tick(api) {
+//!     if api.can_move_to(0, 1) {
+//!         api.accelerate(0.0, 0.2);
+//!     }
+//!     api.move_element();
+//! }
+//! ```
+//!
+//! `api` only exposes a safe subset of [`LocalApi`], with positions relative to the element
+//! being ticked (y grows downwards) and at most [`SCRIPT_REACH`] cells away:
+//!
+//! * `api.get_element(dx, dy)`: the name of the element's kind.
+//! * `api.can_move_to(dx, dy)`
+//! * `api.set_element(dx, dy, name)`
+//! * `api.accelerate(x, y)`
+//! * `api.move_element()`
+//! * `api.random_direction()`: `-1` or `1`.
+//! * `api.explode(dx, dy, radius, power)`: goes off at the start of the next tick, see
+//!   [`Sandbox::explode`].
+//! * `api.velocity_x` and `api.velocity_y`
+//! * `api.gravity_x` and `api.gravity_y`: the acceleration of gravity where the element is, see
+//!   [`Gravity::vector`].
+//!
+//! Scripts may also define the hooks of [`ElementBehavior`]: `fn on_placed(api)`,
+//! `fn on_destroyed(api)` and `fn on_contact(api, dx, dy)`.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{
+    log::{error, info},
+    math::IVec2,
+};
+use rhai::{
+    CallFnOptions, Dynamic, Engine, EvalAltResult, ImmutableString, ParseError, Scope, AST, FLOAT,
+    INT,
+};
+
+use super::*;
+
+/// Where the element scripts are loaded from, and watched for changes.
+pub const SCRIPTS_DIR: &str = "elements";
+
+/// How far from their element scripts can look or write.
+pub const SCRIPT_REACH: INT = 16;
+
+/// The hardness of script elements that don't declare one.
+const DEFAULT_HARDNESS: u8 = 10;
+
+/// Scripts that loop forever would otherwise freeze the simulation.
+const MAX_OPERATIONS: u64 = 10_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The [`ElementBehavior`] of a scripted element.
+pub struct ElementScript {
+    name: String,
+    engine: Arc<Engine>,
+    ast: AST,
+    /// Which of the optional hooks the script defines.
+    on_placed: bool,
+    on_destroyed: bool,
+    on_contact: bool,
+    /// A broken script fails for every element on every tick, so only the first error is logged.
+    failed: AtomicBool,
+}
+
+impl ElementScript {
+    /// Calls a function of the script with `api` as its first argument.
+    fn call(&self, api: &mut LocalApi, function: &str, args: &[Dynamic]) {
+        // Scripts can only hold values they own, so they get a fork that takes over the chunks
+        // until it is joined back
+        let script_api = ScriptApi(Arc::new(Mutex::new(api.fork())));
+        let args = [Dynamic::from(script_api.clone())]
+            .into_iter()
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false),
+            &mut Scope::new(),
+            &self.ast,
+            function,
+            args,
+        );
+        api.join(&mut script_api.0.lock().unwrap());
+
+        if let Err(err) = result {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                error!("The {} script failed in {function}: {err}", self.name);
+            }
+        }
+    }
+}
+
+impl ElementBehavior for ElementScript {
+    fn tick(&self, api: &mut LocalApi) {
+        self.call(api, "tick", &[]);
+    }
+
+    fn on_placed(&self, api: &mut LocalApi) {
+        if self.on_placed {
+            self.call(api, "on_placed", &[]);
+        }
+    }
+
+    fn on_destroyed(&self, api: &mut LocalApi) {
+        if self.on_destroyed {
+            self.call(api, "on_destroyed", &[]);
+        }
+    }
+
+    fn on_contact(&self, api: &mut LocalApi, other: IVec2) {
+        if self.on_contact {
+            let offset = other - api.element.1;
+            self.call(
+                api,
+                "on_contact",
+                &[
+                    Dynamic::from_int(offset.x as INT),
+                    Dynamic::from_int(offset.y as INT),
+                ],
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(std::io::Error),
+    Parse(ParseError),
+    Runtime(Box<EvalAltResult>),
+    Invalid(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => err.fmt(f),
+            Self::Parse(err) => err.fmt(f),
+            Self::Runtime(err) => err.fmt(f),
+            Self::Invalid(message) => message.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for ScriptError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ParseError> for ScriptError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(err: Box<EvalAltResult>) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// The [`LocalApi`] as seen by scripts.
+#[derive(Clone)]
+struct ScriptApi(Arc<Mutex<LocalApi>>);
+
+impl ScriptApi {
+    fn with<T>(&self, callback: impl FnOnce(&mut LocalApi) -> T) -> T {
+        callback(&mut self.0.lock().unwrap())
+    }
+}
+
+/// Turns a script position, relative to the ticked element, into a [`LocalApi`] one.
+fn offset(api: &LocalApi, dx: INT, dy: INT) -> ScriptResult<IVec2> {
+    if dx.abs() > SCRIPT_REACH || dy.abs() > SCRIPT_REACH {
+        return Err(format!("({dx}, {dy}) is more than {SCRIPT_REACH} cells away").into());
+    }
+
+    Ok(api.element.1 + IVec2::new(dx as i32, dy as i32))
+}
+
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| info!("{text}"));
+
+    engine
+        .register_type_with_name::<ScriptApi>("LocalApi")
+        .register_fn(
+            "get_element",
+            |api: &mut ScriptApi, dx: INT, dy: INT| -> ScriptResult<ImmutableString> {
+                api.with(|api| {
+                    let kind = api.get_element(offset(api, dx, dy)?).kind;
+                    Ok(api.elements.name(kind).into())
+                })
+            },
+        )
+        .register_fn(
+            "can_move_to",
+            |api: &mut ScriptApi, dx: INT, dy: INT| -> ScriptResult<bool> {
+                api.with(|api| Ok(api.can_move_to(offset(api, dx, dy)?)))
+            },
+        )
+        .register_fn(
+            "set_element",
+            |api: &mut ScriptApi, dx: INT, dy: INT, name: &str| -> ScriptResult<()> {
+                api.with(|api| {
+                    let position = offset(api, dx, dy)?;
+                    let Some(kind) = api.elements.kind_by_name(name) else {
+                        return Err(format!("Unknown element {name}").into());
+                    };
+
+                    // Don't tick the new element again if the scan reaches it later this tick
+                    let element = Element {
+                        wframe: api.wframe,
+                        ..api.elements.element(kind)
+                    };
+                    api.replace_element(position, element);
+                    if position == api.element.1 {
+                        api.element.0 = element;
+                    }
+                    Ok(())
+                })
+            },
+        )
+        .register_fn("accelerate", |api: &mut ScriptApi, x: FLOAT, y: FLOAT| {
+            api.with(|api| api.accelerate(x as f32, y as f32))
+        })
+        .register_fn("move_element", |api: &mut ScriptApi| {
+            api.with(LocalApi::move_element)
+        })
+        .register_fn("random_direction", |api: &mut ScriptApi| {
+            api.with(|api| api.random_direction() as INT)
+        })
+        .register_fn(
+            "explode",
+            |api: &mut ScriptApi,
+             dx: INT,
+             dy: INT,
+             radius: FLOAT,
+             power: FLOAT|
+             -> ScriptResult<()> {
+                api.with(|api| {
+                    let position = offset(api, dx, dy)?;
+                    api.explode(position, radius as f32, power as f32);
+                    Ok(())
+                })
+            },
+        )
+        .register_get("velocity_x", |api: &mut ScriptApi| {
+            api.with(|api| api.element.0.velocity.x as FLOAT)
+        })
+        .register_get("velocity_y", |api: &mut ScriptApi| {
+            api.with(|api| api.element.0.velocity.y as FLOAT)
+        })
+        .register_get("gravity_x", |api: &mut ScriptApi| {
+            api.with(|api| api.gravity().vector().x as FLOAT)
+        })
+        .register_get("gravity_y", |api: &mut ScriptApi| {
+            api.with(|api| api.gravity().vector().y as FLOAT)
+        });
+
+    engine
+}
+
+/// Compiles a script and reads the element it declares.
+pub fn compile(
+    engine: &Arc<Engine>,
+    source: &str,
+) -> Result<(CustomElement, ElementScript), ScriptError> {
+    let ast = engine.compile(source)?;
+    let mut scope = Scope::new();
+    engine.run_ast_with_scope(&mut scope, &ast)?;
+
+    let name = constant::<ImmutableString>(&scope, "NAME")?.to_string();
+    if ElementKind::ALL.iter().any(|kind| kind.name() == name) {
+        return Err(ScriptError::Invalid(format!(
+            "{name} is a built-in element"
+        )));
+    }
+
+    let color = match constant::<rhai::Array>(&scope, "COLOR")?
+        .iter()
+        .map(channel)
+        .collect::<Option<Vec<_>>>()
+        .as_deref()
+    {
+        Some(&[r, g, b]) => (r, g, b),
+        _ => {
+            return Err(ScriptError::Invalid(
+                "COLOR must be an array of three numbers between 0 and 255".to_string(),
+            ))
+        }
+    };
+
+    let density = u8::try_from(constant::<INT>(&scope, "DENSITY")?).map_err(|_| {
+        ScriptError::Invalid("DENSITY must be a number between 0 and 255".to_string())
+    })?;
+
+    let hardness = match scope.get_value::<INT>("HARDNESS") {
+        None => DEFAULT_HARDNESS,
+        Some(hardness) => u8::try_from(hardness).map_err(|_| {
+            ScriptError::Invalid("HARDNESS must be a number between 0 and 255".to_string())
+        })?,
+    };
+
+    let category = match scope.get_value::<ImmutableString>("CATEGORY") {
+        None => ElementCategory::Special,
+        Some(category) => ElementCategory::ALL
+            .into_iter()
+            .find(|other| format!("{other:?}").eq_ignore_ascii_case(&category))
+            .ok_or_else(|| ScriptError::Invalid(format!("Unknown category {category}")))?,
+    };
+
+    let defines = |name: &str, params: usize| {
+        ast.iter_functions()
+            .any(|function| function.name == name && function.params.len() == params)
+    };
+    if !defines("tick", 1) {
+        return Err(ScriptError::Invalid(
+            "Missing a `fn tick(api)` function".to_string(),
+        ));
+    }
+
+    let script = ElementScript {
+        name: name.clone(),
+        engine: engine.clone(),
+        on_placed: defines("on_placed", 1),
+        on_destroyed: defines("on_destroyed", 1),
+        on_contact: defines("on_contact", 3),
+        failed: AtomicBool::new(false),
+        ast,
+    };
+
+    Ok((
+        CustomElement {
+            name,
+            category,
+            color,
+            density,
+            hardness,
+        },
+        script,
+    ))
+}
+
+fn constant<T: Clone + Send + Sync + 'static>(scope: &Scope, name: &str) -> Result<T, ScriptError> {
+    scope
+        .get_value::<T>(name)
+        .ok_or_else(|| ScriptError::Invalid(format!("Missing or invalid `const {name}`")))
+}
+
+fn channel(value: &Dynamic) -> Option<u8> {
+    u8::try_from(value.as_int().ok()?).ok()
+}
@@ -0,0 +1,106 @@
+//! Exact copies of the state of a [`Sandbox`], that it can be put back to and resumed from.
+//!
+//! Unlike a [`WorldSave`], a snapshot keeps everything the simulation needs to carry on the
+//! same way: the velocities of the elements, which chunks are awake, bodies, flying particles
+//! and queued explosions. It still refers to element kinds by their index, so it only fits a
+//! sandbox with the same elements registered.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{common::Rect, coordinates::ChunkPos};
+
+use super::*;
+
+/// A chunk as an [exact](Precision::Exact) [`ChunkDelta`], which stores runs of identical
+/// elements once, with the dirty rects it had.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SnapshotChunk {
+    delta: ChunkDelta,
+    current_dirty_rect: Rect,
+    next_dirty_rect: Rect,
+}
+
+/// Everything about a [`Sandbox`] that changes as it runs, apart from its random numbers and
+/// the registered elements.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub wframe: u64,
+    chunks: Vec<SnapshotChunk>,
+    gravity: GravityField,
+    bodies: Vec<RigidBody>,
+    particles: Vec<Particle>,
+    explosions: Vec<Explosion>,
+    emitters: Vec<Emitter>,
+}
+
+impl Sandbox {
+    pub fn snapshot(&self) -> Snapshot {
+        // Sorted so that restoring adds the chunks in the same order every time
+        let mut positions = self.chunks.keys().copied().collect::<Vec<_>>();
+        positions.sort_by_key(|position| (position.0.y, position.0.x));
+
+        Snapshot {
+            wframe: self.wframe,
+            chunks: positions
+                .into_iter()
+                .map(|position| {
+                    let chunk = self.get_chunk(position).unwrap();
+                    SnapshotChunk {
+                        delta: ChunkDelta::whole(&chunk, Precision::Exact),
+                        current_dirty_rect: chunk.current_dirty_rect,
+                        next_dirty_rect: chunk.next_dirty_rect,
+                    }
+                })
+                .collect(),
+            gravity: (*self.gravity).clone(),
+            bodies: self.bodies.clone(),
+            particles: self.particles.clone(),
+            explosions: self.explosions.clone(),
+            emitters: self.emitters.clone(),
+        }
+    }
+
+    /// Puts the whole world back to how it was when `snapshot` was taken. The chunks are
+    /// replaced, so they all show up as fresh ones. Fails without touching the world if the
+    /// snapshot doesn't decode or fit the bounds, like one read from a file made for another
+    /// world.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SaveError> {
+        let size = self.chunk_size();
+        let mut chunks = Vec::with_capacity(snapshot.chunks.len());
+        for saved in snapshot.chunks.iter() {
+            let position = ChunkPos(saved.delta.position);
+            if !self.settings().contains_chunk(position) {
+                return Err(SaveError::Invalid(format!(
+                    "Chunk {} is outside of the world",
+                    saved.delta.position
+                )));
+            }
+            let mut chunk = Chunk::new(position, size);
+            saved.delta.apply(&mut chunk)?;
+            chunks.push(chunk);
+        }
+
+        self.chunks.clear();
+        self.fresh_chunks.clear();
+        for chunk in chunks {
+            self.add_chunk(chunk);
+        }
+
+        // Adding a chunk wakes up its neighbours, which they weren't
+        for saved in snapshot.chunks.iter() {
+            let mut chunk = self.get_chunk_mut(ChunkPos(saved.delta.position)).unwrap();
+            chunk.current_dirty_rect = saved.current_dirty_rect;
+            chunk.next_dirty_rect = saved.next_dirty_rect;
+        }
+
+        self.wframe = snapshot.wframe;
+        self.gravity = Arc::new(snapshot.gravity.clone());
+        self.bodies = snapshot.bodies.clone();
+        self.particles = snapshot.particles.clone();
+        self.explosions = snapshot.explosions.clone();
+        self.emitters = snapshot.emitters.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use bevy::{app::App, math::IVec2};
+
+use crate::{common::Rect, coordinates::WorldCell, simulation::plugin::ElementAppExt};
+
+use super::*;
+
+fn custom(name: &str) -> CustomElement {
+    CustomElement {
+        name: name.to_string(),
+        category: ElementCategory::Special,
+        color: (255, 255, 255),
+        density: 100,
+        hardness: 10,
+    }
+}
+
+/// Falls like sand and counts how often each hook ran.
+#[derive(Default)]
+struct Counting {
+    placed: AtomicUsize,
+    destroyed: AtomicUsize,
+    contacts: AtomicUsize,
+}
+
+impl ElementBehavior for Arc<Counting> {
+    fn tick(&self, api: &mut LocalApi) {
+        SandBehavior.tick(api);
+    }
+
+    fn on_placed(&self, _api: &mut LocalApi) {
+        self.placed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_destroyed(&self, _api: &mut LocalApi) {
+        self.destroyed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_contact(&self, api: &mut LocalApi, other: IVec2) {
+        assert_eq!(api.get_element(other).kind, ElementKind::Stone);
+        self.contacts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Moves upwards instead of falling.
+struct Rising;
+
+impl ElementBehavior for Rising {
+    fn tick(&self, api: &mut LocalApi) {
+        api.accelerate(0.0, -0.5);
+        api.move_element();
+    }
+}
+
+/// Destroys whatever is right below it.
+struct Eating;
+
+impl ElementBehavior for Eating {
+    fn tick(&self, api: &mut LocalApi) {
+        let below = api.element.1 + IVec2::Y;
+        api.replace_element(below, Element::default());
+    }
+}
+
+struct Inert;
+
+impl ElementBehavior for Inert {
+    fn tick(&self, _api: &mut LocalApi) {}
+}
+
+fn sandbox_with(elements: ElementRegistry) -> Sandbox {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.elements = Arc::new(elements);
+    sandbox
+}
+
+#[test]
+fn registered_behavior_ticks_its_kind() {
+    let mut elements = ElementRegistry::default();
+    let rising = elements.register_with_behavior(custom("Rising"), Rising);
+    let mut sandbox = sandbox_with(elements);
+    sandbox.set_element(WorldCell::new(5, 40), sandbox.elements.element(rising));
+
+    for _ in 0..10 {
+        sandbox.tick();
+    }
+
+    assert_eq!(kind_at(&sandbox, 5, 40), Some(ElementKind::Air));
+    let risen = sandbox
+        .iter_region(Rect::new(IVec2::new(5, 0), IVec2::new(6, 40)))
+        .find(|(_, element)| element.kind == rising);
+    assert!(risen.is_some());
+}
+
+#[test]
+fn hooks_run_when_elements_are_placed_hit_and_destroyed() {
+    let counting = Arc::new(Counting::default());
+    let mut elements = ElementRegistry::default();
+    let kind = elements.register_with_behavior(custom("Counting"), counting.clone());
+    let mut sandbox = sandbox_with(elements);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 60), IVec2::new(10, 61)),
+        element(ElementKind::Stone),
+    );
+
+    let position = WorldCell::new(5, 50);
+    sandbox.set_element(position, sandbox.elements.element(kind));
+    sandbox.set_element(position, sandbox.elements.element(kind));
+    assert_eq!(counting.placed.load(Ordering::Relaxed), 1);
+
+    for _ in 0..20 {
+        sandbox.tick();
+    }
+    assert_eq!(kind_at(&sandbox, 5, 59), Some(kind));
+    assert!(counting.contacts.load(Ordering::Relaxed) > 0);
+
+    sandbox.set_element(WorldCell::new(5, 59), Element::default());
+    assert_eq!(counting.destroyed.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn replacing_an_element_from_a_behavior_runs_its_hooks() {
+    let counting = Arc::new(Counting::default());
+    let mut elements = ElementRegistry::default();
+    let victim = elements.register_with_behavior(custom("Counting"), counting.clone());
+    let eating = elements.register_with_behavior(custom("Eating"), Eating);
+    let mut sandbox = sandbox_with(elements);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(5, 60), IVec2::new(6, 61)),
+        element(ElementKind::Stone),
+    );
+    sandbox.set_element(WorldCell::new(5, 59), sandbox.elements.element(victim));
+    sandbox.set_element(WorldCell::new(5, 58), sandbox.elements.element(eating));
+
+    sandbox.tick();
+
+    assert_eq!(kind_at(&sandbox, 5, 59), Some(ElementKind::Air));
+    assert_eq!(counting.destroyed.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn built_in_behaviors_can_be_replaced() {
+    let mut elements = ElementRegistry::default();
+    elements.set_behavior(ElementKind::Sand, Inert);
+    let mut sandbox = sandbox_with(elements);
+    sandbox.set_element(WorldCell::new(5, 5), element(ElementKind::Sand));
+
+    for _ in 0..10 {
+        sandbox.tick();
+    }
+
+    assert_eq!(kind_at(&sandbox, 5, 5), Some(ElementKind::Sand));
+}
+
+#[test]
+fn plugins_register_elements_through_the_app() {
+    let mut app = App::new();
+    app.register_element(custom("Rising"), Rising)
+        .set_element_behavior(ElementKind::Water, Inert);
+
+    let elements = app.world().resource::<ElementRegistry>();
+    let rising = elements.kind_by_name("Rising").unwrap();
+    assert!(elements.behavior(rising).is_some());
+    assert!(elements.behavior(ElementKind::Sand).is_some());
+}
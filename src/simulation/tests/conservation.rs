@@ -0,0 +1,172 @@
+//! Elements must never be created or destroyed by the simulation itself, no matter where they
+//! sit relative to chunk borders.
+
+use bevy::math::IVec2;
+use proptest::prelude::*;
+
+use crate::{
+    common::directions::DIRECTIONS,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+fn element_kind() -> impl Strategy<Value = ElementKind> {
+    prop_oneof![
+        Just(ElementKind::Sand),
+        Just(ElementKind::Water),
+        Just(ElementKind::Stone),
+    ]
+}
+
+/// Positions anywhere in the world, with extra weight on the bands around chunk borders.
+fn position() -> impl Strategy<Value = WorldCell> {
+    let region = world_region();
+    prop_oneof![
+        (region.min.x..region.max.x, region.min.y..region.max.y)
+            .prop_map(|(x, y)| WorldCell::new(x, y)),
+        (58..70, region.min.y..region.max.y).prop_map(|(x, y)| WorldCell::new(x, y)),
+        (region.min.x..region.max.x, -6..6).prop_map(|(x, y)| WorldCell::new(x, y)),
+        (-6..6, -70..-58).prop_map(|(x, y)| WorldCell::new(x, y)),
+    ]
+}
+
+fn run_scene(seed: u64, placements: &[(WorldCell, ElementKind)], ticks: usize) -> Vec<Discrepancy> {
+    let mut scene = Scene::with_seed(world_region(), seed);
+    for (position, kind) in placements {
+        scene.place(*position, *kind);
+    }
+
+    let before = ElementCensus::take(&scene.sandbox);
+    for _ in 0..ticks {
+        scene.sandbox.tick();
+    }
+
+    before.compare(&ElementCensus::take(&scene.sandbox))
+}
+
+fn format_discrepancies(discrepancies: &[Discrepancy]) -> String {
+    discrepancies
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn random_scenes_conserve_elements(
+        seed in any::<u64>(),
+        placements in prop::collection::vec((position(), element_kind()), 1..400),
+        ticks in 1usize..60,
+    ) {
+        let discrepancies = run_scene(seed, &placements, ticks);
+        prop_assert!(discrepancies.is_empty(), "{}", format_discrepancies(&discrepancies));
+    }
+
+    #[test]
+    fn dense_blocks_on_chunk_corners_conserve_elements(
+        seed in any::<u64>(),
+        kind in element_kind(),
+        corner in prop_oneof![Just(IVec2::new(64, 0)), Just(IVec2::new(0, -64)), Just(IVec2::new(64, -64))],
+        ticks in 1usize..60,
+    ) {
+        let placements = (-4..4)
+            .flat_map(|x| (-4..4).map(move |y| (WorldCell(corner + IVec2::new(x, y)), kind)))
+            .collect::<Vec<_>>();
+
+        let discrepancies = run_scene(seed, &placements, ticks);
+        prop_assert!(discrepancies.is_empty(), "{}", format_discrepancies(&discrepancies));
+    }
+}
+
+#[test]
+fn census_reports_chunk_and_position_of_lost_elements() {
+    let mut scene = Scene::new(world_region());
+    scene.place(WorldCell::new(70, -3), ElementKind::Sand);
+    scene.place(WorldCell::new(2, 2), ElementKind::Water);
+
+    let before = ElementCensus::take(&scene.sandbox);
+    scene.place(WorldCell::new(70, -3), ElementKind::Air);
+
+    let discrepancies = before.compare(&ElementCensus::take(&scene.sandbox));
+    assert_eq!(
+        discrepancies,
+        vec![Discrepancy {
+            kind: ElementKind::Sand,
+            before: 1,
+            after: 0,
+            chunks: vec![ChunkDiscrepancy {
+                position: ChunkPos::new(1, 1),
+                delta: -1,
+                cells: vec![WorldCell::new(70, -3)],
+            }],
+            particles: 0,
+            expected: 0,
+        }]
+    );
+}
+
+#[test]
+fn cells_without_a_chunk_read_as_air() {
+    let sandbox = Sandbox::with_seed(SEED);
+    // The top row of chunks, with nothing loaded above it
+    let center = ChunkPos::new(0, 2);
+    let chunks = DIRECTIONS.map(|dir| sandbox.get_shared_chunk(center.offset(dir)));
+    let api = LocalApi::new(
+        center,
+        0,
+        Default::default(),
+        chunks,
+        sandbox.context(),
+        SEED,
+    );
+
+    assert_eq!(api.get_element(IVec2::new(10, -1)), Element::default());
+}
+
+#[test]
+fn census_lets_elements_change_kind_on_purpose() {
+    let mut scene = Scene::new(world_region());
+    scene.place(WorldCell::new(2, 2), ElementKind::Water);
+    scene.place(WorldCell::new(5, 5), ElementKind::Sand);
+
+    let mut before = ElementCensus::take(&scene.sandbox);
+    before.expect(&[(ElementKind::Water, ElementKind::Stone)]);
+    scene.place(WorldCell::new(2, 2), ElementKind::Stone);
+    scene.place(WorldCell::new(5, 5), ElementKind::Air);
+
+    let discrepancies = before.compare(&ElementCensus::take(&scene.sandbox));
+    let kinds = discrepancies.iter().map(|d| d.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [ElementKind::Sand]);
+}
+
+#[test]
+fn local_api_records_conversions_but_not_moves() {
+    let sandbox = Sandbox::with_seed(SEED);
+    let center = ChunkPos::new(0, 0);
+    let chunks = DIRECTIONS.map(|dir| sandbox.get_shared_chunk(center.offset(dir)));
+    let mut api = LocalApi::new(
+        center,
+        sandbox.wframe,
+        Default::default(),
+        chunks,
+        sandbox.context(),
+        SEED,
+    );
+
+    api.set_element(IVec2::new(5, 5), element(ElementKind::Sand));
+    api.swap_elements(IVec2::new(5, 5), IVec2::new(5, 6), None);
+    api.set_element(IVec2::new(5, 6), element(ElementKind::Water));
+    api.set_element(IVec2::new(5, 6), element(ElementKind::Water));
+
+    assert_eq!(
+        api.conversions,
+        [
+            (ElementKind::Air, ElementKind::Sand),
+            (ElementKind::Sand, ElementKind::Water),
+        ]
+    );
+}
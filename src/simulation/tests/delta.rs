@@ -0,0 +1,191 @@
+use bevy::math::{IVec2, Vec2};
+use proptest::prelude::*;
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, ChunkSize, LocalCell},
+};
+
+use super::*;
+
+fn elements(chunk: &Chunk) -> Vec<Element> {
+    let size = chunk.size();
+    (0..size.cells())
+        .map(|index| chunk.get_element(size.to_position(index)))
+        .collect()
+}
+
+fn moving(kind: ElementKind, velocity: Vec2, wframe: u64) -> Element {
+    Element {
+        velocity,
+        wframe,
+        rest: 3,
+        ..element(kind)
+    }
+}
+
+#[test]
+fn exact_deltas_decode_to_the_same_chunks() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 40), IVec2::new(64, 41)),
+        element(ElementKind::Stone),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(10, 0), IVec2::new(40, 10)),
+        element(ElementKind::Water),
+    );
+    for _ in 0..20 {
+        sandbox.tick();
+    }
+
+    for shared in sandbox.chunks.values() {
+        let chunk = shared.read();
+        let delta = ChunkDelta::whole(&chunk, Precision::Exact);
+        let mut decoded = Chunk::new(chunk.position, chunk.size());
+        assert_eq!(delta.apply(&mut decoded).unwrap(), chunk.size().rect());
+        assert_eq!(elements(&decoded), elements(&chunk));
+    }
+}
+
+#[test]
+fn deltas_only_hold_the_cells_that_changed() {
+    let size = ChunkSize::default();
+    let mut baseline = Chunk::new(ChunkPos::new(2, -1), size);
+    baseline.set_element(LocalCell::new(5, 5), element(ElementKind::Stone));
+    let mut chunk = baseline.clone();
+    chunk.next_dirty_rect.clear();
+
+    chunk.set_element(
+        LocalCell::new(10, 20),
+        moving(ElementKind::Sand, Vec2::new(0.5, 2.0), 9),
+    );
+    chunk.set_element(
+        LocalCell::new(11, 20),
+        moving(ElementKind::Water, Vec2::ZERO, 9),
+    );
+    chunk.set_element(LocalCell::new(5, 5), element(ElementKind::Air));
+
+    let delta = ChunkDelta::encode(
+        &chunk,
+        Some(&baseline),
+        chunk.next_dirty_rect,
+        Precision::Exact,
+    );
+    assert_eq!(delta.cells(), 3);
+    delta.apply(&mut baseline).unwrap();
+    assert_eq!(elements(&baseline), elements(&chunk));
+
+    let unchanged = ChunkDelta::encode(&chunk, Some(&baseline), size.rect(), Precision::Exact);
+    assert_eq!(unchanged.cells(), 0);
+}
+
+#[test]
+fn quantised_deltas_round_velocities_and_leave_out_ticks() {
+    let size = ChunkSize::default();
+    let mut chunk = Chunk::new(ChunkPos::new(0, 0), size);
+    let velocity = Vec2::new(0.3, -1.234);
+    chunk.set_element(
+        LocalCell::new(1, 2),
+        moving(ElementKind::Sand, velocity, 42),
+    );
+
+    let delta = ChunkDelta::whole(&chunk, Precision::Quantised);
+    let mut decoded = Chunk::new(chunk.position, size);
+    delta.apply(&mut decoded).unwrap();
+    let sand = decoded.get_element(LocalCell::new(1, 2));
+    assert_eq!(sand.kind, ElementKind::Sand);
+    assert_eq!(sand.wframe, 0);
+    assert!((sand.velocity - velocity).abs().max_element() <= VELOCITY_STEP / 2.0);
+
+    // Too small a change to show up
+    let baseline = chunk.clone();
+    chunk.update_element(LocalCell::new(1, 2), |sand| sand.velocity.x += 0.001);
+    let delta = ChunkDelta::encode(&chunk, Some(&baseline), size.rect(), Precision::Quantised);
+    assert_eq!(delta.cells(), 0);
+}
+
+#[test]
+fn an_empty_chunk_takes_a_few_bytes() {
+    let chunk = Chunk::new(ChunkPos::new(0, 0), ChunkSize::default());
+    let delta = ChunkDelta::whole(&chunk, Precision::Exact);
+    assert!(
+        delta.as_bytes().len() < 40,
+        "{} bytes",
+        delta.as_bytes().len()
+    );
+}
+
+#[test]
+fn malformed_deltas_leave_the_chunk_alone() {
+    let size = ChunkSize::default();
+    let mut chunk = Chunk::new(ChunkPos::new(0, 0), size);
+    chunk.set_element(LocalCell::new(3, 3), element(ElementKind::Sand));
+    let delta = ChunkDelta::whole(&chunk, Precision::Exact);
+
+    let mut target = Chunk::new(ChunkPos::new(0, 0), size);
+    let bytes = delta.as_bytes();
+    for len in 0..bytes.len() {
+        let truncated = ChunkDelta::from_bytes(delta.position, bytes[..len].to_vec());
+        assert!(matches!(
+            truncated.apply(&mut target),
+            Err(SaveError::Format(_))
+        ));
+    }
+    // Exact precision, the whole chunk as the area and far more cells than any chunk holds
+    let mut huge = vec![1, 0, 0, 64, 64];
+    huge.extend([0xff; 8]);
+    huge.push(0x7f);
+    let huge = ChunkDelta::from_bytes(delta.position, huge);
+    assert!(matches!(huge.apply(&mut target), Err(SaveError::Format(_))));
+    let mut elsewhere = Chunk::new(ChunkPos::new(1, 0), size);
+    assert!(delta.apply(&mut elsewhere).is_err());
+
+    assert_eq!(
+        elements(&target),
+        elements(&Chunk::new(ChunkPos::new(0, 0), size))
+    );
+}
+
+/// A cell, the index of a built-in kind, a velocity and a tick.
+type Change = ((i32, i32), usize, (f32, f32), u64);
+
+fn change() -> impl Strategy<Value = Change> {
+    (
+        (0..64, 0..64),
+        0..ElementKind::ALL.len(),
+        (-20.0f32..20.0, -20.0f32..20.0),
+        0u64..1000,
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn deltas_turn_the_baseline_into_the_chunk(
+        before in prop::collection::vec(change(), 0..200),
+        after in prop::collection::vec(change(), 0..200),
+    ) {
+        let apply = |chunk: &mut Chunk, changes: &[Change]| {
+            for ((x, y), kind, (vx, vy), wframe) in changes.iter().copied() {
+                let kind = ElementKind::ALL[kind];
+                chunk.set_element(LocalCell::new(x, y), moving(kind, Vec2::new(vx, vy), wframe));
+            }
+        };
+        let mut baseline = Chunk::new(ChunkPos::new(-3, 4), ChunkSize::default());
+        apply(&mut baseline, &before);
+        let mut chunk = baseline.clone();
+        chunk.next_dirty_rect.clear();
+        apply(&mut chunk, &after);
+
+        let delta = ChunkDelta::encode(
+            &chunk,
+            Some(&baseline),
+            chunk.next_dirty_rect,
+            Precision::Exact,
+        );
+        delta.apply(&mut baseline).unwrap();
+        prop_assert_eq!(elements(&baseline), elements(&chunk));
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use bevy::math::{IVec2, Vec2};
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+const PEBBLE: &str = r#"
+const NAME = "Pebble";
+const COLOR = [90, 90, 90];
+const DENSITY = 120;
+
+fn tick(api) {}
+"#;
+
+const MOSS: &str = r#"
+const NAME = "Moss";
+const COLOR = [60, 140, 40];
+const DENSITY = 255;
+
+fn tick(api) {}
+"#;
+
+#[test]
+fn spawners_run_in_sleeping_chunks() {
+    let mut scene = Scene::with_floor(40);
+    for _ in 0..10 {
+        scene.sandbox.tick();
+    }
+    assert!(!scene
+        .sandbox
+        .get_chunk(ChunkPos::new(0, 0))
+        .unwrap()
+        .active());
+
+    scene
+        .sandbox
+        .emitters
+        .push(EmitterPreset::SandHopper.emitter(WorldCell::new(10, 2), &scene.sandbox.gravity));
+    for _ in 0..30 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(count(&scene.sandbox, world_region(), ElementKind::Sand), 30);
+}
+
+#[test]
+fn drains_swallow_what_falls_in() {
+    let mut scene = Scene::with_floor(40);
+    scene.fill(
+        Rect::new(IVec2::new(9, 30), IVec2::new(15, 60)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(10, 30), IVec2::new(14, 60)),
+        ElementKind::Air,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(10, 40), IVec2::new(14, 50)),
+        ElementKind::Sand,
+    );
+    scene
+        .sandbox
+        .emitters
+        .push(EmitterPreset::Drain.emitter(WorldCell::new(10, 59), &scene.sandbox.gravity));
+
+    for _ in 0..100 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(count(&scene.sandbox, world_region(), ElementKind::Sand), 0);
+}
+
+#[test]
+fn faucet_over_a_drain_never_floods() {
+    let mut scene = Scene::with_floor(40);
+    scene
+        .sandbox
+        .emitters
+        .push(EmitterPreset::Faucet.emitter(WorldCell::new(20, 40), &scene.sandbox.gravity));
+    scene
+        .sandbox
+        .emitters
+        .push(EmitterPreset::Void.emitter(WorldCell::new(16, 52), &scene.sandbox.gravity));
+
+    for _ in 0..200 {
+        scene.sandbox.tick();
+    }
+
+    assert!(count(&scene.sandbox, world_region(), ElementKind::Water) < 15);
+}
+
+#[test]
+fn erasing_removes_the_emitters_under_the_cursor() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox
+        .emitters
+        .push(EmitterPreset::Void.emitter(WorldCell::new(0, 0), &sandbox.gravity));
+    sandbox
+        .emitters
+        .push(EmitterPreset::Faucet.emitter(WorldCell::new(20, 0), &sandbox.gravity));
+
+    assert!(!sandbox.remove_emitters_at(WorldCell::new(8, 0)));
+    assert!(sandbox.remove_emitters_at(WorldCell::new(7, 7)));
+    assert_eq!(sandbox.emitters.len(), 1);
+}
+
+#[test]
+fn saved_worlds_load_with_their_emitters() {
+    let mut elements = ElementRegistry::default();
+    let pebble = elements.load_script_source(PEBBLE).unwrap();
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.elements = Arc::new(elements);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(-10, 20), IVec2::new(10, 30)),
+        sandbox.elements.element(pebble),
+    );
+    sandbox.set_element(WorldCell::new(5, -70), element(ElementKind::Water));
+    sandbox.emitters.push(Emitter::new(
+        Rect::new(IVec2::new(0, 0), IVec2::new(2, 2)),
+        EmitterAction::Spawn {
+            element: pebble,
+            interval: 5,
+            velocity: Vec2::ZERO,
+        },
+    ));
+
+    let save = sandbox.save();
+    let save: WorldSave = ron::from_str(&ron::to_string(&save).unwrap()).unwrap();
+
+    // Registered in another order, so the kinds get other indices
+    let mut elements = ElementRegistry::default();
+    elements.load_script_source(MOSS).unwrap();
+    let pebble = elements.load_script_source(PEBBLE).unwrap();
+    let mut loaded = Sandbox::with_seed(SEED);
+    loaded.elements = Arc::new(elements);
+    loaded.load(&save).unwrap();
+
+    assert_eq!(loaded.chunks.len(), sandbox.chunks.len());
+    assert_eq!(count(&loaded, world_region(), pebble), 200);
+    assert_eq!(count(&loaded, world_region(), ElementKind::Water), 1);
+    assert_eq!(loaded.emitters.len(), 1);
+    assert!(matches!(
+        loaded.emitters[0].action,
+        EmitterAction::Spawn { element, interval: 5, .. } if element == pebble
+    ));
+}
+
+#[test]
+fn saves_with_unknown_elements_are_rejected() {
+    let mut elements = ElementRegistry::default();
+    let pebble = elements.load_script_source(PEBBLE).unwrap();
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.elements = Arc::new(elements);
+    sandbox.set_element(WorldCell::new(0, 0), sandbox.elements.element(pebble));
+    let save = sandbox.save();
+
+    let mut other = Sandbox::with_seed(SEED);
+    other.set_element(WorldCell::new(1, 1), element(ElementKind::Sand));
+
+    assert!(matches!(other.load(&save), Err(SaveError::Invalid(_))));
+    assert_eq!(count(&other, world_region(), ElementKind::Sand), 1);
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use bevy::math::{IVec2, Vec2};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+const FUSE: &str = r#"
+const NAME = "Fuse";
+const COLOR = [200, 120, 40];
+const DENSITY = 255;
+
+fn tick(api) {
+    api.explode(0, 0, 3.0, 100.0);
+}
+"#;
+
+#[test]
+fn explosion_crumbles_stone_and_destroys_sand() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 20), IVec2::new(40, 60)));
+    scene.fill(
+        Rect::new(IVec2::new(0, 20), IVec2::new(20, 60)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(20, 20), IVec2::new(40, 60)),
+        ElementKind::Sand,
+    );
+
+    scene.sandbox.explode(Vec2::new(20.0, 40.0), 10.0, 200.0);
+
+    // Stone withstands 80 of the 200 power, which reaches 6 cells out
+    assert_eq!(kind_at(&scene.sandbox, 17, 40), Some(ElementKind::Sand));
+    assert_eq!(kind_at(&scene.sandbox, 12, 40), Some(ElementKind::Stone));
+    // Sand only withstands 15
+    assert_eq!(kind_at(&scene.sandbox, 22, 40), Some(ElementKind::Air));
+    assert_eq!(kind_at(&scene.sandbox, 28, 40), Some(ElementKind::Air));
+
+    let rim = scene.sandbox.get_element(WorldCell::new(29, 40)).unwrap();
+    assert_eq!(rim.kind, ElementKind::Sand);
+    assert!(rim.velocity.x > 0.0);
+    let rim = scene.sandbox.get_element(WorldCell::new(12, 40)).unwrap();
+    assert!(rim.velocity.x < 0.0);
+}
+
+#[test]
+fn tnt_sets_off_the_next_stick() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 30), IVec2::new(40, 50)));
+    for x in (0..40).step_by(8) {
+        scene.place(WorldCell::new(x, 40), ElementKind::Tnt);
+    }
+
+    scene.sandbox.explode(Vec2::new(0.5, 40.5), 2.0, 50.0);
+    assert_eq!(kind_at(&scene.sandbox, 0, 40), Some(ElementKind::Air));
+    assert_eq!(count(&scene.sandbox, world_region(), ElementKind::Tnt), 4);
+    assert_eq!(scene.sandbox.explosions.len(), 1);
+
+    scene.sandbox.tick();
+    assert_eq!(count(&scene.sandbox, world_region(), ElementKind::Tnt), 3);
+
+    for _ in 0..4 {
+        scene.sandbox.tick();
+    }
+    assert_eq!(count(&scene.sandbox, world_region(), ElementKind::Tnt), 0);
+    assert!(scene.sandbox.explosions.is_empty());
+}
+
+#[test]
+fn powerful_explosions_throw_rubble_off_the_grid() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 20), IVec2::new(40, 60)));
+    scene.fill(
+        Rect::new(IVec2::new(10, 30), IVec2::new(30, 50)),
+        ElementKind::Stone,
+    );
+
+    scene.sandbox.explode(Vec2::new(20.0, 40.0), 10.0, 400.0);
+    scene.sandbox.tick();
+
+    assert!(!scene.sandbox.particles.is_empty());
+}
+
+#[test]
+fn radius_queries_cross_chunk_borders() {
+    let sandbox = Sandbox::with_seed(SEED);
+    let center = Vec2::new(0.0, 0.0);
+
+    let cells = sandbox.cells_in_radius(center, 3.0);
+
+    let expected = (-3..3)
+        .flat_map(|y| (-3..3).map(move |x| IVec2::new(x, y)))
+        .filter(|cell| (cell.as_vec2() + Vec2::splat(0.5)).distance(center) <= 3.0)
+        .count();
+    assert_eq!(cells.len(), expected);
+    assert!(cells.iter().any(|(cell, _)| cell.0 == IVec2::new(-1, -1)));
+    assert!(cells.iter().any(|(cell, _)| cell.0 == IVec2::new(0, 0)));
+
+    // Cells of missing chunks are left out
+    let cells = sandbox.cells_in_radius(Vec2::new(-64.0, 0.0), 3.0);
+    assert_eq!(cells.len(), expected / 2);
+    assert!(cells.iter().all(|(cell, _)| cell.0.x >= -64));
+}
+
+#[test]
+fn explosion_breaks_rigid_bodies() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 40), IVec2::new(20, 60)));
+    scene.fill(
+        Rect::new(IVec2::new(2, 54), IVec2::new(18, 58)),
+        ElementKind::Stone,
+    );
+    assert!(scene.sandbox.detach_body(WorldCell::new(2, 54)));
+
+    scene.sandbox.explode(Vec2::new(10.0, 56.0), 2.0, 1.0);
+
+    assert_eq!(scene.sandbox.bodies.len(), 2);
+}
+
+#[test]
+fn scripts_can_set_off_explosions() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let mut elements = ElementRegistry::default();
+    let fuse = elements.load_script_source(FUSE).unwrap();
+    sandbox.elements = Arc::new(elements);
+    sandbox.set_element(WorldCell::new(10, 40), sandbox.elements.element(fuse));
+    sandbox.set_element(WorldCell::new(11, 40), element(ElementKind::Sand));
+
+    sandbox.tick();
+    assert_eq!(sandbox.explosions.len(), 1);
+    sandbox.tick();
+
+    assert_eq!(kind_at(&sandbox, 10, 40), Some(ElementKind::Air));
+    assert_eq!(kind_at(&sandbox, 11, 40), Some(ElementKind::Air));
+}
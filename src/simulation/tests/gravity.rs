@@ -0,0 +1,164 @@
+use bevy::math::{IVec2, Vec2};
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+#[test]
+fn sand_falls_sideways() {
+    let mut scene = Scene::boxed();
+    scene.sandbox.set_gravity(Gravity {
+        direction: GravityDirection::Left,
+        strength: 0.5,
+    });
+    scene.place(WorldCell::new(30, 20), ElementKind::Sand);
+
+    for _ in 0..40 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(kind_at(&scene.sandbox, 1, 20), Some(ElementKind::Sand));
+}
+
+#[test]
+fn water_pools_on_the_ceiling() {
+    let mut scene = Scene::boxed();
+    scene.sandbox.set_gravity(Gravity {
+        direction: GravityDirection::Up,
+        strength: 0.5,
+    });
+    scene.fill(
+        Rect::new(IVec2::new(10, 30), IVec2::new(20, 35)),
+        ElementKind::Water,
+    );
+
+    for _ in 0..300 {
+        scene.sandbox.tick();
+    }
+
+    let water_in_row = |y| {
+        (1..39)
+            .filter(|x| kind_at(&scene.sandbox, *x, y) == Some(ElementKind::Water))
+            .count()
+    };
+    assert_eq!(water_in_row(1), 38);
+    assert!(water_in_row(3) < 38);
+}
+
+#[test]
+fn zones_override_the_world_gravity() {
+    let mut scene = Scene::boxed();
+    scene.sandbox.add_gravity_zone(GravityZone {
+        area: Rect::new(IVec2::new(1, 1), IVec2::new(10, 39)),
+        gravity: Gravity::ZERO,
+    });
+    scene.sandbox.add_gravity_zone(GravityZone {
+        area: Rect::new(IVec2::new(10, 1), IVec2::new(20, 39)),
+        gravity: Gravity {
+            direction: GravityDirection::Up,
+            strength: 0.5,
+        },
+    });
+    scene.place(WorldCell::new(5, 20), ElementKind::Sand);
+    scene.place(WorldCell::new(15, 20), ElementKind::Sand);
+    scene.place(WorldCell::new(25, 20), ElementKind::Sand);
+
+    for _ in 0..40 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(kind_at(&scene.sandbox, 5, 20), Some(ElementKind::Sand));
+    assert_eq!(kind_at(&scene.sandbox, 15, 1), Some(ElementKind::Sand));
+    assert_eq!(kind_at(&scene.sandbox, 25, 38), Some(ElementKind::Sand));
+}
+
+#[test]
+fn changing_gravity_wakes_resting_elements() {
+    let mut scene = Scene::boxed();
+    scene.place(WorldCell::new(20, 20), ElementKind::Sand);
+    for _ in 0..60 {
+        scene.sandbox.tick();
+    }
+    assert!(!scene
+        .sandbox
+        .get_chunk(ChunkPos::new(0, 0))
+        .unwrap()
+        .active());
+
+    scene.sandbox.set_gravity(Gravity {
+        direction: GravityDirection::Right,
+        strength: 0.5,
+    });
+    for _ in 0..40 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(kind_at(&scene.sandbox, 38, 38), Some(ElementKind::Sand));
+}
+
+#[test]
+fn particles_fall_with_gravity() {
+    let mut scene = Scene::boxed();
+    scene.sandbox.set_gravity(Gravity {
+        direction: GravityDirection::Up,
+        strength: 0.5,
+    });
+    scene.place(WorldCell::new(20, 30), ElementKind::Sand);
+    scene
+        .sandbox
+        .launch(WorldCell::new(20, 30), Vec2::new(5.0, 0.0));
+
+    scene.sandbox.tick();
+    assert!(scene.sandbox.particles[0].velocity.y < 0.0);
+}
+
+#[test]
+fn gravity_is_saved_with_the_world() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let sideways = Gravity {
+        direction: GravityDirection::Left,
+        strength: 1.0,
+    };
+    sandbox.set_gravity(sideways);
+    sandbox.add_gravity_zone(GravityZone {
+        area: Rect::new(IVec2::new(0, 0), IVec2::new(8, 8)),
+        gravity: Gravity::ZERO,
+    });
+
+    let mut loaded = Sandbox::with_seed(SEED);
+    loaded.load(&sandbox.save()).unwrap();
+
+    assert_eq!(loaded.gravity, sandbox.gravity);
+    assert_eq!(loaded.gravity.at(WorldCell::new(20, 0)), sideways);
+    assert_eq!(loaded.gravity.at(WorldCell::new(4, 4)), Gravity::ZERO);
+}
+
+#[test]
+fn emitter_presets_follow_gravity() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.add_gravity_zone(GravityZone {
+        area: Rect::new(IVec2::new(0, 0), IVec2::new(20, 20)),
+        gravity: Gravity {
+            direction: GravityDirection::Left,
+            strength: 0.5,
+        },
+    });
+
+    let spawn_velocity = |emitter: Emitter| match emitter.action {
+        EmitterAction::Spawn { velocity, .. } => velocity,
+        EmitterAction::Drain => unreachable!(),
+    };
+
+    let hopper = EmitterPreset::SandHopper.emitter(WorldCell::new(5, 5), &sandbox.gravity);
+    assert_eq!(spawn_velocity(hopper), Vec2::new(-1.0, 0.0));
+    assert_eq!(hopper.area, Rect::new(IVec2::new(5, 5), IVec2::new(6, 8)));
+
+    let geyser = EmitterPreset::Geyser.emitter(WorldCell::new(5, 5), &sandbox.gravity);
+    assert_eq!(spawn_velocity(geyser), Vec2::new(12.0, 0.0));
+
+    let faucet = EmitterPreset::Faucet.emitter(WorldCell::new(30, 5), &sandbox.gravity);
+    assert_eq!(spawn_velocity(faucet), Vec2::new(0.0, 1.0));
+}
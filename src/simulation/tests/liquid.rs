@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use bevy::math::IVec2;
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+/// The rows of the box holding at least one element of `kind`, top to bottom.
+fn rows_with(sandbox: &Sandbox, kind: ElementKind) -> Vec<i32> {
+    (1..39)
+        .filter(|y| (1..39).any(|x| kind_at(sandbox, x, *y) == Some(kind)))
+        .collect()
+}
+
+fn asleep(sandbox: &Sandbox) -> bool {
+    !sandbox.get_chunk(ChunkPos::new(0, 0)).unwrap().active()
+}
+
+#[test]
+fn oil_floats_on_water() {
+    let mut scene = Scene::boxed();
+    scene.fill(
+        Rect::new(IVec2::new(10, 20), IVec2::new(30, 25)),
+        ElementKind::Oil,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(10, 25), IVec2::new(30, 30)),
+        ElementKind::Water,
+    );
+
+    for _ in 0..600 {
+        scene.sandbox.tick();
+    }
+
+    // The row where they meet holds both, but no oil is left under water
+    for x in 1..39 {
+        let column = (1..39)
+            .filter_map(|y| kind_at(&scene.sandbox, x, y))
+            .collect::<Vec<_>>();
+        let first_water = column.iter().position(|kind| *kind == ElementKind::Water);
+        let last_oil = column.iter().rposition(|kind| *kind == ElementKind::Oil);
+        assert!(last_oil < first_water, "column {x}: {column:?}");
+    }
+}
+
+#[test]
+fn honey_flows_slower_than_water() {
+    let spread = |kind| {
+        let mut scene = Scene::boxed();
+        scene.fill(Rect::new(IVec2::new(18, 20), IVec2::new(22, 39)), kind);
+        for _ in 0..60 {
+            scene.sandbox.tick();
+        }
+        (1..39)
+            .filter(|x| kind_at(&scene.sandbox, *x, 38) == Some(kind))
+            .count()
+    };
+
+    let (water, honey) = (spread(ElementKind::Water), spread(ElementKind::Honey));
+    assert!(honey < water, "honey {honey}, water {water}");
+}
+
+#[test]
+fn water_in_a_box_comes_to_rest() {
+    let mut scene = Scene::boxed();
+    scene.fill(
+        Rect::new(IVec2::new(5, 5), IVec2::new(25, 15)),
+        ElementKind::Water,
+    );
+
+    for _ in 0..600 {
+        scene.sandbox.tick();
+    }
+
+    assert!(asleep(&scene.sandbox));
+    assert_eq!(rows_with(&scene.sandbox, ElementKind::Water).len(), 6);
+}
+
+#[test]
+fn water_against_a_wall_levels_out() {
+    // The far end of the surface settles before the water next to the wall has flowed away
+    let mut scene = Scene::boxed();
+    scene.fill(
+        Rect::new(IVec2::new(20, 5), IVec2::new(39, 15)),
+        ElementKind::Water,
+    );
+
+    for _ in 0..600 {
+        scene.sandbox.tick();
+    }
+
+    assert!(asleep(&scene.sandbox));
+    assert_eq!(rows_with(&scene.sandbox, ElementKind::Water).len(), 5);
+}
+
+#[test]
+fn liquids_of_equal_density_settle() {
+    let mut elements = ElementRegistry::default();
+    let dyed_water = elements.register_with_behavior(
+        CustomElement {
+            name: "Dyed water".to_string(),
+            category: ElementCategory::Liquid,
+            color: (200, 40, 120),
+            density: ElementKind::Water.density(),
+            hardness: ElementKind::Water.hardness(),
+        },
+        LiquidBehavior(Liquid::WATER),
+    );
+    let mut scene = Scene::boxed();
+    scene.sandbox.elements = Arc::new(elements);
+    for x in 5..25 {
+        for y in 5..15 {
+            let kind = if (x + y) % 2 == 0 {
+                ElementKind::Water
+            } else {
+                dyed_water
+            };
+            scene.place(WorldCell::new(x, y), kind);
+        }
+    }
+
+    for _ in 0..600 {
+        scene.sandbox.tick();
+    }
+
+    assert!(asleep(&scene.sandbox));
+}
@@ -0,0 +1,242 @@
+//! Golden-state regression tests for element behaviour.
+//!
+//! Every test builds a small scene on a seeded [`Sandbox`], runs it for a number of ticks and
+//! compares ASCII renders of the world against the snapshots stored in `snapshots/`.
+//! Run with `BLESS_SNAPSHOTS=1 cargo test` to (re)write the snapshots after an intended change.
+
+use std::{fs, path::PathBuf};
+
+use bevy::math::IVec2;
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+mod behavior;
+mod conservation;
+mod delta;
+mod emitter;
+mod explosion;
+mod gravity;
+mod liquid;
+mod network;
+mod particle;
+mod remote;
+mod replay;
+mod rewind;
+mod rigid_body;
+mod scripting;
+mod sleep;
+mod world;
+mod world_api;
+
+const SEED: u64 = 0x5eed;
+
+fn kind_to_char(kind: ElementKind) -> char {
+    match kind {
+        ElementKind::Air => '.',
+        ElementKind::Sand => 's',
+        ElementKind::Stone => '#',
+        ElementKind::Water => '~',
+        ElementKind::Oil => 'o',
+        ElementKind::Honey => 'h',
+        ElementKind::Gunpowder => 'g',
+        ElementKind::Tnt => 'T',
+        ElementKind::Custom(_) => '?',
+    }
+}
+
+fn element(kind: ElementKind) -> Element {
+    Element {
+        color: kind.base_color(),
+        kind,
+        ..Default::default()
+    }
+}
+
+/// Every cell covered by the chunks of [`Sandbox::with_seed`].
+fn world_region() -> Rect {
+    Rect::new(IVec2::new(-64, -128), IVec2::new(128, 64))
+}
+
+/// The number of `kind` cells in `rect`.
+fn count(sandbox: &Sandbox, rect: Rect, kind: ElementKind) -> usize {
+    sandbox
+        .iter_region(rect)
+        .filter(|(_, element)| element.kind == kind)
+        .count()
+}
+
+fn kind_at(sandbox: &Sandbox, x: i32, y: i32) -> Option<ElementKind> {
+    sandbox
+        .get_element(WorldCell::new(x, y))
+        .map(|element| element.kind)
+}
+
+/// A sandbox plus the world region that gets rendered into the snapshot.
+struct Scene {
+    sandbox: Sandbox,
+    region: Rect,
+    frames: Vec<String>,
+}
+
+impl Scene {
+    fn new(region: Rect) -> Self {
+        Self::with_seed(region, SEED)
+    }
+
+    fn with_seed(region: Rect, seed: u64) -> Self {
+        Self {
+            sandbox: Sandbox::with_seed(seed),
+            region,
+            frames: Vec::new(),
+        }
+    }
+
+    /// A stone floor along the bottom of the world at y = 60, rendering the `width` columns
+    /// above it.
+    fn with_floor(width: i32) -> Self {
+        let mut scene = Self::new(Rect::new(IVec2::new(0, 30), IVec2::new(width, 64)));
+        scene.fill(
+            Rect::new(IVec2::new(-64, 60), IVec2::new(128, 64)),
+            ElementKind::Stone,
+        );
+        scene
+    }
+
+    /// A closed stone box from (0, 0) to (40, 40), walls included.
+    fn boxed() -> Self {
+        let mut scene = Self::new(Rect::new(IVec2::new(0, 0), IVec2::new(40, 40)));
+        scene.fill(
+            Rect::new(IVec2::new(0, 0), IVec2::new(40, 40)),
+            ElementKind::Stone,
+        );
+        scene.fill(
+            Rect::new(IVec2::new(1, 1), IVec2::new(39, 39)),
+            ElementKind::Air,
+        );
+        scene
+    }
+
+    fn place(&mut self, position: WorldCell, kind: ElementKind) {
+        self.sandbox.set_element(position, element(kind));
+    }
+
+    fn fill(&mut self, rect: Rect, kind: ElementKind) {
+        self.sandbox.fill_rect(rect, element(kind));
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for y in self.region.min.y..self.region.max.y {
+            for x in self.region.min.x..self.region.max.x {
+                let element = self.sandbox.get_element(WorldCell::new(x, y));
+                out.push(element.map_or(' ', |element| kind_to_char(element.kind)));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Advances the simulation and records a frame of the region.
+    fn run(&mut self, ticks: usize) -> &mut Self {
+        for _ in 0..ticks {
+            self.sandbox.tick();
+        }
+
+        let frame = format!("-- tick {}\n{}", self.sandbox.wframe, self.render());
+        self.frames.push(frame);
+        self
+    }
+
+    fn assert_snapshot(&self, name: &str) {
+        assert_snapshot(name, &self.frames.concat());
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/simulation/tests/snapshots")
+        .join(format!("{name}.txt"))
+}
+
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = snapshot_path(name);
+    if std::env::var_os("BLESS_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing snapshot {}, run with BLESS_SNAPSHOTS=1 to create it",
+            path.display()
+        )
+    });
+
+    assert!(
+        expected == actual,
+        "snapshot `{name}` differs\n--- expected\n{expected}\n--- actual\n{actual}\n\
+         (run with BLESS_SNAPSHOTS=1 if the change is intended)"
+    );
+}
+
+#[test]
+fn sand_column_piles_up() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 30), IVec2::new(24, 64)));
+    scene.fill(
+        Rect::new(IVec2::new(12, 32), IVec2::new(13, 44)),
+        ElementKind::Sand,
+    );
+
+    scene
+        .run(0)
+        .run(5)
+        .run(20)
+        .run(60)
+        .assert_snapshot("sand_column");
+}
+
+#[test]
+fn water_drop_fills_container() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 30), IVec2::new(32, 64)));
+    scene.fill(
+        Rect::new(IVec2::new(4, 50), IVec2::new(5, 63)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(4, 62), IVec2::new(28, 63)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(27, 50), IVec2::new(28, 63)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(12, 32), IVec2::new(18, 38)),
+        ElementKind::Water,
+    );
+
+    scene
+        .run(0)
+        .run(10)
+        .run(30)
+        .run(120)
+        .assert_snapshot("water_drop");
+}
+
+#[test]
+fn sand_falls_across_chunk_boundary() {
+    // The world cells y < 0 belong to the chunk above the origin chunk
+    let mut scene = Scene::new(Rect::new(IVec2::new(52, -16), IVec2::new(76, 64)));
+    scene.fill(
+        Rect::new(IVec2::new(60, -12), IVec2::new(68, -4)),
+        ElementKind::Sand,
+    );
+
+    scene
+        .run(0)
+        .run(20)
+        .run(100)
+        .assert_snapshot("sand_chunk_boundary");
+}
@@ -0,0 +1,205 @@
+use std::{
+    io::Write,
+    net::TcpListener,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::math::{IVec2, Vec2};
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+type View = (
+    Vec<(ChunkPos, Vec<ElementKind>, Vec<(u8, u8, u8)>)>,
+    Vec<Vec2>,
+);
+
+/// What a client shows: every chunk and where the particles are.
+fn view(sandbox: &Sandbox) -> View {
+    let mut positions = sandbox.chunks.keys().copied().collect::<Vec<_>>();
+    positions.sort_by_key(|position| (position.0.y, position.0.x));
+    let chunks = positions
+        .into_iter()
+        .map(|position| {
+            let chunk = sandbox.get_chunk(position).unwrap();
+            (position, chunk.kinds().to_vec(), chunk.colors().to_vec())
+        })
+        .collect();
+    let particles = sandbox
+        .particles
+        .iter()
+        .map(|particle| particle.position)
+        .collect();
+    (chunks, particles)
+}
+
+/// Polls until `condition` holds, the other end of the connection runs on its own threads.
+fn eventually(mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "timed out waiting on the other end"
+        );
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn join(host: &mut Host, sandbox: &Sandbox) -> Client {
+    let client = Client::connect(host.address()).unwrap();
+    let clients = host.clients();
+    eventually(|| {
+        assert!(host.receive(sandbox).is_empty());
+        host.clients() > clients
+    });
+    client
+}
+
+/// Runs the host for a number of frames, checking that every client shows the same after
+/// each. No ticks still sends what changed.
+fn run(host: &mut Host, sandbox: &mut Sandbox, clients: &mut [(Client, Sandbox)], ticks: usize) {
+    for _ in 0..ticks.max(1) {
+        if ticks > 0 {
+            sandbox.tick();
+        }
+        host.send_changes(sandbox);
+
+        for (client, shown) in clients.iter_mut() {
+            eventually(|| {
+                client.receive(shown).unwrap();
+                shown.wframe == sandbox.wframe
+            });
+            assert_eq!(view(shown), view(sandbox), "tick {}", sandbox.wframe);
+            assert_eq!(shown.emitters, sandbox.emitters);
+            assert_eq!(shown.gravity, sandbox.gravity);
+        }
+    }
+}
+
+#[test]
+fn clients_show_exactly_what_the_host_simulates() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 40), IVec2::new(64, 41)),
+        element(ElementKind::Stone),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(10, 30), IVec2::new(20, 40)),
+        element(ElementKind::Sand),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(30, 0), IVec2::new(40, 10)),
+        element(ElementKind::Water),
+    );
+    for _ in 0..5 {
+        sandbox.tick();
+    }
+
+    let mut host = Host::bind("127.0.0.1:0").unwrap();
+    let first = join(&mut host, &sandbox);
+    let mut clients = vec![(first, Sandbox::with_seed(SEED + 1))];
+    run(&mut host, &mut sandbox, &mut clients, 10);
+
+    // Set off while paused, so only the edit itself changes the world
+    sandbox.active = false;
+    sandbox.explode(Vec2::new(15.0, 38.0), 10.0, 400.0);
+    host.mark_edited();
+    run(&mut host, &mut sandbox, &mut clients, 0);
+    sandbox.active = true;
+    run(&mut host, &mut sandbox, &mut clients, 1);
+    assert!(!sandbox.particles.is_empty());
+    run(&mut host, &mut sandbox, &mut clients, 10);
+
+    // Joining in the middle of it all, with changes the first client didn't get yet
+    sandbox.tick();
+    let second = join(&mut host, &sandbox);
+    clients.push((second, Sandbox::with_seed(SEED + 2)));
+    run(&mut host, &mut sandbox, &mut clients, 10);
+
+    // Emitters and gravity zones show up too
+    let emitter = EmitterPreset::Faucet.emitter(WorldCell::new(50, 5), &sandbox.gravity);
+    sandbox.emitters.push(emitter);
+    sandbox.add_gravity_zone(GravityZone {
+        area: Rect::new(IVec2::new(0, 0), IVec2::new(20, 20)),
+        gravity: Gravity {
+            direction: GravityDirection::Up,
+            ..Default::default()
+        },
+    });
+    host.mark_edited();
+    run(&mut host, &mut sandbox, &mut clients, 5);
+
+    // Reloading replaces every chunk, which are then sent whole
+    let save = sandbox.save();
+    sandbox.load(&save).unwrap();
+    run(&mut host, &mut sandbox, &mut clients, 20);
+}
+
+#[test]
+fn the_host_gets_the_edits_of_its_clients() {
+    let sandbox = Sandbox::with_seed(SEED);
+    let mut host = Host::bind("127.0.0.1:0").unwrap();
+    let mut client = join(&mut host, &sandbox);
+
+    let stroke = Edit::Stroke {
+        from: IVec2::new(3, 4),
+        to: IVec2::new(20, 4),
+        element: ElementKind::Water,
+    };
+    client.send(&stroke).unwrap();
+    client.send(&Edit::SetPaused(true)).unwrap();
+
+    let mut edits = Vec::new();
+    eventually(|| {
+        edits.append(&mut host.receive(&sandbox));
+        edits.len() == 2
+    });
+    assert_eq!(edits, [stroke, Edit::SetPaused(true)]);
+}
+
+#[test]
+fn clients_need_the_elements_of_the_host() {
+    let mut elements = ElementRegistry::default();
+    elements.register(CustomElement {
+        name: "Glitter".to_string(),
+        category: ElementCategory::Powder,
+        color: (250, 200, 250),
+        density: 50,
+        hardness: 5,
+    });
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.elements = Arc::new(elements);
+
+    let mut host = Host::bind("127.0.0.1:0").unwrap();
+    let mut client = join(&mut host, &sandbox);
+    let mut shown = Sandbox::with_seed(SEED);
+
+    let mut result = Ok(());
+    eventually(|| {
+        result = client.receive(&mut shown);
+        result.is_err()
+    });
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn messages_too_long_to_be_real_are_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut stream, _) = listener.accept().unwrap();
+    stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+
+    let mut shown = Sandbox::with_seed(SEED);
+    let mut result = Ok(());
+    eventually(|| {
+        result = client.receive(&mut shown);
+        result.is_err()
+    });
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,102 @@
+use bevy::math::{IVec2, Vec2};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+fn sand_cells(sandbox: &Sandbox) -> Vec<WorldCell> {
+    sandbox
+        .iter_region(Rect::new(IVec2::new(-64, -128), IVec2::new(128, 64)))
+        .filter(|(_, element)| element.kind == ElementKind::Sand)
+        .map(|(cell, _)| cell)
+        .collect()
+}
+
+#[test]
+fn launched_element_arcs_over_a_wall() {
+    let mut scene = Scene::with_floor(100);
+    scene.fill(
+        Rect::new(IVec2::new(15, 50), IVec2::new(16, 60)),
+        ElementKind::Stone,
+    );
+    scene.place(WorldCell::new(5, 58), ElementKind::Sand);
+
+    assert!(scene
+        .sandbox
+        .launch(WorldCell::new(5, 58), Vec2::new(3.0, -6.0)));
+    assert_eq!(scene.sandbox.particles.len(), 1);
+    assert!(sand_cells(&scene.sandbox).is_empty());
+
+    for _ in 0..60 {
+        scene.sandbox.tick();
+    }
+
+    assert!(scene.sandbox.particles.is_empty());
+    let cells = sand_cells(&scene.sandbox);
+    assert_eq!(cells.len(), 1);
+    assert!(cells[0].0.x > 16, "landed at {}", cells[0]);
+    assert_eq!(cells[0].0.y, 59);
+}
+
+#[test]
+fn elements_faster_than_the_grid_take_off() {
+    let mut scene = Scene::with_floor(100);
+    scene.sandbox.set_element(
+        WorldCell::new(5, 40),
+        Element {
+            velocity: Vec2::new(0.0, -15.0),
+            ..element(ElementKind::Sand)
+        },
+    );
+
+    scene.sandbox.tick();
+
+    assert_eq!(scene.sandbox.particles.len(), 1);
+    let particle = scene.sandbox.particles[0];
+    assert_eq!(particle.element.kind, ElementKind::Sand);
+    assert!(particle.position.y < 30.0);
+    assert!(sand_cells(&scene.sandbox).is_empty());
+}
+
+#[test]
+fn particle_merges_back_in_front_of_what_it_hits() {
+    let mut scene = Scene::with_floor(100);
+    scene.fill(
+        Rect::new(IVec2::new(30, 30), IVec2::new(31, 60)),
+        ElementKind::Stone,
+    );
+    scene.place(WorldCell::new(5, 40), ElementKind::Water);
+    scene
+        .sandbox
+        .launch(WorldCell::new(5, 40), Vec2::new(20.0, 0.0));
+
+    scene.sandbox.tick();
+    scene.sandbox.tick();
+
+    assert!(scene.sandbox.particles.is_empty());
+    let element = scene.sandbox.get_element(WorldCell::new(29, 41)).unwrap();
+    assert_eq!(element.kind, ElementKind::Water);
+    assert!(element.velocity.x <= MAX_GRID_SPEED);
+}
+
+#[test]
+fn census_counts_flying_particles() {
+    let mut scene = Scene::with_floor(100);
+    scene.fill(
+        Rect::new(IVec2::new(5, 50), IVec2::new(15, 60)),
+        ElementKind::Sand,
+    );
+    for x in 5..15 {
+        scene
+            .sandbox
+            .launch(WorldCell::new(x, 50), Vec2::new(x as f32 - 10.0, -8.0));
+    }
+
+    let before = ElementCensus::take(&scene.sandbox);
+    for _ in 0..40 {
+        scene.sandbox.tick();
+        let discrepancies = before.compare(&ElementCensus::take(&scene.sandbox));
+        assert!(discrepancies.is_empty(), "{discrepancies:?}");
+    }
+    assert!(scene.sandbox.particles.is_empty());
+}
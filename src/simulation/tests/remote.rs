@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::simulation::plugin::answer_remote;
+
+use super::*;
+
+/// Sends every line to the remote control from another thread while the sandbox answers, like
+/// a script would, and returns the responses.
+fn run_script(sandbox: &mut Sandbox, lines: Vec<String>) -> Vec<Value> {
+    let mut remote = RemoteControl::bind("127.0.0.1:0").unwrap();
+    let address = remote.address();
+    let script = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        lines
+            .into_iter()
+            .map(|line| {
+                writeln!(stream, "{line}").unwrap();
+                let mut response = String::new();
+                reader.read_line(&mut response).unwrap();
+                serde_json::from_str::<Value>(&response).unwrap()
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let (mut replay, mut rewind) = (Replay::default(), Rewind::default());
+    while !script.is_finished() {
+        remote.serve(|request| answer_remote(sandbox, &mut replay, &mut rewind, None, request));
+        thread::sleep(Duration::from_millis(1));
+    }
+    script.join().unwrap()
+}
+
+fn requests(requests: &[Value]) -> Vec<String> {
+    requests.iter().map(Value::to_string).collect()
+}
+
+#[test]
+fn scripts_edit_step_and_inspect_the_world() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let responses = run_script(
+        &mut sandbox,
+        requests(&[
+            json!({"command": "pause"}),
+            json!({"command": "fill", "min": [0, 40], "max": [64, 41], "element": "Stone"}),
+            json!({"command": "set_element", "x": 10, "y": 30, "element": "Sand"}),
+            json!({"command": "get_element", "x": 10, "y": 30}),
+            json!({"command": "step", "ticks": 20}),
+            json!({"command": "get_element", "x": 10, "y": 39}),
+            json!({"command": "stats"}),
+        ]),
+    );
+
+    for response in &responses[..3] {
+        assert_eq!(*response, json!({"result": null}));
+    }
+    assert_eq!(responses[3]["result"]["kind"], "Sand");
+    assert_eq!(responses[4], json!({"result": null}));
+    assert_eq!(responses[5]["result"]["kind"], "Sand");
+
+    let stats = &responses[6]["result"];
+    assert_eq!(stats["tick"], 20);
+    assert_eq!(stats["paused"], true);
+    assert_eq!(stats["elements"], json!({"Sand": 1, "Stone": 64}));
+    assert!(!sandbox.active);
+}
+
+#[test]
+fn bad_requests_get_an_error_and_change_nothing() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let before = sandbox.save();
+    let mut lines = requests(&[
+        json!({"command": "set_element", "x": 0, "y": 0, "element": "Unobtainium"}),
+        json!({"command": "fill", "min": [0, 0], "max": [100000, 100000], "element": "Sand"}),
+        json!({"command": "load", "path": "no/such/world.ron"}),
+        json!({"command": "load", "path": "../world.ron"}),
+        json!({"command": "save", "path": std::env::temp_dir().join("world.ron")}),
+        json!({"command": "screenshot", "path": "target/../../world.png"}),
+        json!({"command": "step", "ticks": 1001}),
+        json!({"command": "fly"}),
+    ]);
+    lines.push("hello".to_string());
+    let responses = run_script(&mut sandbox, lines);
+
+    for response in responses {
+        assert!(response["error"].is_string(), "{response}");
+    }
+    assert_eq!(sandbox.save(), before);
+    assert_eq!(sandbox.wframe, 0);
+}
+
+#[test]
+fn connections_that_dont_start_with_a_request_are_closed() {
+    let mut remote = RemoteControl::bind("127.0.0.1:0").unwrap();
+    let address = remote.address();
+    // What a browser sends when a page posts a request to the socket
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(
+                b"POST / HTTP/1.1\r\nHost: 127.0.0.1:7879\r\nContent-Type: text/plain\r\n\r\n\
+                  {\"command\": \"pause\"}\n",
+            )
+            .unwrap();
+        let mut responses = String::new();
+        stream.read_to_string(&mut responses).unwrap();
+        responses
+    });
+
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let (mut replay, mut rewind) = (Replay::default(), Rewind::default());
+    while !client.is_finished() {
+        remote
+            .serve(|request| answer_remote(&mut sandbox, &mut replay, &mut rewind, None, request));
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let responses = client.join().unwrap();
+    let responses = responses.lines().collect::<Vec<_>>();
+    assert_eq!(responses.len(), 1, "{responses:?}");
+    assert!(serde_json::from_str::<Value>(responses[0]).unwrap()["error"].is_string());
+    assert!(sandbox.active);
+}
+
+#[test]
+fn worlds_are_saved_loaded_and_drawn_to_files() {
+    // Relative to the working directory, which is as far as requests reach
+    let dir = Path::new("target").join(format!("remote-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let save = dir.join("world.ron");
+    let screenshot = dir.join("world.png");
+
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 0), IVec2::new(8, 8)),
+        element(ElementKind::Water),
+    );
+    let responses = run_script(
+        &mut sandbox,
+        requests(&[
+            json!({"command": "save", "path": save}),
+            json!({"command": "fill", "min": [0, 0], "max": [8, 8], "element": "Oil"}),
+            json!({"command": "load", "path": save}),
+            json!({"command": "get_element", "x": 3, "y": 3}),
+            json!({"command": "screenshot", "path": screenshot, "min": [0, 0], "max": [8, 4]}),
+        ]),
+    );
+
+    assert_eq!(responses[3]["result"]["kind"], "Water");
+    assert_eq!(responses[4], json!({"result": null}));
+    // The width and height in the header of the PNG
+    let png = fs::read(&screenshot).unwrap();
+    assert_eq!(png[16..24], [0, 0, 0, 8, 0, 0, 0, 4]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
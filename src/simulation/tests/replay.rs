@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use bevy::math::{IVec2, Vec2};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+/// A bit of everything the user can do, each after running a number of ticks.
+fn session() -> Vec<(usize, Edit)> {
+    vec![
+        (
+            0,
+            Edit::Stroke {
+                from: IVec2::new(10, 5),
+                to: IVec2::new(30, 12),
+                element: ElementKind::Sand,
+            },
+        ),
+        (
+            3,
+            Edit::PlaceEmitter {
+                preset: EmitterPreset::Faucet,
+                cell: IVec2::new(40, 2),
+            },
+        ),
+        (
+            4,
+            Edit::DetachBody {
+                cell: IVec2::new(50, 30),
+            },
+        ),
+        (
+            10,
+            Edit::Explode {
+                center: Vec2::new(20.5, 38.5),
+                radius: 6.0,
+                power: 100.0,
+            },
+        ),
+        (
+            2,
+            Edit::SetGravity(Gravity {
+                direction: GravityDirection::Left,
+                strength: 0.5,
+            }),
+        ),
+        (5, Edit::SetPaused(true)),
+        (
+            3,
+            Edit::Stroke {
+                from: IVec2::new(0, 20),
+                to: IVec2::new(63, 20),
+                element: ElementKind::Air,
+            },
+        ),
+        (0, Edit::SetPaused(false)),
+        (8, Edit::SetGravity(Gravity::default())),
+    ]
+}
+
+fn world(sandbox: &Sandbox) -> Vec<(WorldCell, Element)> {
+    sandbox.iter_region(world_region()).collect()
+}
+
+/// A floor with a stone block on it, which has been running for a while so that its elements
+/// are moving when the recording starts.
+fn running_sandbox(seed: u64) -> Sandbox {
+    let mut sandbox = Sandbox::with_seed(seed);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 40), IVec2::new(64, 41)),
+        element(ElementKind::Stone),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(48, 28), IVec2::new(54, 34)),
+        element(ElementKind::Stone),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(20, 0), IVec2::new(26, 6)),
+        element(ElementKind::Water),
+    );
+    for _ in 0..5 {
+        sandbox.tick();
+    }
+    sandbox
+}
+
+fn run(replay: &mut Replay, sandbox: &mut Sandbox, ticks: usize) {
+    for _ in 0..ticks {
+        replay.advance(sandbox);
+        sandbox.tick();
+    }
+}
+
+#[test]
+fn a_replay_plays_out_exactly_like_the_recording() {
+    let mut sandbox = running_sandbox(SEED);
+    let mut replay = Replay::Recording(Recording::start(&mut sandbox, 7));
+    for (ticks, edit) in session() {
+        run(&mut replay, &mut sandbox, ticks);
+        replay.apply(&mut sandbox, edit).unwrap();
+    }
+    run(&mut replay, &mut sandbox, 40);
+    let recording = replay.stop(&sandbox).unwrap();
+    assert_eq!(recording.edits.len(), session().len());
+
+    let contents = ron::to_string(&recording).unwrap();
+    let recording = ron::from_str::<Recording>(&contents).unwrap();
+
+    let mut played = running_sandbox(SEED + 1);
+    let mut replay = Replay::Off;
+    replay.play(&mut played, recording).unwrap();
+    loop {
+        replay.advance(&mut played);
+        if !replay.is_playing() {
+            break;
+        }
+        played.tick();
+    }
+
+    assert_eq!(played.wframe, sandbox.wframe);
+    assert_eq!(played.gravity.world, Gravity::default());
+    assert_eq!(played.emitters, sandbox.emitters);
+    assert_eq!(played.particles, sandbox.particles);
+    assert_eq!(world(&played), world(&sandbox));
+}
+
+#[test]
+fn starting_a_recording_leaves_the_world_alone() {
+    let mut sandbox = running_sandbox(SEED);
+    assert!(sandbox.detach_body(WorldCell::new(50, 30)));
+    sandbox.set_element(WorldCell::new(10, 10), element(ElementKind::Sand));
+    assert!(sandbox.launch(WorldCell::new(10, 10), Vec2::new(6.0, -3.0)));
+    sandbox.tick();
+    let before = sandbox.snapshot();
+
+    let recording = Recording::start(&mut sandbox, 7);
+    assert_eq!(sandbox.snapshot(), before);
+    assert_eq!(recording.world, before);
+    assert_eq!(sandbox.bodies.len(), 1);
+    assert_eq!(sandbox.particles.len(), 1);
+
+    let mut played = running_sandbox(SEED + 1);
+    Replay::Off.play(&mut played, recording).unwrap();
+    assert_eq!(played.snapshot(), before);
+}
+
+#[test]
+fn edits_reaching_too_many_cells_are_refused() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let chunks = sandbox.chunks.len();
+    let stroke = Edit::Stroke {
+        from: IVec2::new(0, 0),
+        to: IVec2::new(i32::MAX, 0),
+        element: ElementKind::Sand,
+    };
+
+    let fill = Edit::Fill {
+        min: IVec2::new(0, 0),
+        max: IVec2::new(1 << 16, 1 << 16),
+        element: ElementKind::Sand,
+    };
+
+    assert!(matches!(sandbox.apply(&stroke), Err(SaveError::Invalid(_))));
+    assert!(matches!(sandbox.apply(&fill), Err(SaveError::Invalid(_))));
+    assert_eq!(sandbox.chunks.len(), chunks);
+}
+
+#[test]
+fn users_can_only_pause_while_a_replay_plays() {
+    let mut sandbox = running_sandbox(SEED);
+    let recording = Recording::start(&mut sandbox, 7);
+    let mut replay = Replay::Off;
+    replay.play(&mut sandbox, recording).unwrap();
+
+    let stroke = Edit::Stroke {
+        from: IVec2::new(5, 5),
+        to: IVec2::new(5, 5),
+        element: ElementKind::Sand,
+    };
+    replay.apply(&mut sandbox, stroke).unwrap();
+    replay.apply(&mut sandbox, Edit::SetPaused(true)).unwrap();
+
+    let kind = sandbox.get_element(WorldCell::new(5, 5)).unwrap().kind;
+    assert_eq!(kind, ElementKind::Air);
+    assert!(!sandbox.active);
+}
+
+#[test]
+fn replays_need_the_elements_they_were_recorded_with() {
+    let mut sandbox = running_sandbox(SEED);
+    let recording = Recording::start(&mut sandbox, 7);
+
+    let mut elements = ElementRegistry::default();
+    elements.register(CustomElement {
+        name: "Glitter".to_string(),
+        category: ElementCategory::Powder,
+        color: (250, 200, 250),
+        density: 50,
+        hardness: 5,
+    });
+    let mut other = Sandbox::with_seed(SEED);
+    other.elements = Arc::new(elements);
+
+    assert!(matches!(
+        Replay::Off.play(&mut other, recording),
+        Err(SaveError::Invalid(_))
+    ));
+}
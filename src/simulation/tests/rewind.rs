@@ -0,0 +1,91 @@
+use bevy::math::{IVec2, Vec2};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+fn world(sandbox: &Sandbox) -> Vec<(WorldCell, Element)> {
+    sandbox.iter_region(world_region()).collect()
+}
+
+/// Sand and water pouring onto a floor, with a stone block that is blown up and thrown around.
+fn busy_sandbox() -> Sandbox {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 40), IVec2::new(64, 41)),
+        element(ElementKind::Stone),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(10, 0), IVec2::new(20, 10)),
+        element(ElementKind::Sand),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(30, 0), IVec2::new(40, 10)),
+        element(ElementKind::Water),
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(50, 30), IVec2::new(56, 36)),
+        element(ElementKind::Stone),
+    );
+    sandbox.detach_body(WorldCell::new(50, 30));
+    sandbox.explode(Vec2::new(48.0, 39.0), 8.0, 150.0);
+    sandbox
+}
+
+#[test]
+fn a_rewound_sandbox_runs_on_exactly_like_before() {
+    let mut sandbox = busy_sandbox();
+    let mut rewind = Rewind::new(10, 10);
+    for _ in 0..10 {
+        sandbox.tick();
+        rewind.record(&sandbox);
+    }
+    for _ in 0..40 {
+        sandbox.tick();
+    }
+    let expected = (world(&sandbox), sandbox.particles.clone(), sandbox.wframe);
+
+    // Scribbling over the world in between doesn't matter
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 0), IVec2::new(64, 20)),
+        element(ElementKind::Oil),
+    );
+    rewind.restore(&mut sandbox, 0);
+    assert_eq!(sandbox.wframe, 10);
+    for _ in 0..40 {
+        sandbox.tick();
+    }
+
+    assert_eq!(
+        (world(&sandbox), sandbox.particles.clone(), sandbox.wframe),
+        expected
+    );
+}
+
+#[test]
+fn rewinding_keeps_the_latest_snapshots_and_drops_the_future() {
+    let mut sandbox = busy_sandbox();
+    let mut rewind = Rewind::new(5, 4);
+    for _ in 0..40 {
+        sandbox.tick();
+        rewind.record(&sandbox);
+    }
+    let ticks = |rewind: &Rewind| {
+        rewind
+            .points()
+            .iter()
+            .map(|point| point.snapshot.wframe)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ticks(&rewind), [25, 30, 35, 40]);
+
+    rewind.restore(&mut sandbox, 1);
+    assert_eq!(sandbox.wframe, 30);
+    assert_eq!(ticks(&rewind), [25, 30, 35, 40]);
+
+    for _ in 0..5 {
+        sandbox.tick();
+        rewind.record(&sandbox);
+    }
+    assert_eq!(ticks(&rewind), [25, 30, 35]);
+}
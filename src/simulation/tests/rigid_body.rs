@@ -0,0 +1,208 @@
+use bevy::math::{IVec2, Vec2};
+
+use crate::{common::Rect, coordinates::WorldCell};
+
+use super::*;
+
+fn detach(scene: &mut Scene, rect: Rect) {
+    scene.fill(rect, ElementKind::Stone);
+    assert!(scene.sandbox.detach_body(WorldCell(rect.min)));
+}
+
+#[test]
+fn detached_block_falls_and_lands_intact() {
+    let mut scene = Scene::with_floor(20);
+    detach(&mut scene, Rect::new(IVec2::new(8, 30), IVec2::new(12, 34)));
+
+    for _ in 0..60 {
+        scene.sandbox.tick();
+    }
+
+    assert_eq!(scene.sandbox.bodies.len(), 1);
+    assert_eq!(scene.sandbox.bodies[0].len(), 16);
+    let landed = Rect::new(IVec2::new(0, 50), IVec2::new(20, 60));
+    assert_eq!(count(&scene.sandbox, landed, ElementKind::Stone), 16);
+    assert_eq!(
+        count(
+            &scene.sandbox,
+            Rect::new(IVec2::new(0, 30), IVec2::new(20, 50)),
+            ElementKind::Stone
+        ),
+        0
+    );
+}
+
+#[test]
+fn falling_body_displaces_water_without_losing_any() {
+    let mut scene = Scene::with_floor(20);
+    scene.fill(
+        Rect::new(IVec2::new(0, 52), IVec2::new(20, 60)),
+        ElementKind::Water,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(-1, 40), IVec2::new(0, 60)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(20, 40), IVec2::new(21, 60)),
+        ElementKind::Stone,
+    );
+    detach(&mut scene, Rect::new(IVec2::new(6, 30), IVec2::new(14, 34)));
+
+    let before = ElementCensus::take(&scene.sandbox);
+    for _ in 0..100 {
+        scene.sandbox.tick();
+    }
+
+    let discrepancies = before.compare(&ElementCensus::take(&scene.sandbox));
+    assert!(discrepancies.is_empty(), "{discrepancies:?}");
+    let body = &scene.sandbox.bodies[0];
+    assert!(
+        body.position.y > 52.0,
+        "the body floats at {}",
+        body.position
+    );
+}
+
+#[test]
+fn resting_body_lets_its_chunks_sleep() {
+    let mut scene = Scene::with_floor(20);
+    detach(&mut scene, Rect::new(IVec2::new(8, 50), IVec2::new(12, 54)));
+
+    for _ in 0..100 {
+        scene.sandbox.tick();
+    }
+
+    let chunk = WorldCell::new(8, 59).chunk(scene.sandbox.chunk_size());
+    assert!(scene
+        .sandbox
+        .get_chunk(chunk)
+        .unwrap()
+        .dirty_rect()
+        .is_empty());
+}
+
+#[test]
+fn breaking_the_middle_of_a_plank_splits_it() {
+    let mut scene = Scene::with_floor(20);
+    detach(&mut scene, Rect::new(IVec2::new(2, 54), IVec2::new(18, 58)));
+
+    scene.sandbox.break_bodies(Vec2::new(10.0, 56.0), 2.0);
+
+    assert_eq!(scene.sandbox.bodies.len(), 2);
+    let cells = scene
+        .sandbox
+        .bodies
+        .iter()
+        .map(RigidBody::len)
+        .sum::<usize>();
+    assert!(cells < 64);
+    let stone = Rect::new(IVec2::new(0, 50), IVec2::new(20, 60));
+    assert_eq!(count(&scene.sandbox, stone, ElementKind::Stone), 64);
+}
+
+#[test]
+fn erasing_the_only_link_splits_the_body() {
+    let mut scene = Scene::with_floor(20);
+    scene.fill(
+        Rect::new(IVec2::new(2, 54), IVec2::new(6, 58)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(10, 54), IVec2::new(14, 58)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(6, 54), IVec2::new(10, 55)),
+        ElementKind::Stone,
+    );
+    assert!(scene.sandbox.detach_body(WorldCell::new(2, 54)));
+    assert_eq!(scene.sandbox.bodies[0].len(), 36);
+
+    scene.sandbox.fill_rect(
+        Rect::new(IVec2::new(8, 54), IVec2::new(9, 55)),
+        Element::default(),
+    );
+    scene.sandbox.tick();
+
+    let mut sizes = scene
+        .sandbox
+        .bodies
+        .iter()
+        .map(RigidBody::len)
+        .collect::<Vec<_>>();
+    sizes.sort();
+    assert_eq!(sizes, vec![17, 18]);
+}
+
+#[test]
+fn outline_is_closed() {
+    let mut scene = Scene::with_floor(20);
+    scene.fill(
+        Rect::new(IVec2::new(2, 50), IVec2::new(8, 53)),
+        ElementKind::Stone,
+    );
+    scene.fill(
+        Rect::new(IVec2::new(4, 53), IVec2::new(6, 58)),
+        ElementKind::Stone,
+    );
+    assert!(scene.sandbox.detach_body(WorldCell::new(2, 50)));
+
+    let mut vertices = std::collections::HashMap::<IVec2, usize>::new();
+    for (start, end) in scene.sandbox.bodies[0].outline() {
+        for point in [start, end] {
+            *vertices
+                .entry((point * 4.0).round().as_ivec2())
+                .or_default() += 1;
+        }
+    }
+    assert!(vertices.values().all(|uses| *uses == 2), "{vertices:?}");
+}
+
+#[test]
+fn overhanging_plank_tips_over() {
+    let mut scene = Scene::with_floor(20);
+    // Mostly hanging over the edge of the pillar, which is built afterwards so it isn't part of
+    // the body
+    detach(&mut scene, Rect::new(IVec2::new(1, 48), IVec2::new(15, 50)));
+    scene.fill(
+        Rect::new(IVec2::new(0, 50), IVec2::new(4, 60)),
+        ElementKind::Stone,
+    );
+
+    for _ in 0..60 {
+        scene.sandbox.tick();
+    }
+
+    // It ends up leaning against the pillar
+    let body = &scene.sandbox.bodies[0];
+    assert!(body.rotation > 0.5, "rotation {}", body.rotation);
+    assert_eq!(body.velocity, Vec2::ZERO);
+    assert_eq!(body.len(), 28);
+}
+
+#[test]
+fn only_solids_are_detached() {
+    let mut scene = Scene::with_floor(20);
+    scene.fill(
+        Rect::new(IVec2::new(2, 40), IVec2::new(6, 44)),
+        ElementKind::Sand,
+    );
+    scene.place(WorldCell::new(10, 40), ElementKind::Stone);
+
+    assert!(!scene.sandbox.detach_body(WorldCell::new(2, 40)));
+    assert!(!scene.sandbox.detach_body(WorldCell::new(10, 40)));
+    assert!(scene.sandbox.bodies.is_empty());
+}
+
+#[test]
+fn groups_that_are_too_big_stay_in_place() {
+    let mut scene = Scene::with_floor(20);
+    scene.fill(
+        Rect::new(IVec2::new(0, -10), IVec2::new(65, 55)),
+        ElementKind::Stone,
+    );
+
+    assert!(!scene.sandbox.detach_body(WorldCell::new(0, 0)));
+    assert!(scene.sandbox.bodies.is_empty());
+}
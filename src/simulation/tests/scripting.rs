@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use bevy::math::IVec2;
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+const PEBBLE: &str = r#"
+const NAME = "Pebble";
+const COLOR = [90, 90, 90];
+const DENSITY = 120;
+const CATEGORY = "powder";
+
+fn tick(api) {
+    if api.can_move_to(0, 1) {
+        api.accelerate(0.0, 0.5);
+    }
+    api.move_element();
+}
+"#;
+
+const MOLD: &str = r#"
+const NAME = "Mold";
+const COLOR = [60, 140, 40];
+const DENSITY = 255;
+
+fn tick(api) {
+    if api.get_element(0, 1) == "Stone" {
+        api.set_element(0, 1, "Mold");
+    }
+}
+"#;
+
+const SEEDER: &str = r#"
+const NAME = "Seeder";
+const COLOR = [120, 200, 80];
+const DENSITY = 255;
+
+fn tick(api) {
+    api.set_element(0, 1, "Sand");
+}
+"#;
+
+fn scripted_sandbox(sources: &[&str]) -> (Sandbox, Vec<ElementKind>) {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let mut elements = ElementRegistry::default();
+    let kinds = sources
+        .iter()
+        .map(|source| elements.load_script_source(source).unwrap())
+        .collect();
+    sandbox.elements = Arc::new(elements);
+    (sandbox, kinds)
+}
+
+#[test]
+fn scripted_element_falls_onto_the_floor() {
+    let (mut sandbox, kinds) = scripted_sandbox(&[PEBBLE]);
+    let pebble = sandbox.elements.element(kinds[0]);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 60), IVec2::new(10, 61)),
+        element(ElementKind::Stone),
+    );
+    sandbox.set_element(WorldCell::new(5, 40), pebble);
+
+    for _ in 0..40 {
+        sandbox.tick();
+    }
+
+    assert_eq!(
+        sandbox.get_element(WorldCell::new(5, 59)).map(|e| e.kind),
+        Some(kinds[0])
+    );
+    assert_eq!(
+        sandbox.get_element(WorldCell::new(5, 40)).map(|e| e.kind),
+        Some(ElementKind::Air)
+    );
+}
+
+#[test]
+fn scripts_can_set_elements_by_name() {
+    let (mut sandbox, kinds) = scripted_sandbox(&[MOLD]);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(3, 11), IVec2::new(4, 20)),
+        element(ElementKind::Stone),
+    );
+    sandbox.set_element(WorldCell::new(3, 10), sandbox.elements.element(kinds[0]));
+
+    for _ in 0..20 {
+        sandbox.tick();
+    }
+
+    let molds = sandbox
+        .iter_region(Rect::new(IVec2::new(3, 10), IVec2::new(4, 20)))
+        .filter(|(_, element)| element.kind == kinds[0])
+        .count();
+    assert_eq!(molds, 10);
+}
+
+#[test]
+fn scripts_can_set_elements_in_unloaded_chunks() {
+    let (mut sandbox, kinds) = scripted_sandbox(&[SEEDER]);
+    sandbox.check_conservation = false;
+    // The bottom row of the loaded world
+    sandbox.set_element(WorldCell::new(5, 63), sandbox.elements.element(kinds[0]));
+    assert!(sandbox.get_chunk(ChunkPos::new(0, -1)).is_none());
+
+    sandbox.tick();
+    sandbox.tick();
+
+    assert!(sandbox.get_chunk(ChunkPos::new(0, -1)).is_some());
+    assert_eq!(
+        sandbox.get_element(WorldCell::new(5, 64)).map(|e| e.kind),
+        Some(ElementKind::Sand)
+    );
+}
+
+#[test]
+fn reloading_a_script_keeps_its_kind() {
+    let mut elements = ElementRegistry::default();
+    let pebble = elements.load_script_source(PEBBLE).unwrap();
+    let mold = elements.load_script_source(MOLD).unwrap();
+
+    let reloaded = elements
+        .load_script_source(&PEBBLE.replace("[90, 90, 90]", "[1, 2, 3]"))
+        .unwrap();
+
+    assert_eq!(reloaded, pebble);
+    assert_ne!(mold, pebble);
+    assert_eq!(elements.base_color(pebble), (1, 2, 3));
+    assert_eq!(elements.kind_by_name("Mold"), Some(mold));
+    assert_eq!(elements.kinds().count(), ElementKind::ALL.len() + 2);
+}
+
+#[test]
+fn invalid_scripts_are_rejected() {
+    let mut elements = ElementRegistry::default();
+    for source in [
+        "fn tick(api) {",
+        "const COLOR = [0, 0, 0]; const DENSITY = 1; fn tick(api) {}",
+        r#"const NAME = "Sand"; const COLOR = [0, 0, 0]; const DENSITY = 1; fn tick(api) {}"#,
+        r#"const NAME = "A"; const COLOR = [0, 0]; const DENSITY = 1; fn tick(api) {}"#,
+        r#"const NAME = "A"; const COLOR = [0, 0, 0]; const DENSITY = 256; fn tick(api) {}"#,
+        r#"const NAME = "A"; const COLOR = [0, 0, 0]; const DENSITY = 1;"#,
+    ] {
+        assert!(elements.load_script_source(source).is_err(), "{source}");
+    }
+    assert_eq!(elements.kinds().count(), ElementKind::ALL.len());
+}
+
+#[test]
+fn failing_scripts_leave_the_world_alone() {
+    let (mut sandbox, kinds) = scripted_sandbox(&[r#"
+        const NAME = "Broken";
+        const COLOR = [0, 0, 0];
+        const DENSITY = 1;
+
+        fn tick(api) {
+            api.set_element(100, 0, "Sand");
+        }
+    "#]);
+    sandbox.set_element(WorldCell::new(5, 5), sandbox.elements.element(kinds[0]));
+
+    for _ in 0..5 {
+        sandbox.tick();
+    }
+
+    assert_eq!(
+        sandbox.get_element(WorldCell::new(5, 5)).map(|e| e.kind),
+        Some(kinds[0])
+    );
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use bevy::math::IVec2;
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+/// Keeps pushing to the right, whatever is in the way.
+struct Pushing;
+
+impl ElementBehavior for Pushing {
+    fn tick(&self, api: &mut LocalApi) {
+        api.accelerate(1.0, 0.0);
+        api.move_element();
+    }
+}
+
+fn awake(sandbox: &Sandbox) -> bool {
+    sandbox.get_chunk(ChunkPos::new(0, 0)).unwrap().active()
+}
+
+/// A pushing element at (10, 10) with a stone wall to its right, run until it could have
+/// come to rest. Bouncing off the wall, it only runs into it every other tick.
+fn pushing_scene() -> (Scene, ElementKind) {
+    let mut elements = ElementRegistry::default();
+    let pushing = elements.register_with_behavior(
+        CustomElement {
+            name: "Pushing".to_string(),
+            category: ElementCategory::Special,
+            color: (255, 255, 255),
+            density: 100,
+            hardness: 10,
+        },
+        Pushing,
+    );
+
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 0), IVec2::new(20, 20)));
+    scene.sandbox.elements = Arc::new(elements);
+    scene.place(WorldCell::new(10, 10), pushing);
+    scene.place(WorldCell::new(11, 10), ElementKind::Stone);
+    for _ in 0..(REST_TICKS as usize * 2 + 4) {
+        scene.sandbox.tick();
+    }
+    (scene, pushing)
+}
+
+#[test]
+fn elements_pushing_against_a_wall_come_to_rest() {
+    let (scene, _) = pushing_scene();
+
+    let element = scene.sandbox.get_element(WorldCell::new(10, 10)).unwrap();
+    assert!(element.is_resting());
+    assert!(!awake(&scene.sandbox));
+}
+
+#[test]
+fn resting_elements_move_once_the_way_is_clear() {
+    let (mut scene, pushing) = pushing_scene();
+    assert!(!awake(&scene.sandbox));
+
+    scene.place(WorldCell::new(11, 10), ElementKind::Air);
+    for _ in 0..4 {
+        scene.sandbox.tick();
+    }
+
+    let element = scene.sandbox.get_element(WorldCell::new(10, 10)).unwrap();
+    assert_eq!(element.kind, ElementKind::Air);
+    assert!(scene
+        .sandbox
+        .iter_region(Rect::new(IVec2::new(12, 10), IVec2::new(20, 11)))
+        .any(|(_, element)| element.kind == pushing && !element.is_resting()));
+}
+
+#[test]
+fn tracked_wakers_are_the_moving_cells() {
+    let mut scene = Scene::new(Rect::new(IVec2::new(0, 0), IVec2::new(20, 20)));
+    scene.fill(
+        Rect::new(IVec2::new(0, 20), IVec2::new(20, 21)),
+        ElementKind::Stone,
+    );
+    for _ in 0..4 {
+        scene.sandbox.tick();
+    }
+    scene.sandbox.track_wakers(true);
+    scene.place(WorldCell::new(5, 5), ElementKind::Sand);
+
+    scene.sandbox.tick();
+    let wakers = scene
+        .sandbox
+        .get_chunk(ChunkPos::new(0, 0))
+        .unwrap()
+        .wakers
+        .clone();
+    assert!(!wakers.is_empty());
+    assert!(wakers.iter().all(|cell| cell.0.x == 5));
+
+    for _ in 0..60 {
+        scene.sandbox.tick();
+    }
+    assert!(!awake(&scene.sandbox));
+    assert!(scene
+        .sandbox
+        .get_chunk(ChunkPos::new(0, 0))
+        .unwrap()
+        .wakers
+        .is_empty());
+}
@@ -0,0 +1,235 @@
+use bevy::math::{IVec2, Vec2};
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, ChunkSize, WorldCell},
+};
+
+use super::*;
+
+/// A 20×20 arena at the origin, walled in by water so that sand would sink into it if the
+/// bounds didn't hold.
+fn arena() -> Sandbox {
+    Sandbox::with_settings(
+        SEED,
+        WorldSettings {
+            bounds: WorldBounds::Finite {
+                area: Rect::new(IVec2::ZERO, IVec2::splat(20)),
+                boundary: ElementKind::Water,
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// The element at `position`, even outside the bounds.
+fn boundary_at(sandbox: &Sandbox, position: WorldCell) -> ElementKind {
+    let size = sandbox.chunk_size();
+    sandbox
+        .get_chunk(position.chunk(size))
+        .unwrap()
+        .kind(position.local(size))
+}
+
+#[test]
+fn nothing_leaves_a_finite_world() {
+    let mut sandbox = arena();
+    sandbox.set_element(WorldCell::new(10, 0), element(ElementKind::Sand));
+    sandbox.set_element(WorldCell::new(19, 5), element(ElementKind::Water));
+    for _ in 0..120 {
+        sandbox.tick();
+    }
+
+    let sand = sandbox.get_element(WorldCell::new(10, 19)).unwrap();
+    assert_eq!(sand.kind, ElementKind::Sand);
+    assert_eq!(
+        boundary_at(&sandbox, WorldCell::new(10, 20)),
+        ElementKind::Water
+    );
+    assert_eq!(
+        boundary_at(&sandbox, WorldCell::new(20, 19)),
+        ElementKind::Water
+    );
+    assert!(sandbox
+        .iter_region(Rect::new(IVec2::ZERO, IVec2::splat(20)))
+        .any(|(_, element)| element.kind == ElementKind::Water));
+}
+
+#[test]
+fn the_boundary_cant_be_changed() {
+    let mut sandbox = arena();
+    assert_eq!(sandbox.get_element(WorldCell::new(-1, 5)), None);
+
+    sandbox.set_element(WorldCell::new(-1, 5), element(ElementKind::Stone));
+    sandbox.explode(Vec2::new(0.0, 5.0), 6.0, 200.0);
+    for _ in 0..10 {
+        sandbox.tick();
+    }
+
+    for y in 0..12 {
+        for x in -6..0 {
+            let position = WorldCell::new(x, y);
+            assert_eq!(boundary_at(&sandbox, position), ElementKind::Water);
+        }
+    }
+}
+
+#[test]
+fn sand_and_water_settle_with_smaller_chunks() {
+    let small = WorldSettings {
+        chunk_size: ChunkSize(MAX_REACH + 1),
+        ..Default::default()
+    };
+    let mut sandboxes = [
+        Sandbox::with_seed(SEED),
+        Sandbox::with_settings(SEED, small),
+    ];
+    // A basin 100 cells wide, across chunk borders at 33 and 66, and water right next to them
+    for sandbox in &mut sandboxes {
+        let stone = element(ElementKind::Stone);
+        sandbox.fill_rect(Rect::new(IVec2::new(-1, 40), IVec2::new(101, 41)), stone);
+        sandbox.fill_rect(Rect::new(IVec2::new(-1, 30), IVec2::new(0, 40)), stone);
+        sandbox.fill_rect(Rect::new(IVec2::new(100, 30), IVec2::new(101, 40)), stone);
+        sandbox.fill_rect(
+            Rect::new(IVec2::new(14, 0), IVec2::new(18, 10)),
+            element(ElementKind::Sand),
+        );
+        sandbox.fill_rect(
+            Rect::new(IVec2::new(60, 20), IVec2::new(70, 30)),
+            element(ElementKind::Water),
+        );
+    }
+
+    for sandbox in &mut sandboxes {
+        for _ in 0..600 {
+            sandbox.tick();
+        }
+    }
+
+    assert_eq!(sandboxes[1].chunk_size(), ChunkSize(33));
+    for sandbox in &sandboxes {
+        let cells = |kind| {
+            sandbox
+                .iter_region(Rect::new(IVec2::ZERO, IVec2::new(100, 40)))
+                .filter(|(_, element)| element.kind == kind)
+                .map(|(position, _)| position.0.y)
+                .collect::<Vec<_>>()
+        };
+        let sand = cells(ElementKind::Sand);
+        assert_eq!(sand.len(), 40);
+        assert!(sand.iter().all(|&y| y >= 30));
+        // Spread out over the whole basin, in two rows at most
+        let water = cells(ElementKind::Water);
+        assert_eq!(water.len(), 100);
+        assert!(water.iter().all(|&y| y >= 38), "{water:?}");
+    }
+}
+
+#[test]
+fn chunks_liquids_can_see_past_are_rejected() {
+    let settings = WorldSettings {
+        chunk_size: ChunkSize(16),
+        ..Default::default()
+    };
+    assert_eq!(
+        settings.validate(),
+        Err("chunks are at least 33 cells wide, not 16".to_string())
+    );
+    assert!(Sandbox::try_with_settings(SEED, settings).is_err());
+}
+
+#[test]
+fn wrapping_worlds_narrower_than_a_neighbourhood_are_rejected() {
+    let settings = WorldSettings {
+        bounds: WorldBounds::Wrapping { width: 2 },
+        ..Default::default()
+    };
+    assert_eq!(
+        settings.validate(),
+        Err("a wrapping world is at least 3 chunks wide, not 2".to_string())
+    );
+}
+
+#[test]
+fn saves_with_chunks_outside_the_bounds_are_rejected() {
+    let unbounded = Sandbox::with_seed(SEED).save();
+    for mut sandbox in [arena(), ring()] {
+        let before = sandbox.save();
+        assert!(matches!(
+            sandbox.load(&unbounded),
+            Err(SaveError::Invalid(_))
+        ));
+        assert_eq!(sandbox.save(), before);
+        sandbox.load(&before).unwrap();
+    }
+}
+
+/// A world 3 chunks (192 cells) wide, with a floor all the way round.
+fn ring() -> Sandbox {
+    let mut sandbox = Sandbox::with_settings(
+        SEED,
+        WorldSettings {
+            bounds: WorldBounds::Wrapping { width: 3 },
+            ..Default::default()
+        },
+    );
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 40), IVec2::new(192, 41)),
+        element(ElementKind::Stone),
+    );
+    sandbox
+}
+
+#[test]
+fn a_wrapping_world_has_no_sides() {
+    let mut sandbox = ring();
+    assert_eq!(
+        sandbox.get_chunk(ChunkPos::new(-1, 0)).unwrap().position,
+        ChunkPos::new(2, 0)
+    );
+
+    sandbox.set_element(WorldCell::new(-1, 10), element(ElementKind::Sand));
+    let sand = sandbox.get_element(WorldCell::new(191, 10)).unwrap();
+    assert_eq!(sand.kind, ElementKind::Sand);
+    assert_eq!(sandbox.get_element(WorldCell::new(383, 10)), Some(sand));
+    assert!(sandbox.get_chunk(ChunkPos::new(3, -1)).is_none());
+    assert_eq!(sandbox.settings().closest_x(190.5, 2.0), -1.5);
+}
+
+#[test]
+fn sand_moving_off_the_right_comes_back_on_the_left() {
+    let mut sandbox = ring();
+    sandbox.set_element(
+        WorldCell::new(189, 10),
+        Element {
+            velocity: Vec2::new(9.0, 0.0),
+            ..element(ElementKind::Sand)
+        },
+    );
+    for _ in 0..60 {
+        sandbox.tick();
+    }
+
+    let landed = sandbox
+        .iter_region(Rect::new(IVec2::new(0, 0), IVec2::new(192, 40)))
+        .filter(|(_, element)| element.kind == ElementKind::Sand)
+        .map(|(position, _)| position)
+        .collect::<Vec<_>>();
+    assert_eq!(landed.len(), 1);
+    assert_eq!(landed[0].0.y, 39);
+    assert!(landed[0].0.x < 96, "{}", landed[0]);
+}
+
+#[test]
+fn particles_fly_around_a_wrapping_world() {
+    let mut sandbox = ring();
+    sandbox.set_element(WorldCell::new(180, 30), element(ElementKind::Sand));
+    assert!(sandbox.launch(WorldCell::new(180, 30), Vec2::new(20.0, -2.0)));
+    for _ in 0..3 {
+        sandbox.tick();
+    }
+
+    let particle = sandbox.particles[0];
+    assert!((0.0..192.0).contains(&particle.position.x), "{particle:?}");
+    assert!(particle.position.x < 60.0, "{particle:?}");
+}
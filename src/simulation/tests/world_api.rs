@@ -0,0 +1,187 @@
+use bevy::math::IVec2;
+
+use crate::{
+    common::Rect,
+    coordinates::{ChunkPos, WorldCell},
+};
+
+use super::*;
+
+/// Clears the dirty rects of every chunk so tests can observe what gets woken up.
+fn settle(sandbox: &Sandbox) {
+    for chunk in sandbox.chunks.values() {
+        let mut chunk = chunk.write();
+        chunk.current_dirty_rect.clear();
+        chunk.next_dirty_rect.clear();
+    }
+}
+
+#[test]
+fn get_and_set_round_trip_with_negative_positions() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    for position in [
+        WorldCell::new(-1, -1),
+        WorldCell::new(-64, -65),
+        WorldCell::new(-63, 0),
+        WorldCell::new(0, -128),
+        WorldCell::new(127, 63),
+    ] {
+        sandbox.set_element(position, element(ElementKind::Water));
+        assert_eq!(
+            sandbox.get_element(position).map(|element| element.kind),
+            Some(ElementKind::Water),
+            "{position}"
+        );
+    }
+
+    assert_eq!(
+        sandbox
+            .get_element(WorldCell::new(-2, -1))
+            .map(|element| element.kind),
+        Some(ElementKind::Air)
+    );
+}
+
+#[test]
+fn get_element_is_none_outside_loaded_chunks() {
+    let sandbox = Sandbox::with_seed(SEED);
+    assert_eq!(sandbox.get_element(WorldCell::new(0, 64)), None);
+    assert_eq!(sandbox.get_element(WorldCell::new(-65, 0)), None);
+}
+
+#[test]
+fn set_element_creates_missing_chunks() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fresh_chunks.clear();
+
+    sandbox.set_element(WorldCell::new(-100, 200), element(ElementKind::Sand));
+
+    assert!(sandbox.chunks.contains_key(&ChunkPos::new(-2, -3)));
+    assert_eq!(sandbox.fresh_chunks, vec![ChunkPos::new(-2, -3)]);
+    assert_eq!(
+        sandbox
+            .get_element(WorldCell::new(-100, 200))
+            .map(|element| element.kind),
+        Some(ElementKind::Sand)
+    );
+}
+
+#[test]
+fn set_element_on_an_edge_wakes_up_the_neighbours() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    settle(&sandbox);
+
+    // Top-left corner of the origin chunk
+    sandbox.set_element(WorldCell::new(0, 0), element(ElementKind::Sand));
+
+    let is_dirty = |position: ChunkPos| {
+        !sandbox
+            .get_chunk(position)
+            .unwrap()
+            .next_dirty_rect
+            .is_empty()
+    };
+
+    assert!(is_dirty(ChunkPos::new(0, 0)));
+    assert!(is_dirty(ChunkPos::new(-1, 0)));
+    assert!(is_dirty(ChunkPos::new(0, 1)));
+    assert!(is_dirty(ChunkPos::new(-1, 1)));
+    assert!(!is_dirty(ChunkPos::new(1, 0)));
+    assert!(!is_dirty(ChunkPos::new(1, 1)));
+    assert!(!is_dirty(ChunkPos::new(0, 2)));
+
+    let above = sandbox.get_chunk(ChunkPos::new(0, 1)).unwrap();
+    assert!(above.next_dirty_rect.max.y >= 63);
+}
+
+#[test]
+fn fill_rect_and_iter_region_span_chunks() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    let rect = Rect::new(IVec2::new(-3, -2), IVec2::new(3, 2));
+    sandbox.fill_rect(rect, element(ElementKind::Stone));
+
+    let region = sandbox
+        .iter_region(Rect::new(IVec2::new(-4, -3), IVec2::new(4, 3)))
+        .collect::<Vec<_>>();
+    assert_eq!(region.len(), 8 * 6);
+    assert_eq!(region[0].0, WorldCell::new(-4, -3));
+
+    let stone = region
+        .iter()
+        .filter(|(_, element)| element.kind == ElementKind::Stone)
+        .map(|(position, _)| *position)
+        .collect::<Vec<_>>();
+    assert_eq!(stone.len(), 6 * 4);
+    assert!(stone.iter().all(|position| rect.contains(position.0)));
+
+    // Only the loaded part of a region is yielded
+    let partial = sandbox.iter_region(Rect::new(IVec2::new(0, 60), IVec2::new(1, 70)));
+    assert_eq!(partial.count(), 4);
+}
+
+#[test]
+fn replace_where_replaces_every_match() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(60, -2), IVec2::new(68, 2)),
+        element(ElementKind::Water),
+    );
+    sandbox.set_element(WorldCell::new(0, 0), element(ElementKind::Sand));
+
+    let replaced = sandbox.replace_where(
+        |element| element.kind == ElementKind::Water,
+        element(ElementKind::Stone),
+    );
+    assert_eq!(replaced, 32);
+
+    let census = ElementCensus::take(&sandbox).totals();
+    assert_eq!(census.get(&ElementKind::Water), None);
+    assert_eq!(census.get(&ElementKind::Stone), Some(&32));
+    assert_eq!(census.get(&ElementKind::Sand), Some(&1));
+}
+
+#[test]
+fn elements_placed_after_256_ticks_are_updated() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    for _ in 0..254 {
+        sandbox.tick();
+    }
+
+    // Placed elements were never updated, which an 8-bit tick counter mistook for tick 256.
+    // The chunk is woken by the first tick and updated by the second.
+    sandbox.set_element(WorldCell::new(5, 5), element(ElementKind::Sand));
+    sandbox.tick();
+    sandbox.tick();
+
+    let sand = sandbox.get_element(WorldCell::new(5, 5)).unwrap();
+    assert_eq!(sandbox.wframe, 256);
+    assert_eq!(sand.wframe, sandbox.wframe);
+    assert!(sand.velocity.y > 0.0);
+}
+
+#[test]
+fn elements_idle_for_256_ticks_are_updated() {
+    let mut sandbox = Sandbox::with_seed(SEED);
+    sandbox.fill_rect(
+        Rect::new(IVec2::new(0, 10), IVec2::new(10, 11)),
+        element(ElementKind::Stone),
+    );
+    sandbox.set_element(WorldCell::new(5, 9), element(ElementKind::Sand));
+    for _ in 0..10 {
+        sandbox.tick();
+    }
+    let updated = sandbox.get_element(WorldCell::new(5, 9)).unwrap().wframe;
+    assert_ne!(updated, 0);
+
+    // Update the sand again exactly 256 ticks after it was last updated
+    while sandbox.wframe < updated + 254 {
+        sandbox.tick();
+    }
+    sandbox.set_element(WorldCell::new(5, 10), element(ElementKind::Air));
+    sandbox.tick();
+    sandbox.tick();
+
+    let sand = sandbox.get_element(WorldCell::new(5, 9)).unwrap();
+    assert_eq!(sand.wframe, updated + 256);
+    assert!(sand.velocity.y > 0.0);
+}
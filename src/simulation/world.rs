@@ -0,0 +1,202 @@
+//! How a world is laid out: the size of its chunks and how far it reaches.
+//!
+//! Chosen once, when the [`Sandbox`] is created. Apps pick their own by inserting a
+//! [`WorldSettings`] resource before adding the
+//! [`SimulationPlugin`](super::plugin::SimulationPlugin).
+
+use bevy::{
+    ecs::resource::Resource,
+    math::{IVec2, Vec2},
+};
+
+use crate::{
+    common::Rect,
+    constants::DEFAULT_RESOLUTION,
+    coordinates::{ChunkPos, ChunkSize, LocalCell, WorldCell},
+};
+
+use super::*;
+
+/// The cells an unbounded world starts with, whatever the size of its chunks.
+const STARTING_AREA: Rect = Rect {
+    min: IVec2::new(-64, -128),
+    max: IVec2::new(128, 64),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct WorldSettings {
+    /// More than [`MAX_REACH`] cells wide.
+    pub chunk_size: ChunkSize,
+    /// How many pixels wide a cell is on screen, before zooming.
+    pub resolution: f32,
+    pub bounds: WorldBounds,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            chunk_size: ChunkSize::default(),
+            resolution: DEFAULT_RESOLUTION,
+            bounds: WorldBounds::Unbounded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum WorldBounds {
+    /// The world grows wherever elements are placed.
+    #[default]
+    Unbounded,
+    /// A fixed arena: only the cells inside `area` (world positions, `max` exclusive) exist.
+    /// The chunks around it are filled with `boundary`, which nothing moves into, breaks or
+    /// replaces.
+    Finite { area: Rect, boundary: ElementKind },
+    /// Grows up and down, but wraps around horizontally after `width` chunks (at least 3), so
+    /// whatever leaves on the right comes back on the left. The chunks are at x `0..width`.
+    Wrapping { width: i32 },
+}
+
+impl WorldBounds {
+    pub fn contains(&self, cell: WorldCell) -> bool {
+        match self {
+            Self::Unbounded | Self::Wrapping { .. } => true,
+            Self::Finite { area, .. } => area.contains(cell.0),
+        }
+    }
+}
+
+impl WorldSettings {
+    /// Checks that a world can be laid out this way.
+    pub fn validate(&self) -> Result<(), String> {
+        // Otherwise liquids looking for somewhere to flow would see past the neighbouring chunks
+        let size = self.chunk_size.0;
+        if size <= MAX_REACH {
+            return Err(format!(
+                "chunks are at least {} cells wide, not {size}",
+                MAX_REACH + 1
+            ));
+        }
+        // Otherwise a chunk would be its own neighbour, and locked twice by a `LocalApi`
+        if let WorldBounds::Wrapping { width } = self.bounds {
+            if width < 3 {
+                return Err(format!(
+                    "a wrapping world is at least 3 chunks wide, not {width}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The part of the chunk at `position` inside the bounds, in local cells.
+    pub fn local_area(&self, position: ChunkPos) -> Rect {
+        let chunk = self.chunk_size.rect();
+        match self.bounds {
+            WorldBounds::Unbounded | WorldBounds::Wrapping { .. } => chunk,
+            WorldBounds::Finite { area, .. } => {
+                let origin = position.origin(self.chunk_size).0;
+                Rect::new(
+                    (area.min - origin).clamp(chunk.min, chunk.max),
+                    (area.max - origin).clamp(chunk.min, chunk.max),
+                )
+            }
+        }
+    }
+
+    /// The chunks a new world starts with. A finite one has all of its chunks from the start,
+    /// including the ones with only a cell of boundary, so the boundary shows all around.
+    pub fn initial_chunks(&self) -> Vec<ChunkPos> {
+        let (min, max) = match self.bounds {
+            WorldBounds::Unbounded => (STARTING_AREA.min, STARTING_AREA.max),
+            WorldBounds::Wrapping { width } => (
+                IVec2::new(0, STARTING_AREA.min.y),
+                IVec2::new(width * self.chunk_size.0, STARTING_AREA.max.y),
+            ),
+            WorldBounds::Finite { area, .. } => (area.min - IVec2::ONE, area.max + IVec2::ONE),
+        };
+        let top_left = WorldCell(min).chunk(self.chunk_size).0;
+        let bottom_right = WorldCell(max - IVec2::ONE).chunk(self.chunk_size).0;
+
+        // Chunk y grows upwards
+        (top_left.x..=bottom_right.x)
+            .flat_map(|x| (bottom_right.y..=top_left.y).map(move |y| ChunkPos::new(x, y)))
+            .collect()
+    }
+
+    /// Whether a world with these settings can have a chunk at `position`: a finite one only
+    /// has the chunks it starts with, and a wrapping one only those at x `0..width`.
+    pub fn contains_chunk(&self, position: ChunkPos) -> bool {
+        match self.bounds {
+            WorldBounds::Unbounded => true,
+            WorldBounds::Wrapping { width } => (0..width).contains(&position.0.x),
+            WorldBounds::Finite { area, .. } => {
+                let origin = position.origin(self.chunk_size).0;
+                let chunk = Rect::new(origin, origin + self.chunk_size.0);
+                let around = Rect::new(area.min - IVec2::ONE, area.max + IVec2::ONE);
+                chunk.intersection(&around).size().min_element() > 0
+            }
+        }
+    }
+
+    /// How many cells wide a [wrapping](WorldBounds::Wrapping) world is.
+    pub fn width(&self) -> Option<i32> {
+        match self.bounds {
+            WorldBounds::Wrapping { width } => Some(width * self.chunk_size.0),
+            _ => None,
+        }
+    }
+
+    /// Where a cell really is in a wrapping world, any other world leaves it as it is.
+    pub fn wrap(&self, cell: WorldCell) -> WorldCell {
+        match self.width() {
+            Some(width) => WorldCell::new(cell.0.x.rem_euclid(width), cell.0.y),
+            None => cell,
+        }
+    }
+
+    /// Like [`WorldSettings::wrap`], for chunks.
+    pub fn wrap_chunk(&self, position: ChunkPos) -> ChunkPos {
+        match self.bounds {
+            WorldBounds::Wrapping { width } => {
+                ChunkPos::new(position.0.x.rem_euclid(width), position.0.y)
+            }
+            _ => position,
+        }
+    }
+
+    /// Like [`WorldSettings::wrap`], for continuous world coordinates.
+    pub fn wrap_point(&self, point: Vec2) -> Vec2 {
+        match self.width() {
+            Some(width) => Vec2::new(point.x.rem_euclid(width as f32), point.y),
+            None => point,
+        }
+    }
+
+    /// The copy of world x coordinate `x` that is closest to `near`, to draw things next to
+    /// the camera when it looks across the seam of a wrapping world.
+    pub fn closest_x(&self, x: f32, near: f32) -> f32 {
+        match self.width() {
+            Some(width) => {
+                let width = width as f32;
+                x + ((near - x) / width).round() * width
+            }
+            None => x,
+        }
+    }
+
+    /// An empty chunk, with the boundary already in place where it's outside the bounds.
+    pub fn new_chunk(&self, position: ChunkPos, elements: &ElementRegistry) -> Chunk {
+        let mut chunk = Chunk::new(position, self.chunk_size);
+        if let WorldBounds::Finite { boundary, .. } = self.bounds {
+            let inside = self.local_area(position);
+            let boundary = elements.element(boundary);
+            for y in 0..self.chunk_size.0 {
+                for x in 0..self.chunk_size.0 {
+                    if !inside.contains(IVec2::new(x, y)) {
+                        chunk.set_element(LocalCell::new(x, y), boundary);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+}
@@ -0,0 +1,84 @@
+//! The timeline window, to scrub back through the last stretch of the simulation and resume
+//! from any point of it, see [`Rewind`].
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    controls::{Action, ActionState},
+    simulation::{Replay, Rewind, Sandbox},
+};
+
+#[derive(Debug, Default, Resource)]
+pub struct Timeline {
+    pub open: bool,
+    /// The snapshot the world was rewound to, until the simulation resumes from it.
+    pub selected: Option<usize>,
+}
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Timeline>()
+            .add_systems(Update, (toggle_timeline, timeline_ui));
+    }
+}
+
+fn toggle_timeline(mut timeline: ResMut<Timeline>, actions: Res<ActionState>) {
+    if actions.just_pressed(Action::ToggleTimeline) {
+        timeline.open = !timeline.open;
+    }
+}
+
+fn timeline_ui(
+    mut contexts: EguiContexts,
+    mut timeline: ResMut<Timeline>,
+    mut sandbox: ResMut<Sandbox>,
+    rewind: Res<Rewind>,
+    replay: Res<Replay>,
+) {
+    // Resuming some other way, like unpausing, also leaves the rewound point behind
+    if sandbox.active && timeline.selected.is_some() {
+        timeline.selected = None;
+    }
+
+    let Timeline { open, selected } = &mut *timeline;
+    egui::Window::new("Timeline")
+        .open(open)
+        .show(contexts.ctx_mut(), |ui| {
+            // Rewinding would make the replay useless
+            if !matches!(*replay, Replay::Off) {
+                ui.label("Unavailable while a replay records or plays");
+                return;
+            }
+
+            let points = rewind.points();
+            let (Some(first), Some(last)) = (points.front(), points.back()) else {
+                ui.label("Nothing to rewind to yet");
+                return;
+            };
+            ui.label(format!(
+                "Ticks {} to {}",
+                first.snapshot.wframe, last.snapshot.wframe
+            ));
+
+            let mut index = selected.unwrap_or(points.len() - 1);
+            let slider =
+                egui::Slider::new(&mut index, 0..=points.len() - 1).custom_formatter(|index, _| {
+                    format!("tick {}", points[index as usize].snapshot.wframe)
+                });
+            if ui.add(slider).changed() {
+                rewind.restore(&mut sandbox, index);
+                sandbox.active = false;
+                *selected = Some(index);
+            }
+
+            let resume = ui.add_enabled(selected.is_some(), egui::Button::new("Resume from here"));
+            if resume.clicked() {
+                sandbox.active = true;
+                *selected = None;
+            }
+            ui.label("Resuming drops everything after this point");
+        });
+}